@@ -0,0 +1,226 @@
+use crate::block::device::{check_blocks, check_range, BlockDevice, BlockDeviceRef};
+use crate::error::CNFSError::InvalidArgument;
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+struct Buffer
+{
+    dirty: bool,
+    data: Vec<u8>,
+}
+
+/// Buffer cache in front of a block device
+///
+/// Blocks are cached on first access and written back when evicted or flushed, so filesystem
+/// drivers can read and modify small structures in place without going to the device.
+/// The cache is also a [BlockDevice] itself, and it is flushed when dropped.
+///
+/// ```rust
+///  use cnfs::{BlockDevice, BufferCache, MemBlockDevice};
+///  use std::sync::Arc;
+///  let device = Arc::new(MemBlockDevice::new(512, 16).unwrap());
+///  let cache = BufferCache::new(device.clone(), 4).unwrap();
+///  cache.with_block_mut(3, |block| block[..4].copy_from_slice(b"cnss")).unwrap();
+///  assert_eq!(cache.dirty_blocks(), 1);
+///  assert_eq!(&device.to_vec()[1536..1540], [0; 4]);
+///  cache.flush().unwrap();
+///  assert_eq!(&device.to_vec()[1536..1540], b"cnss");
+/// ```
+///
+pub struct BufferCache
+{
+    device: BlockDeviceRef,
+    capacity: usize,
+    buffers: UPCell<BTreeMap<u64, Buffer>>,
+}
+
+impl BufferCache
+{
+    /// New a cache holding at most `capacity` blocks of the device
+    pub fn new(device: BlockDeviceRef, capacity: usize) -> CNFSResult<Self>
+    {
+        if capacity == 0 { return Err(InvalidArgument); }
+        Ok(Self { device, capacity, buffers: unsafe { UPCell::new(BTreeMap::new()) } })
+    }
+
+    /// Returns the cached device
+    pub fn device(&self) -> &BlockDeviceRef
+    {
+        &self.device
+    }
+
+    /// Returns the number of cached blocks
+    pub fn cached_blocks(&self) -> usize
+    {
+        self.buffers.shared_access().len()
+    }
+
+    /// Returns the number of cached blocks not written back yet
+    pub fn dirty_blocks(&self) -> usize
+    {
+        self.buffers.shared_access().values().filter(|b| b.dirty).count()
+    }
+
+    /// Run `f` on a copy of the data of a block
+    ///
+    /// The cache is not borrowed while `f` runs, so it may use the cache again.
+    pub fn with_block<R>(&self, block: u64, f: impl FnOnce(&[u8]) -> R) -> CNFSResult<R>
+    {
+        let data = self.load(&mut self.buffers.exclusive_access(), block, true)?.data.clone();
+        Ok(f(&data))
+    }
+
+    /// Run `f` on a copy of the data of a block, then store it back and mark it dirty
+    ///
+    /// The cache is not borrowed while `f` runs, so it may use the cache again, but changes it
+    /// makes to the same block are overwritten.
+    pub fn with_block_mut<R>(&self, block: u64, f: impl FnOnce(&mut [u8]) -> R) -> CNFSResult<R>
+    {
+        let mut data = self.load(&mut self.buffers.exclusive_access(), block, true)?.data.clone();
+        let ret = f(&mut data);
+        let mut buffers = self.buffers.exclusive_access();
+        let buffer = self.load(&mut buffers, block, false)?;
+        buffer.data = data;
+        buffer.dirty = true;
+        Ok(ret)
+    }
+
+    /// Write the dirty blocks back and flush the device
+    pub fn flush(&self) -> CNFSResult
+    {
+        for (block, buffer) in self.buffers.exclusive_access().iter_mut()
+        {
+            if buffer.dirty
+            {
+                self.device.write_blocks(*block, &buffer.data)?;
+                buffer.dirty = false;
+            }
+        }
+        self.device.flush()
+    }
+
+    /// Write the dirty blocks back and drop the cache
+    pub fn invalidate(&self) -> CNFSResult
+    {
+        self.flush()?;
+        self.buffers.exclusive_access().clear();
+        Ok(())
+    }
+
+    /// Returns the cached block, reading it from the device if `fill` is set
+    fn load<'a>(&self, buffers: &'a mut BTreeMap<u64, Buffer>, block: u64, fill: bool)
+                -> CNFSResult<&'a mut Buffer>
+    {
+        check_blocks(self, block, self.device.block_size())?;
+        if !buffers.contains_key(&block) && buffers.len() >= self.capacity
+        {
+            // evict a clean block if there is one
+            let target = buffers.iter().find(|b| !b.1.dirty)
+                .or(buffers.iter().next()).map(|b| *b.0).unwrap();
+            let buffer = &buffers[&target];
+            if buffer.dirty
+            {
+                self.device.write_blocks(target, &buffer.data)?;
+            }
+            buffers.remove(&target);
+        }
+        match buffers.entry(block) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let mut data = vec![0_u8; self.device.block_size()];
+                if fill { self.device.read_blocks(block, &mut data)?; }
+                Ok(entry.insert(Buffer { dirty: false, data }))
+            }
+        }
+    }
+}
+
+impl BlockDevice for BufferCache
+{
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let mut buffers = self.buffers.exclusive_access();
+        for (i, chunk) in buffer.chunks_mut(self.block_size()).enumerate()
+        {
+            chunk.copy_from_slice(&self.load(&mut buffers, block + i as u64, true)?.data);
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let mut buffers = self.buffers.exclusive_access();
+        for (i, chunk) in buffer.chunks(self.block_size()).enumerate()
+        {
+            // Whole blocks are overwritten, so there is no need to read them
+            let buffer = self.load(&mut buffers, block + i as u64, false)?;
+            buffer.data.copy_from_slice(chunk);
+            buffer.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> CNFSResult {
+        BufferCache::flush(self)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        let block_size = self.block_size();
+        let mut buffers = self.buffers.exclusive_access();
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            let pos = offset + done as u64;
+            let start = (pos % block_size as u64) as usize;
+            let len = min(block_size - start, buffer.len() - done);
+            let data = &self.load(&mut buffers, pos / block_size as u64, true)?.data;
+            buffer[done..done + len].copy_from_slice(&data[start..start + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        let block_size = self.block_size();
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            let pos = offset + done as u64;
+            let start = (pos % block_size as u64) as usize;
+            let len = min(block_size - start, buffer.len() - done);
+            let index = pos / block_size as u64;
+            if len == block_size {
+                self.write_blocks(index, &buffer[done..done + len])?;
+            } else {
+                let mut buffers = self.buffers.exclusive_access();
+                let block = self.load(&mut buffers, index, true)?;
+                block.data[start..start + len].copy_from_slice(&buffer[done..done + len]);
+                block.dirty = true;
+            }
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BufferCache
+{
+    fn drop(&mut self) {
+        let _ = BufferCache::flush(self);
+    }
+}
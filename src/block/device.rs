@@ -0,0 +1,283 @@
+use crate::error::CNFSError::InvalidArgument;
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Trait for block devices
+///
+/// Blocks are numbered from 0, and buffers passed to [read_blocks](BlockDevice::read_blocks)
+/// and [write_blocks](BlockDevice::write_blocks) hold a whole number of blocks.
+pub trait BlockDevice: Send + Sync {
+    /// Returns the size of a block in bytes
+    fn block_size(&self) -> usize;
+
+    /// Returns the number of blocks
+    fn block_count(&self) -> u64;
+
+    /// Read blocks starting at `block` into the buffer
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult;
+
+    /// Write blocks starting at `block` from the buffer
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult;
+
+    /// Write the data buffered by the device back to its storage
+    fn flush(&self) -> CNFSResult
+    {
+        Ok(())
+    }
+
+    /// Read bytes at a byte offset, which needs not to be aligned to blocks
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult
+    {
+        check_range(self, offset, buffer.len())?;
+        let block_size = self.block_size();
+        let mut block = vec![0_u8; block_size];
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            let pos = offset + done as u64;
+            let start = (pos % block_size as u64) as usize;
+            let len = min(block_size - start, buffer.len() - done);
+            self.read_blocks(pos / block_size as u64, &mut block)?;
+            buffer[done..done + len].copy_from_slice(&block[start..start + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Write bytes at a byte offset, reading the partially written blocks first
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> CNFSResult
+    {
+        check_range(self, offset, buffer.len())?;
+        let block_size = self.block_size();
+        let mut block = vec![0_u8; block_size];
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            let pos = offset + done as u64;
+            let start = (pos % block_size as u64) as usize;
+            let len = min(block_size - start, buffer.len() - done);
+            let index = pos / block_size as u64;
+            if len != block_size { self.read_blocks(index, &mut block)?; }
+            block[start..start + len].copy_from_slice(&buffer[done..done + len]);
+            self.write_blocks(index, &block)?;
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+/// Block device reference
+pub type BlockDeviceRef = Arc<dyn BlockDevice>;
+
+/// Fails if `len` bytes at `offset` don't fit in the device
+pub(crate) fn check_range<D: BlockDevice + ?Sized>(device: &D, offset: u64, len: usize) -> CNFSResult
+{
+    let size = device.block_count() * device.block_size() as u64;
+    match offset.checked_add(len as u64) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(InvalidArgument),
+    }
+}
+
+/// Fails if the buffer doesn't hold whole blocks fitting in the device from `block`
+pub(crate) fn check_blocks<D: BlockDevice + ?Sized>(device: &D, block: u64, len: usize) -> CNFSResult
+{
+    if !len.is_multiple_of(device.block_size()) { return Err(InvalidArgument); }
+    let offset = block.checked_mul(device.block_size() as u64).ok_or(InvalidArgument)?;
+    check_range(device, offset, len)
+}
+
+/// Block device in memory
+///
+/// ```rust
+///  use cnfs::{BlockDevice, MemBlockDevice};
+///  let device = MemBlockDevice::new(512, 4).unwrap();
+///  device.write_at(510, b"cnss").unwrap();
+///  let mut block = [0_u8; 512];
+///  device.read_blocks(1, &mut block).unwrap();
+///  assert_eq!(&block[..2], b"ss");
+/// ```
+///
+pub struct MemBlockDevice
+{
+    block_size: usize,
+    data: UPCell<Vec<u8>>,
+}
+
+impl MemBlockDevice
+{
+    /// New a zeroed device of `block_count` blocks
+    pub fn new(block_size: usize, block_count: u64) -> CNFSResult<Self>
+    {
+        let size = usize::try_from(block_count).ok()
+            .and_then(|count| count.checked_mul(block_size)).ok_or(InvalidArgument)?;
+        Self::from_vec(block_size, vec![0_u8; size])
+    }
+
+    /// New a device holding the data, whose length must be a multiple of the block size
+    pub fn from_vec(block_size: usize, data: Vec<u8>) -> CNFSResult<Self>
+    {
+        if block_size == 0 || !data.len().is_multiple_of(block_size) { return Err(InvalidArgument); }
+        Ok(Self { block_size, data: unsafe { UPCell::new(data) } })
+    }
+
+    /// Returns a copy of the device data
+    pub fn to_vec(&self) -> Vec<u8>
+    {
+        self.data.shared_access().clone()
+    }
+}
+
+impl BlockDevice for MemBlockDevice
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.shared_access().len() / self.block_size) as u64
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let offset = block as usize * self.block_size;
+        buffer.copy_from_slice(&self.data.shared_access()[offset..offset + buffer.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let offset = block as usize * self.block_size;
+        self.data.exclusive_access()[offset..offset + buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+/// Block device over a `Read + Write + Seek` storage, like a host file or a [File](crate::File)
+/// inside the VFS, available with the `std` feature
+///
+/// Trailing bytes that don't make up a whole block are not used.
+#[cfg(feature = "std")]
+pub struct FileBlockDevice<T: std::io::Read + std::io::Write + std::io::Seek>
+{
+    block_size: usize,
+    block_count: u64,
+    storage: UPCell<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write + std::io::Seek> FileBlockDevice<T>
+{
+    /// New a device over the storage, with as many blocks as fit in it
+    pub fn new(mut storage: T, block_size: usize) -> CNFSResult<Self>
+    {
+        if block_size == 0 { return Err(InvalidArgument); }
+        let size = storage.seek(std::io::SeekFrom::End(0))?;
+        Ok(Self {
+            block_size,
+            block_count: size / block_size as u64,
+            storage: unsafe { UPCell::new(storage) },
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write + std::io::Seek + Send> BlockDevice for FileBlockDevice<T>
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let mut storage = self.storage.exclusive_access();
+        storage.seek(std::io::SeekFrom::Start(block * self.block_size as u64))?;
+        Ok(storage.read_exact(buffer)?)
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        let mut storage = self.storage.exclusive_access();
+        storage.seek(std::io::SeekFrom::Start(block * self.block_size as u64))?;
+        Ok(storage.write_all(buffer)?)
+    }
+
+    fn flush(&self) -> CNFSResult {
+        Ok(self.storage.exclusive_access().flush()?)
+    }
+}
+
+/// `Read + Write + Seek` storage over a block device, available with the `std` feature
+///
+/// It lets drivers written against a byte stream, like [FatFileSystem](crate::FatFileSystem),
+/// run on a block device. Put a [BufferCache](crate::BufferCache) in front of the device to
+/// avoid reading a whole block for every small access.
+#[cfg(feature = "std")]
+pub struct BlockStorage
+{
+    device: BlockDeviceRef,
+    offset: u64,
+}
+
+#[cfg(feature = "std")]
+impl BlockStorage
+{
+    /// New a storage positioned at the start of the device
+    pub fn new(device: BlockDeviceRef) -> Self
+    {
+        Self { device, offset: 0 }
+    }
+
+    fn size(&self) -> u64
+    {
+        self.device.block_count() * self.device.block_size() as u64
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for BlockStorage
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = min(buf.len() as u64, self.size().saturating_sub(self.offset)) as usize;
+        self.device.read_at(self.offset, &mut buf[..len])?;
+        self.offset += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for BlockStorage
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = min(buf.len() as u64, self.size().saturating_sub(self.offset)) as usize;
+        self.device.write_at(self.offset, &buf[..len])?;
+        self.offset += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.device.flush()?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Seek for BlockStorage
+{
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let offset = match pos {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            std::io::SeekFrom::End(delta) => self.size().checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or(crate::CNFSError::InvalidArgument)?;
+        Ok(self.offset)
+    }
+}
@@ -0,0 +1,13 @@
+mod cache;
+mod device;
+mod partition;
+mod source;
+
+pub use cache::BufferCache;
+pub use device::{BlockDevice, BlockDeviceRef, MemBlockDevice};
+pub(crate) use device::{check_blocks, check_range};
+pub use partition::{read_partitions, Partition, PartitionDevice, PartitionType};
+pub(crate) use partition::crc32_update;
+pub use source::ReadAt;
+#[cfg(feature = "std")]
+pub use device::{BlockStorage, FileBlockDevice};
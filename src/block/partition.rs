@@ -0,0 +1,303 @@
+use crate::block::device::{check_blocks, check_range, BlockDevice, BlockDeviceRef};
+use crate::error::CNFSError::{FSInternal, InvalidArgument, PathNotFound};
+use crate::error::CNFSResult;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Size of a sector addressed by an MBR
+const SECTOR_SIZE: u64 = 512;
+/// Logical block sizes tried when looking for a GPT header
+const GPT_BLOCK_SIZES: [u64; 2] = [512, 4096];
+/// MBR type of the protective partition covering a GPT disk
+const MBR_PROTECTIVE: u8 = 0xEE;
+/// MBR types of extended partitions
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+/// Maximum number of logical partitions followed in an extended partition
+const MAX_LOGICAL: usize = 128;
+/// Maximum size of a GPT partition entry array
+const MAX_GPT_ENTRIES_SIZE: u64 = 1 << 20;
+
+/// Type of a partition, as recorded by its partition table
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PartitionType
+{
+    /// System ID of an MBR partition, like `0x0C` for FAT32 or `0x83` for Linux
+    Mbr(u8),
+    /// Partition type GUID of a GPT partition, in on-disk byte order
+    Gpt([u8; 16]),
+}
+
+/// A partition of a disk
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Partition
+{
+    /// Number of the partition from 1, which is its slot in the table. Logical partitions of
+    /// an MBR extended partition are numbered from 5.
+    pub number: usize,
+    /// Offset in bytes from the start of the disk
+    pub offset: u64,
+    /// Size in bytes
+    pub size: u64,
+    /// Type of the partition
+    pub partition_type: PartitionType,
+    /// Name of a GPT partition, empty for MBR
+    pub name: String,
+}
+
+fn le32(data: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64
+{
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// CRC32 used by GPT, the same as zlib
+fn crc32(data: &[u8]) -> u32
+{
+    crc32_update(0, data)
+}
+
+/// Continue a CRC32 with more data, starting from 0
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32
+{
+    let mut crc = !crc;
+    for &byte in data
+    {
+        crc ^= byte as u32;
+        for _ in 0..8
+        {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn device_size(device: &dyn BlockDevice) -> u64
+{
+    device.block_count() * device.block_size() as u64
+}
+
+/// Read a sector holding an MBR or an EBR, returning None without the boot signature
+fn read_boot_sector(device: &dyn BlockDevice, offset: u64) -> CNFSResult<Option<Vec<u8>>>
+{
+    if offset + SECTOR_SIZE > device_size(device) { return Ok(None); }
+    let mut sector = vec![0_u8; SECTOR_SIZE as usize];
+    device.read_at(offset, &mut sector)?;
+    Ok(if sector[510..512] == [0x55, 0xAA] { Some(sector) } else { None })
+}
+
+/// Returns the type, first sector and sector count of an entry of an MBR or EBR
+fn mbr_entry(sector: &[u8], index: usize) -> (u8, u64, u64)
+{
+    let entry = &sector[446 + index * 16..462 + index * 16];
+    (entry[4], le32(entry, 8) as u64, le32(entry, 12) as u64)
+}
+
+fn mbr_partition(number: usize, system_id: u8, start: u64, count: u64) -> Partition
+{
+    Partition {
+        number,
+        offset: start * SECTOR_SIZE,
+        size: count * SECTOR_SIZE,
+        partition_type: PartitionType::Mbr(system_id),
+        name: String::new(),
+    }
+}
+
+/// Follow the chain of EBRs of an extended partition starting at sector `start`
+fn read_logical(device: &dyn BlockDevice, start: u64, partitions: &mut Vec<Partition>) -> CNFSResult
+{
+    let mut ebr = start;
+    for number in 5..5 + MAX_LOGICAL
+    {
+        let Some(sector) = read_boot_sector(device, ebr * SECTOR_SIZE)? else { break };
+        let (system_id, first, count) = mbr_entry(&sector, 0);
+        if system_id != 0 && count != 0
+        {
+            partitions.push(mbr_partition(number, system_id, ebr + first, count));
+        }
+        // The next EBR is relative to the extended partition
+        let (next_id, next, _) = mbr_entry(&sector, 1);
+        if next_id == 0 || next == 0 { break; }
+        ebr = start + next;
+    }
+    Ok(())
+}
+
+/// Read the GPT header at `lba` and its entries, returning None if either is invalid
+fn read_gpt(device: &dyn BlockDevice, block_size: u64, lba: u64) -> CNFSResult<Option<Vec<Partition>>>
+{
+    let size = device_size(device);
+    if (lba + 1) * block_size > size { return Ok(None); }
+    let mut header = vec![0_u8; block_size as usize];
+    device.read_at(lba * block_size, &mut header)?;
+    let header_size = le32(&header, 12) as usize;
+    if &header[..8] != b"EFI PART" || header_size < 92 || header_size > header.len() { return Ok(None); }
+    let expected = le32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != expected || le64(&header, 24) != lba { return Ok(None); }
+
+    let entries_lba = le64(&header, 72);
+    let count = le32(&header, 80) as u64;
+    let entry_size = le32(&header, 84) as u64;
+    let entries_size = count * entry_size;
+    if entry_size < 128 || !entry_size.is_multiple_of(8) || entries_size > MAX_GPT_ENTRIES_SIZE
+        || entries_lba.checked_mul(block_size).and_then(|o| o.checked_add(entries_size))
+            .is_none_or(|end| end > size)
+    {
+        return Ok(None);
+    }
+    let mut entries = vec![0_u8; entries_size as usize];
+    device.read_at(entries_lba * block_size, &mut entries)?;
+    if crc32(&entries) != le32(&header, 88) { return Ok(None); }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.chunks(entry_size as usize).enumerate()
+    {
+        let type_guid: [u8; 16] = entry[..16].try_into().unwrap();
+        if type_guid == [0; 16] { continue; }
+        let (first, last) = (le64(entry, 32), le64(entry, 40));
+        let offset = first.checked_mul(block_size);
+        let size = last.checked_sub(first).and_then(|n| n.checked_add(1)).and_then(|n| n.checked_mul(block_size));
+        let (Some(offset), Some(size)) = (offset, size) else {
+            return Err(FSInternal("corrupted GPT partition entry".into()));
+        };
+        let name: Vec<u16> = entry[56..128].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0).collect();
+        partitions.push(Partition {
+            number: i + 1,
+            offset,
+            size,
+            partition_type: PartitionType::Gpt(type_guid),
+            name: String::from_utf16_lossy(&name),
+        });
+    }
+    Ok(Some(partitions))
+}
+
+/// Read the partition table of a disk
+///
+/// GPT disks are recognized by their protective MBR, and the backup GPT header at the end of
+/// the disk is used if the primary one is corrupted. For MBR disks, the four primary entries
+/// are followed by the logical partitions of an extended partition, which itself is not
+/// returned. A disk without partition table has no partitions.
+pub fn read_partitions(device: &dyn BlockDevice) -> CNFSResult<Vec<Partition>>
+{
+    let Some(mbr) = read_boot_sector(device, 0)? else { return Ok(Vec::new()) };
+    let entries: Vec<_> = (0..4).map(|i| mbr_entry(&mbr, i)).collect();
+
+    if entries.iter().any(|e| e.0 == MBR_PROTECTIVE)
+    {
+        for block_size in GPT_BLOCK_SIZES
+        {
+            let Some(last) = (device_size(device) / block_size).checked_sub(1) else { continue };
+            for lba in [1, last]
+            {
+                if let Some(partitions) = read_gpt(device, block_size, lba)? { return Ok(partitions); }
+            }
+        }
+        return Err(FSInternal("corrupted GPT header".into()));
+    }
+
+    let mut partitions = Vec::new();
+    for (i, &(system_id, first, count)) in entries.iter().enumerate()
+    {
+        if system_id == 0 || count == 0 { continue; }
+        if !MBR_EXTENDED.contains(&system_id)
+        {
+            partitions.push(mbr_partition(i + 1, system_id, first, count));
+        }
+    }
+    if let Some(&(_, first, _)) = entries.iter().find(|e| MBR_EXTENDED.contains(&e.0))
+    {
+        read_logical(device, first, &mut partitions)?;
+    }
+    Ok(partitions)
+}
+
+/// Block device limited to a range of another device, like a partition of a disk
+///
+/// ```rust
+///  use cnfs::{BlockDevice, MemBlockDevice, PartitionDevice};
+///  use std::sync::Arc;
+///  let disk = Arc::new(MemBlockDevice::new(512, 8).unwrap());
+///  let partition = PartitionDevice::new(disk.clone(), 1024, 2048).unwrap();
+///  partition.write_at(0, b"cnss").unwrap();
+///  assert_eq!(partition.block_count(), 4);
+///  assert_eq!(&disk.to_vec()[1024..1028], b"cnss");
+/// ```
+///
+pub struct PartitionDevice
+{
+    device: BlockDeviceRef,
+    first_block: u64,
+    block_count: u64,
+}
+
+impl PartitionDevice
+{
+    /// New a device over `size` bytes of the device from `offset`, both aligned to its blocks
+    pub fn new(device: BlockDeviceRef, offset: u64, size: u64) -> CNFSResult<Self>
+    {
+        let block_size = device.block_size() as u64;
+        if !offset.is_multiple_of(block_size) || !size.is_multiple_of(block_size) { return Err(InvalidArgument); }
+        match offset.checked_add(size) {
+            Some(end) if end <= device_size(device.as_ref()) => {}
+            _ => return Err(InvalidArgument),
+        }
+        Ok(Self { device, first_block: offset / block_size, block_count: size / block_size })
+    }
+
+    /// Open the partition numbered `number` of the disk, see [read_partitions]
+    pub fn open(device: BlockDeviceRef, number: usize) -> CNFSResult<Self>
+    {
+        let partition = read_partitions(device.as_ref())?.into_iter()
+            .find(|p| p.number == number).ok_or(PathNotFound)?;
+        Self::new(device, partition.offset, partition.size)
+    }
+
+    /// Returns the offset in bytes of the partition on the underlying device
+    pub fn offset(&self) -> u64
+    {
+        self.first_block * self.device.block_size() as u64
+    }
+}
+
+impl BlockDevice for PartitionDevice
+{
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        self.device.read_blocks(self.first_block + block, buffer)
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        self.device.write_blocks(self.first_block + block, buffer)
+    }
+
+    fn flush(&self) -> CNFSResult {
+        self.device.flush()
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        self.device.read_at(self.offset() + offset, buffer)
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        self.device.write_at(self.offset() + offset, buffer)
+    }
+}
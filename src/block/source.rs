@@ -0,0 +1,63 @@
+use crate::block::device::BlockDeviceRef;
+use crate::error::CNFSResult;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Trait for read-only sources of bytes that can be read at any offset, like archives
+///
+/// It is implemented for bytes in memory and for block devices, so an archive can live in a
+/// `&'static [u8]` from `include_bytes!`, a [LoopDevice](crate::LoopDevice) over a file inside
+/// the VFS, or a [FileBlockDevice](crate::FileBlockDevice) with 1-byte blocks over a host file.
+pub trait ReadAt: Send + Sync
+{
+    /// Returns the size in bytes
+    fn size(&self) -> u64;
+
+    /// Read bytes at an offset, returning how many bytes were read, which is less than the
+    /// length of the buffer only at the end of the source
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize>;
+}
+
+fn read_slice(data: &[u8], offset: u64, buffer: &mut [u8]) -> usize
+{
+    if offset >= data.len() as u64 { return 0; }
+    let offset = offset as usize;
+    let len = min(buffer.len(), data.len() - offset);
+    buffer[..len].copy_from_slice(&data[offset..offset + len]);
+    len
+}
+
+impl ReadAt for &'static [u8]
+{
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        Ok(read_slice(self, offset, buffer))
+    }
+}
+
+impl ReadAt for Vec<u8>
+{
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        Ok(read_slice(self, offset, buffer))
+    }
+}
+
+impl ReadAt for BlockDeviceRef
+{
+    fn size(&self) -> u64 {
+        self.block_count() * self.block_size() as u64
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        let len = min(buffer.len() as u64, ReadAt::size(self).saturating_sub(offset)) as usize;
+        crate::block::BlockDevice::read_at(self.as_ref(), offset, &mut buffer[..len])?;
+        Ok(len)
+    }
+}
@@ -1,4 +1,127 @@
+use crate::error::CNFSError::InvalidArgument;
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use lazy_static::lazy_static;
+
 pub const DEFAULT_FILE_BUFFER_SIZE: usize = 4096;
 pub const DCACHE_SIZE: usize = 4096;
 pub const OSINODE_PAGE_SIZE: usize = 1024;
 pub const OSINODE_PAGE_ENTRY_SIZE: usize = 1024;
+pub const PIPE_BUFFER_SIZE: usize = 4096;
+pub const WATCH_QUEUE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Runtime configuration of the virtual file system
+///
+/// ```rust
+///  use cnfs::VfsConfig;
+///  let embedded = VfsConfig::default().page_size(256).page_entry_size(16);
+///  assert_eq!(embedded.page_size, 256);
+/// ```
+///
+pub struct VfsConfig
+{
+    /// Default size of the IO buffer of an opened [File](crate::File)
+    pub file_buffer_size: usize,
+    /// Maximum number of names kept in the dentry cache
+    pub dcache_size: usize,
+    /// Size of a page in the inode page cache
+    pub page_size: usize,
+    /// Maximum number of pages cached for one inode
+    pub page_entry_size: usize,
+    /// Number of bytes a pipe or FIFO holds before writes to it would block
+    pub pipe_buffer_size: usize,
+    /// Number of events a [Watch](crate::Watch) queues before it drops the new ones
+    pub watch_queue_size: usize,
+}
+
+impl VfsConfig
+{
+    /// Set the default file buffer size
+    pub fn file_buffer_size(mut self, size: usize) -> Self
+    {
+        self.file_buffer_size = size;
+        self
+    }
+
+    /// Set the dentry cache size
+    pub fn dcache_size(mut self, size: usize) -> Self
+    {
+        self.dcache_size = size;
+        self
+    }
+
+    /// Set the page size of the inode page cache
+    pub fn page_size(mut self, size: usize) -> Self
+    {
+        self.page_size = size;
+        self
+    }
+
+    /// Set the maximum number of cached pages per inode
+    pub fn page_entry_size(mut self, size: usize) -> Self
+    {
+        self.page_entry_size = size;
+        self
+    }
+
+    /// Set the buffer size of pipes and FIFOs
+    pub fn pipe_buffer_size(mut self, size: usize) -> Self
+    {
+        self.pipe_buffer_size = size;
+        self
+    }
+
+    /// Set the event queue size of watches
+    pub fn watch_queue_size(mut self, size: usize) -> Self
+    {
+        self.watch_queue_size = size;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> CNFSResult
+    {
+        if self.file_buffer_size == 0 || self.dcache_size == 0
+            || self.page_size == 0 || self.page_entry_size == 0 || self.pipe_buffer_size == 0
+            || self.watch_queue_size == 0
+        {
+            return Err(InvalidArgument);
+        }
+        Ok(())
+    }
+}
+
+impl Default for VfsConfig
+{
+    fn default() -> Self {
+        Self {
+            file_buffer_size: DEFAULT_FILE_BUFFER_SIZE,
+            dcache_size: DCACHE_SIZE,
+            page_size: OSINODE_PAGE_SIZE,
+            page_entry_size: OSINODE_PAGE_ENTRY_SIZE,
+            pipe_buffer_size: PIPE_BUFFER_SIZE,
+            watch_queue_size: WATCH_QUEUE_SIZE,
+        }
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: UPCell<VfsConfig> = unsafe{UPCell::new(VfsConfig::default())};
+}
+
+/// Set the global configuration.
+///
+/// It is used by every filesystem mounted afterwards unless the mount overrides it.
+/// The dentry cache size takes effect immediately.
+pub fn init(config: VfsConfig) -> CNFSResult
+{
+    config.validate()?;
+    *CONFIG.exclusive_access() = config;
+    Ok(())
+}
+
+/// Returns the global configuration.
+pub fn config() -> VfsConfig
+{
+    *CONFIG.shared_access()
+}
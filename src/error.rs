@@ -17,12 +17,37 @@ pub enum CNFSError
     NoMountedFilesystem,
     /// The method has not been implemented
     NotImplemented,
+    /// Invalid argument
+    InvalidArgument,
+    /// The filesystem is mounted read-only
+    ReadOnly,
+    /// The filesystem is busy
+    Busy,
+    /// The filesystem type is not registered or can't be detected
+    UnknownFileSystem,
+    /// Not a directory
+    NotADirectory,
+    /// Is a directory
+    IsADirectory,
+    /// The directory is not empty
+    DirectoryNotEmpty,
+    /// No space left on the filesystem
+    NoSpace,
+    /// The operation would block, like reading an empty pipe
+    WouldBlock,
+    /// Writing to a pipe whose readers are all closed
+    BrokenPipe,
+    /// The credentials don't allow the operation
+    PermissionDenied,
+    /// The extended attribute doesn't exist
+    NoAttribute,
     /// Internal filesystem error
     FSInternal(String),
     /// Unexpected error
     Unexpected,
 }
 
+#[allow(clippy::inherent_to_string_shadow_display)]
 impl CNFSError {
     /// Returns the error description.
     pub fn to_string(&self) -> String {
@@ -34,6 +59,18 @@ impl CNFSError {
             AlreadyExisted => "Already existed".into(),
             NoMountedFilesystem => "There is no filesystem mounted on the path".into(),
             NotImplemented => "Not implemented".into(),
+            InvalidArgument => "Invalid argument".into(),
+            ReadOnly => "Read-only filesystem".into(),
+            Busy => "The filesystem is busy".into(),
+            UnknownFileSystem => "Unknown filesystem type".into(),
+            NotADirectory => "Not a directory".into(),
+            IsADirectory => "Is a directory".into(),
+            DirectoryNotEmpty => "Directory not empty".into(),
+            NoSpace => "No space left on the filesystem".into(),
+            WouldBlock => "Operation would block".into(),
+            BrokenPipe => "Broken pipe".into(),
+            PermissionDenied => "Permission denied".into(),
+            NoAttribute => "No such attribute".into(),
             FSInternal(description) => "Internal Filesystem Error: ".to_string() + description,
             Unexpected => "Unexpected Error".into(),
         }
@@ -46,4 +83,50 @@ impl fmt::Display for CNFSError {
 }
 
 /// Result definition
-pub type CNFSResult<T = ()> = Result<T, CNFSError>;
\ No newline at end of file
+pub type CNFSResult<T = ()> = Result<T, CNFSError>;
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for CNFSError {
+    fn from(err: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        match err.kind() {
+            ErrorKind::NotFound => CNFSError::PathNotFound,
+            ErrorKind::AlreadyExists => CNFSError::AlreadyExisted,
+            ErrorKind::InvalidInput => CNFSError::InvalidArgument,
+            ErrorKind::NotADirectory => CNFSError::NotADirectory,
+            ErrorKind::IsADirectory => CNFSError::IsADirectory,
+            ErrorKind::DirectoryNotEmpty => CNFSError::DirectoryNotEmpty,
+            ErrorKind::StorageFull => CNFSError::NoSpace,
+            ErrorKind::ReadOnlyFilesystem => CNFSError::ReadOnly,
+            ErrorKind::Unsupported => CNFSError::NotImplemented,
+            ErrorKind::WouldBlock => CNFSError::WouldBlock,
+            ErrorKind::BrokenPipe => CNFSError::BrokenPipe,
+            ErrorKind::PermissionDenied => CNFSError::PermissionDenied,
+            _ => CNFSError::FSInternal(std::format!("{err}")),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<CNFSError> for std::io::Error {
+    fn from(err: CNFSError) -> Self {
+        use std::io::ErrorKind;
+        let kind = match err {
+            CNFSError::PathNotFound => ErrorKind::NotFound,
+            CNFSError::AlreadyExisted => ErrorKind::AlreadyExists,
+            CNFSError::InvalidArgument | CNFSError::InvalidPath => ErrorKind::InvalidInput,
+            CNFSError::NotADirectory => ErrorKind::NotADirectory,
+            CNFSError::IsADirectory => ErrorKind::IsADirectory,
+            CNFSError::DirectoryNotEmpty => ErrorKind::DirectoryNotEmpty,
+            CNFSError::NoSpace => ErrorKind::StorageFull,
+            CNFSError::ReadOnly => ErrorKind::ReadOnlyFilesystem,
+            CNFSError::Busy => ErrorKind::ResourceBusy,
+            CNFSError::NotImplemented => ErrorKind::Unsupported,
+            CNFSError::WouldBlock => ErrorKind::WouldBlock,
+            CNFSError::BrokenPipe => ErrorKind::BrokenPipe,
+            CNFSError::PermissionDenied => ErrorKind::PermissionDenied,
+            _ => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err.to_string())
+    }
+}
@@ -0,0 +1,276 @@
+use crate::error::CNFSError::{AlreadyExisted, InvalidArgument, IsADirectory, NoSpace, NotADirectory,
+                              NotImplemented, PathNotFound, ReadOnly};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::{check_root, DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata, DCACHE};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+/// Seed of the random device of [DevFs::new]
+const DEFAULT_SEED: u64 = 0x636e_6673;
+/// Type name of a devfs
+const FS_TYPE: &str = "devfs";
+
+/// Trait for character devices, streams of bytes that a [DevFs] shows as files
+///
+/// Reads and writes reach the device directly, without the page cache or the IO buffer of
+/// a [File](crate::File), and the file offset is not passed on.
+pub trait Device: Send + Sync
+{
+    /// Read from the device, returning how many bytes were read, or 0 at the end of the stream
+    fn read(&self, _buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        Err(NotImplemented)
+    }
+
+    /// Write to the device, returning how many bytes were written, which is never 0 for a
+    /// non-empty buffer
+    fn write(&self, _buffer: &[u8]) -> CNFSResult<usize>
+    {
+        Err(NotImplemented)
+    }
+}
+
+/// Reads nothing and discards writes
+struct Null;
+
+impl Device for Null
+{
+    fn read(&self, _buffer: &mut [u8]) -> CNFSResult<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, buffer: &[u8]) -> CNFSResult<usize> {
+        Ok(buffer.len())
+    }
+}
+
+/// Reads zeros and discards writes
+struct Zero;
+
+impl Device for Zero
+{
+    fn read(&self, buffer: &mut [u8]) -> CNFSResult<usize> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&self, buffer: &[u8]) -> CNFSResult<usize> {
+        Ok(buffer.len())
+    }
+}
+
+/// Reads zeros and is always out of space
+struct Full;
+
+impl Device for Full
+{
+    fn read(&self, buffer: &mut [u8]) -> CNFSResult<usize> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&self, _buffer: &[u8]) -> CNFSResult<usize> {
+        Err(NoSpace)
+    }
+}
+
+/// Reads a pseudo-random stream from splitmix64 and discards writes
+struct Random
+{
+    state: UPCell<u64>,
+}
+
+impl Random
+{
+    fn next(&self) -> u64
+    {
+        let mut state = self.state.exclusive_access();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Device for Random
+{
+    fn read(&self, buffer: &mut [u8]) -> CNFSResult<usize> {
+        for chunk in buffer.chunks_mut(8)
+        {
+            chunk.copy_from_slice(&self.next().to_le_bytes()[..chunk.len()]);
+        }
+        Ok(buffer.len())
+    }
+
+    fn write(&self, buffer: &[u8]) -> CNFSResult<usize> {
+        Ok(buffer.len())
+    }
+}
+
+/// Names of the devices every [DevFs] provides
+const BUILTIN_DEVICES: [&str; 4] = ["full", "null", "random", "zero"];
+
+lazy_static! {
+    static ref DEVICES: UPCell<BTreeMap<String, Arc<dyn Device>>> = unsafe{UPCell::new(BTreeMap::new())};
+}
+
+/// Register a device, making it a file in every [DevFs].
+///
+/// The name can't be one of the devices a [DevFs] provides itself. Only the superuser can
+/// register and unregister devices.
+pub fn register_device(name: &str, device: Arc<dyn Device>) -> CNFSResult
+{
+    check_root()?;
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') { return Err(InvalidArgument); }
+    let mut devices = DEVICES.exclusive_access();
+    if BUILTIN_DEVICES.contains(&name) || devices.contains_key(name) { return Err(AlreadyExisted); }
+    devices.insert(name.into(), device);
+    Ok(())
+}
+
+/// Unregister a device.
+///
+/// Files opened on it keep working until they are closed.
+pub fn unregister_device(name: &str) -> CNFSResult
+{
+    check_root()?;
+    DEVICES.exclusive_access().remove(name).ok_or(PathNotFound)?;
+    // Cached dentries would keep the device visible
+    let mut dcache = DCACHE.exclusive_access();
+    let removed: Vec<_> = match dcache.get_mut(name) {
+        Some(vec) => vec.extract_if(.., |d| d.mount.fs.fs_type() == FS_TYPE).collect(),
+        None => Vec::new(),
+    };
+    drop(dcache);
+    drop(removed);
+    Ok(())
+}
+
+/// Inode of a devfs, the root directory or a device
+pub struct DevInode
+{
+    builtin: Arc<BTreeMap<&'static str, Arc<dyn Device>>>,
+    device: Option<Arc<dyn Device>>,
+}
+
+impl DevInode
+{
+    fn device(&self) -> CNFSResult<&Arc<dyn Device>>
+    {
+        self.device.as_ref().ok_or(IsADirectory)
+    }
+}
+
+impl Inode for DevInode
+{
+    fn read(&self, _offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        self.device()?.read(buffer)
+    }
+
+    fn write(&self, _offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        self.device()?.write(buffer)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        if self.device.is_some() { return Err(NotADirectory); }
+        let device = match self.builtin.get(name) {
+            Some(device) => device.clone(),
+            None => DEVICES.shared_access().get(name).cloned().ok_or(PathNotFound)?,
+        };
+        Ok(Arc::new(DevInode { builtin: self.builtin.clone(), device: Some(device) }))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType) -> CNFSResult<InodeRef> {
+        Err(ReadOnly)
+    }
+
+    fn remove(&self, _name: &str) -> CNFSResult {
+        Err(ReadOnly)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        match self.device {
+            Some(_) => Ok(Metadata::new(InodeType::CharDevice, 0).with_permissions(0, 0, 0o666)),
+            None => Ok(Metadata::new(InodeType::Dir, 0).with_permissions(0, 0, 0o755)),
+        }
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        if self.device.is_some() { return Err(NotADirectory); }
+        let mut names: Vec<String> = self.builtin.keys().map(|&name| name.into()).collect();
+        names.extend(DEVICES.shared_access().keys().cloned());
+        names.sort();
+        Ok(names.into_iter().map(|name| DirEntry { name, inode_type: InodeType::CharDevice }).collect())
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Filesystem of character devices
+///
+/// It provides `null`, `zero`, `full` and `random`, a pseudo-random stream that is the same
+/// for the same seed, along with the devices added by [register_device].
+///
+/// ```rust
+///  use cnfs::{create_directory, mount, open, umount, FileMode, DevFs, MountOptions, Path, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  create_directory(&Path::new("/dev")).unwrap();
+///  mount(Arc::new(DevFs::new()), Path::new("/dev"), MountOptions::new()).unwrap();
+///  let mut buffer = [0x55_u8; 16];
+///  open(&Path::new("/dev/zero"), FileMode::read).unwrap().read(&mut buffer).unwrap();
+///  assert_eq!(buffer, [0; 16]);
+///  umount(Path::new("/dev")).unwrap();
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct DevFs
+{
+    builtin: Arc<BTreeMap<&'static str, Arc<dyn Device>>>,
+}
+
+impl DevFs
+{
+    /// New a devfs with the default seed
+    pub fn new() -> Self
+    {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    /// New a devfs whose random device is seeded with the given seed
+    pub fn with_seed(seed: u64) -> Self
+    {
+        let devices: [Arc<dyn Device>; 4] =
+            [Arc::new(Full), Arc::new(Null), Arc::new(Random { state: unsafe { UPCell::new(seed) } }), Arc::new(Zero)];
+        Self { builtin: Arc::new(BUILTIN_DEVICES.into_iter().zip(devices).collect()) }
+    }
+}
+
+impl Default for DevFs
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for DevFs
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(DevInode { builtin: self.builtin.clone(), device: None })
+    }
+
+    fn fs_type(&self) -> &str {
+        FS_TYPE
+    }
+}
@@ -0,0 +1,223 @@
+use super::layout::{DiskInode, FT_DIR, FT_REG_FILE, FT_SYMLINK, S_IFDIR, S_IFMT, S_IFREG};
+use super::volume::{now, Ext2Volume};
+use crate::error::CNFSError::{AlreadyExisted, DirectoryNotEmpty, InvalidArgument, InvalidPath, IsADirectory,
+                              NotADirectory, NotImplemented, PathNotFound};
+use crate::error::CNFSResult;
+use crate::vfs::{DirEntry, Inode, InodeRef, InodeType, Metadata};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Inode of an ext2 filesystem
+///
+/// It only holds the inode number, the on-disk inode is read through the buffer cache by
+/// every operation.
+pub struct Ext2Inode
+{
+    pub(super) volume: Arc<Ext2Volume>,
+    pub(super) ino: u32,
+}
+
+impl Ext2Inode
+{
+    fn child(&self, ino: u32) -> InodeRef
+    {
+        Arc::new(Ext2Inode { volume: self.volume.clone(), ino })
+    }
+
+    /// Read the on-disk inode, failing if it is not a directory
+    fn dir_inode(&self) -> CNFSResult<DiskInode>
+    {
+        let inode = self.volume.read_inode(self.ino)?;
+        if inode.is_dir() { Ok(inode) } else { Err(NotADirectory) }
+    }
+}
+
+fn check_name(name: &str) -> CNFSResult
+{
+    if name.is_empty() || name.len() > 255 || name == "." || name == ".." || name.contains(['/', '\0']) {
+        Err(InvalidPath)
+    } else {
+        Ok(())
+    }
+}
+
+impl Inode for Ext2Inode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        if self.volume.read_inode(self.ino)?.is_dir() { return Err(IsADirectory); }
+        self.volume.read_data(self.ino, offset, buffer)
+    }
+
+    fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        // Only regular files take data, links are rewritten by replacing them
+        let inode = self.volume.read_inode(self.ino)?;
+        if inode.is_dir() { return Err(IsADirectory); }
+        if !inode.is_reg() { return Err(InvalidArgument); }
+        self.volume.write_data(self.ino, offset, buffer)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        self.volume.cache.flush()
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        self.dir_inode()?;
+        let pos = self.volume.find_entry(self.ino, name)?.ok_or(PathNotFound)?;
+        // Devices, FIFOs and sockets of the volume can't be used through the VFS
+        if self.volume.read_inode(pos.inode)?.inode_type().is_none() { return Err(NotImplemented); }
+        Ok(self.child(pos.inode))
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType) -> CNFSResult<InodeRef> {
+        self.volume.check_writable()?;
+        if matches!(inode_type, InodeType::CharDevice | InodeType::Fifo | InodeType::Symlink)
+        {
+            return Err(InvalidArgument);
+        }
+        check_name(name)?;
+        self.dir_inode()?;
+        if self.volume.find_entry(self.ino, name)?.is_some() { return Err(AlreadyExisted); }
+
+        let is_dir = inode_type == InodeType::Dir;
+        let ino = self.volume.alloc_inode(self.volume.inode_group(self.ino), is_dir)?;
+        let time = now();
+        let mut inode = DiskInode([0; 128]);
+        inode.set_atime(time);
+        inode.set_ctime(time);
+        inode.set_mtime(time);
+        let created = if is_dir {
+            inode.set_mode(S_IFDIR | 0o755);
+            inode.set_links_count(2);
+            self.volume.map_block(ino, &mut inode, 0, true).and_then(|block| {
+                inode.set_size(self.volume.block_size as u64);
+                self.volume.init_dir(block, ino, self.ino)
+            })
+        } else {
+            inode.set_mode(S_IFREG | 0o644);
+            inode.set_links_count(1);
+            Ok(())
+        }.and_then(|_| {
+            self.volume.write_inode(ino, &inode)?;
+            self.volume.add_entry(self.ino, name, ino, is_dir)
+        });
+
+        if let Err(err) = created
+        {
+            // Give the inode back
+            self.volume.free_blocks(&mut inode)?;
+            inode.set_links_count(0);
+            inode.set_dtime(now());
+            self.volume.write_inode(ino, &inode)?;
+            self.volume.free_inode(ino, is_dir)?;
+            return Err(err);
+        }
+        if is_dir
+        {
+            // `add_entry` may have changed the parent
+            let mut parent = self.volume.read_inode(self.ino)?;
+            parent.set_links_count(parent.links_count() + 1);
+            self.volume.write_inode(self.ino, &parent)?;
+        }
+        Ok(self.child(ino))
+    }
+
+    fn remove(&self, name: &str) -> CNFSResult {
+        self.volume.check_writable()?;
+        check_name(name)?;
+        self.dir_inode()?;
+        let pos = self.volume.find_entry(self.ino, name)?.ok_or(PathNotFound)?;
+        let mut inode = self.volume.read_inode(pos.inode)?;
+        let is_dir = inode.is_dir();
+        if is_dir && !self.volume.is_empty_dir(pos.inode)? { return Err(DirectoryNotEmpty); }
+
+        self.volume.remove_entry(self.ino, &pos)?;
+        if is_dir
+        {
+            let mut parent = self.volume.read_inode(self.ino)?;
+            parent.set_links_count(parent.links_count().saturating_sub(1));
+            self.volume.write_inode(self.ino, &parent)?;
+            inode.set_links_count(0);
+        } else {
+            inode.set_links_count(inode.links_count().saturating_sub(1));
+        }
+        inode.set_ctime(now());
+        if inode.links_count() == 0
+        {
+            self.volume.free_blocks(&mut inode)?;
+            inode.set_dtime(now());
+            self.volume.write_inode(pos.inode, &inode)?;
+            self.volume.free_inode(pos.inode, is_dir)
+        } else {
+            self.volume.write_inode(pos.inode, &inode)
+        }
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> CNFSResult {
+        self.volume.check_writable()?;
+        check_name(old_name)?;
+        check_name(new_name)?;
+        self.dir_inode()?;
+        if self.volume.find_entry(self.ino, new_name)?.is_some() { return Err(AlreadyExisted); }
+        self.volume.rename_entry(self.ino, old_name, new_name)
+    }
+
+    fn set_modified(&self, modified: u64) -> CNFSResult {
+        self.volume.check_writable()?;
+        let mut inode = self.volume.read_inode(self.ino)?;
+        inode.set_mtime(modified as u32);
+        self.volume.write_inode(self.ino, &inode)
+    }
+
+    fn set_mode(&self, mode: u32) -> CNFSResult {
+        self.volume.check_writable()?;
+        let mut inode = self.volume.read_inode(self.ino)?;
+        inode.set_mode((inode.mode() & S_IFMT) | (mode & 0o7777) as u16);
+        inode.set_ctime(now());
+        self.volume.write_inode(self.ino, &inode)
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult {
+        self.volume.check_writable()?;
+        let mut inode = self.volume.read_inode(self.ino)?;
+        inode.set_owner(uid, gid);
+        inode.set_ctime(now());
+        self.volume.write_inode(self.ino, &inode)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let inode = self.volume.read_inode(self.ino)?;
+        let inode_type = inode.inode_type().ok_or(NotImplemented)?;
+        Ok(Metadata {
+            inode_type,
+            size: inode.size(),
+            accessed: Some(inode.atime() as u64),
+            modified: Some(inode.mtime() as u64),
+            created: None,
+            changed: Some(inode.ctime() as u64),
+            uid: Some(inode.full_uid()),
+            gid: Some(inode.full_gid()),
+            mode: Some((inode.mode() & !S_IFMT) as u32),
+        })
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        self.dir_inode()?;
+        let mut entries = Vec::new();
+        for (name, ino, file_type) in self.volume.list_dir(self.ino)?
+        {
+            // Names that are not UTF-8 can't be looked up, so they are left out, like the
+            // inodes of unsupported types
+            let Ok(name) = String::from_utf8(name) else { continue };
+            let inode_type = match file_type {
+                FT_DIR => Some(InodeType::Dir),
+                FT_REG_FILE => Some(InodeType::File),
+                FT_SYMLINK => Some(InodeType::Symlink),
+                _ => self.volume.read_inode(ino)?.inode_type(),
+            };
+            let Some(inode_type) = inode_type else { continue };
+            entries.push(DirEntry { name, inode_type });
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,290 @@
+//! On-disk structures of ext2, all little-endian
+use crate::vfs::InodeType;
+use alloc::vec::Vec;
+
+/// Offset of the superblock from the start of the volume
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+/// Size of the superblock
+pub const SUPERBLOCK_SIZE: usize = 1024;
+/// Magic number of ext2
+pub const EXT2_MAGIC: u16 = 0xEF53;
+/// Inode number of the root directory
+pub const ROOT_INO: u32 = 2;
+/// Size of a group descriptor
+pub const GROUP_DESC_SIZE: usize = 32;
+/// Number of direct blocks of an inode
+pub const DIRECT_BLOCKS: usize = 12;
+
+/// The `filetype` incompatible feature, the only one supported
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+/// The `sparse_super` read-only compatible feature
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+/// The `large_file` read-only compatible feature
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+
+/// Type bits of `i_mode`
+pub const S_IFMT: u16 = 0o170000;
+/// Directory
+pub const S_IFDIR: u16 = 0o040000;
+/// Regular file
+pub const S_IFREG: u16 = 0o100000;
+/// Symbolic link
+pub const S_IFLNK: u16 = 0o120000;
+
+/// Directory indexed by a hash tree, which can be read as a linear directory
+pub const INDEX_FL: u32 = 0x1000;
+
+/// File type of a directory entry, with the `filetype` feature
+pub const FT_REG_FILE: u8 = 1;
+/// Directory type of a directory entry, with the `filetype` feature
+pub const FT_DIR: u8 = 2;
+/// Symbolic link type of a directory entry, with the `filetype` feature
+pub const FT_SYMLINK: u8 = 7;
+
+pub fn le16(data: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub fn le32(data: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub fn set_le16(data: &mut [u8], offset: usize, value: u16)
+{
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn set_le32(data: &mut [u8], offset: usize, value: u32)
+{
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+macro_rules! field {
+    ($get: ident, $set: ident, u16, $offset: expr) => {
+        #[allow(dead_code)]
+        pub fn $get(&self) -> u16 { le16(&self.0, $offset) }
+        #[allow(dead_code)]
+        pub fn $set(&mut self, value: u16) { set_le16(&mut self.0, $offset, value) }
+    };
+    ($get: ident, $set: ident, u32, $offset: expr) => {
+        #[allow(dead_code)]
+        pub fn $get(&self) -> u32 { le32(&self.0, $offset) }
+        #[allow(dead_code)]
+        pub fn $set(&mut self, value: u32) { set_le32(&mut self.0, $offset, value) }
+    };
+}
+
+/// The superblock
+pub struct SuperBlock(pub Vec<u8>);
+
+impl SuperBlock
+{
+    field!(inodes_count, set_inodes_count, u32, 0);
+    field!(blocks_count, set_blocks_count, u32, 4);
+    field!(free_blocks_count, set_free_blocks_count, u32, 12);
+    field!(free_inodes_count, set_free_inodes_count, u32, 16);
+    field!(first_data_block, set_first_data_block, u32, 20);
+    field!(log_block_size, set_log_block_size, u32, 24);
+    field!(blocks_per_group, set_blocks_per_group, u32, 32);
+    field!(inodes_per_group, set_inodes_per_group, u32, 40);
+    field!(wtime, set_wtime, u32, 48);
+    field!(magic, set_magic, u16, 56);
+    field!(rev_level, set_rev_level, u32, 76);
+    field!(first_ino_rev1, set_first_ino, u32, 84);
+    field!(inode_size_rev1, set_inode_size, u16, 88);
+    field!(feature_incompat_rev1, set_feature_incompat, u32, 96);
+    field!(feature_ro_compat_rev1, set_feature_ro_compat, u32, 100);
+
+    pub fn block_size(&self) -> usize
+    {
+        1024 << self.log_block_size()
+    }
+
+    pub fn first_ino(&self) -> u32
+    {
+        if self.rev_level() == 0 { 11 } else { self.first_ino_rev1() }
+    }
+
+    pub fn inode_size(&self) -> usize
+    {
+        if self.rev_level() == 0 { 128 } else { self.inode_size_rev1() as usize }
+    }
+
+    pub fn feature_incompat(&self) -> u32
+    {
+        if self.rev_level() == 0 { 0 } else { self.feature_incompat_rev1() }
+    }
+
+    pub fn feature_ro_compat(&self) -> u32
+    {
+        if self.rev_level() == 0 { 0 } else { self.feature_ro_compat_rev1() }
+    }
+
+    pub fn group_count(&self) -> u32
+    {
+        (self.blocks_count() - self.first_data_block()).div_ceil(self.blocks_per_group())
+    }
+
+    /// Returns the volume name, without trailing NULs
+    pub fn volume_name(&self) -> &[u8]
+    {
+        let name = &self.0[120..136];
+        &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())]
+    }
+}
+
+/// A block group descriptor
+pub struct GroupDesc(pub [u8; GROUP_DESC_SIZE]);
+
+impl GroupDesc
+{
+    field!(block_bitmap, set_block_bitmap, u32, 0);
+    field!(inode_bitmap, set_inode_bitmap, u32, 4);
+    field!(inode_table, set_inode_table, u32, 8);
+    field!(free_blocks_count, set_free_blocks_count, u16, 12);
+    field!(free_inodes_count, set_free_inodes_count, u16, 14);
+    field!(used_dirs_count, set_used_dirs_count, u16, 16);
+}
+
+/// The first 128 bytes of an on-disk inode, which are the same in every revision
+pub struct DiskInode(pub [u8; 128]);
+
+impl DiskInode
+{
+    field!(mode, set_mode, u16, 0);
+    field!(uid, set_uid, u16, 2);
+    field!(size_lo, set_size_lo, u32, 4);
+    field!(atime, set_atime, u32, 8);
+    field!(ctime, set_ctime, u32, 12);
+    field!(mtime, set_mtime, u32, 16);
+    field!(dtime, set_dtime, u32, 20);
+    field!(gid, set_gid, u16, 24);
+    field!(links_count, set_links_count, u16, 26);
+    field!(blocks, set_blocks, u32, 28);
+    field!(flags, set_flags, u32, 32);
+    field!(size_high, set_size_high, u32, 108);
+    field!(uid_high, set_uid_high, u16, 120);
+    field!(gid_high, set_gid_high, u16, 122);
+
+    pub fn is_dir(&self) -> bool
+    {
+        self.mode() & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_reg(&self) -> bool
+    {
+        self.mode() & S_IFMT == S_IFREG
+    }
+
+    /// Returns the type of the inode, or `None` for devices, FIFOs and sockets, which are not
+    /// supported
+    pub fn inode_type(&self) -> Option<InodeType>
+    {
+        match self.mode() & S_IFMT {
+            S_IFDIR => Some(InodeType::Dir),
+            S_IFREG => Some(InodeType::File),
+            S_IFLNK => Some(InodeType::Symlink),
+            _ => None,
+        }
+    }
+
+    /// Returns the owner user ID, with the high 16 bits Linux keeps in the OS-dependent field
+    pub fn full_uid(&self) -> u32
+    {
+        (self.uid_high() as u32) << 16 | self.uid() as u32
+    }
+
+    /// Returns the owner group ID, with the high 16 bits Linux keeps in the OS-dependent field
+    pub fn full_gid(&self) -> u32
+    {
+        (self.gid_high() as u32) << 16 | self.gid() as u32
+    }
+
+    pub fn set_owner(&mut self, uid: u32, gid: u32)
+    {
+        self.set_uid(uid as u16);
+        self.set_uid_high((uid >> 16) as u16);
+        self.set_gid(gid as u16);
+        self.set_gid_high((gid >> 16) as u16);
+    }
+
+    /// A symlink whose target is stored in the block pointers
+    pub fn is_fast_symlink(&self) -> bool
+    {
+        self.mode() & S_IFMT == S_IFLNK && self.blocks() == 0
+    }
+
+    pub fn size(&self) -> u64
+    {
+        let high = if self.mode() & S_IFMT == S_IFREG { self.size_high() as u64 } else { 0 };
+        (high << 32) | self.size_lo() as u64
+    }
+
+    pub fn set_size(&mut self, size: u64)
+    {
+        self.set_size_lo(size as u32);
+        if self.mode() & S_IFMT == S_IFREG { self.set_size_high((size >> 32) as u32); }
+    }
+
+    /// Returns the i-th block pointer, direct ones first, then the single, double and
+    /// triple indirect ones
+    pub fn block(&self, i: usize) -> u32
+    {
+        le32(&self.0, 40 + i * 4)
+    }
+
+    pub fn set_block(&mut self, i: usize, block: u32)
+    {
+        set_le32(&mut self.0, 40 + i * 4, block)
+    }
+
+    /// Returns the raw block pointers, holding the target of a fast symlink
+    pub fn block_bytes(&self) -> &[u8]
+    {
+        &self.0[40..100]
+    }
+}
+
+/// Returns the length of a directory entry holding a name of `name_len` bytes
+pub fn dir_entry_len(name_len: usize) -> usize
+{
+    (8 + name_len + 3) & !3
+}
+
+/// A directory entry, parsed from a directory block
+pub struct DirEntry<'a>
+{
+    pub inode: u32,
+    pub rec_len: usize,
+    /// File type, with the `filetype` feature
+    pub file_type: u8,
+    pub name: &'a [u8],
+}
+
+/// Parse the entry at `offset` of a directory block, failing if it is corrupted
+pub fn parse_dir_entry(block: &[u8], offset: usize) -> Option<DirEntry<'_>>
+{
+    if offset + 8 > block.len() { return None; }
+    let rec_len = le16(block, offset + 4) as usize;
+    let name_len = block[offset + 6] as usize;
+    if rec_len < 8 || offset + rec_len > block.len() || 8 + name_len > rec_len { return None; }
+    Some(DirEntry {
+        inode: le32(block, offset),
+        rec_len,
+        file_type: block[offset + 7],
+        name: &block[offset + 8..offset + 8 + name_len],
+    })
+}
+
+/// Write a directory entry at `offset` of a directory block
+pub fn write_dir_entry(block: &mut [u8], offset: usize, inode: u32, rec_len: usize, name: &[u8],
+                       file_type: u8)
+{
+    set_le32(block, offset, inode);
+    set_le16(block, offset + 4, rec_len as u16);
+    block[offset + 6] = name.len() as u8;
+    block[offset + 7] = file_type;
+    block[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+}
@@ -0,0 +1,128 @@
+mod inode;
+mod layout;
+mod volume;
+
+use crate::block::{BlockDevice, BlockDeviceRef, BufferCache};
+use crate::error::CNFSError::{InvalidArgument, NotImplemented, UnknownFileSystem};
+use crate::error::CNFSResult;
+use crate::vfs::{FileSystem, FileSystemFactory, InodeRef, MountSource};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use inode::Ext2Inode;
+use layout::*;
+use volume::{corrupted, Ext2Volume};
+
+/// Number of device blocks kept by the buffer cache of a volume
+const CACHE_BLOCKS: usize = 1024;
+
+/// ext2 filesystem
+///
+/// Files use direct and indirect blocks, and directories are linear. Directories indexed
+/// by a hash tree are read as linear directories and lose their index when modified.
+/// Volumes with incompatible features other than `filetype` can't be opened, and volumes
+/// with read-only compatible features other than `sparse_super` and `large_file` are
+/// opened read-only.
+///
+/// ```rust
+///  use cnfs::{mount, read_to_end, umount, Ext2FileSystem, MountOptions, Path};
+///  use std::sync::Arc;
+///  let image = std::fs::read("tests/resources/ext2_1.img").unwrap();
+///  let fs = Ext2FileSystem::from_storage(std::io::Cursor::new(image)).unwrap();
+///  mount(Arc::new(fs), Path::new("/"), MountOptions::new()).unwrap();
+///  assert_eq!(read_to_end(&Path::new("/hello.txt")).unwrap(), b"cnss");
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct Ext2FileSystem
+{
+    volume: Arc<Ext2Volume>,
+}
+
+impl Ext2FileSystem
+{
+    /// Open the ext2 filesystem on the block device
+    pub fn open(device: BlockDeviceRef) -> CNFSResult<Self>
+    {
+        let cache = BufferCache::new(device, CACHE_BLOCKS)?;
+        let mut raw = vec![0_u8; SUPERBLOCK_SIZE];
+        cache.read_at(SUPERBLOCK_OFFSET, &mut raw).map_err(|_| UnknownFileSystem)?;
+        let sb = SuperBlock(raw);
+        if sb.magic() != EXT2_MAGIC || sb.log_block_size() > 6 || sb.blocks_per_group() == 0
+            || sb.inodes_per_group() == 0 || sb.inode_size() < 128
+        {
+            return Err(UnknownFileSystem);
+        }
+        // Every block group and inode must be covered by the group descriptor table
+        if sb.blocks_count() <= sb.first_data_block()
+            || sb.inodes_count() as u64 > sb.group_count() as u64 * sb.inodes_per_group() as u64
+        {
+            return Err(corrupted());
+        }
+        if sb.feature_incompat() & !INCOMPAT_FILETYPE != 0 { return Err(NotImplemented); }
+        let read_only = sb.feature_ro_compat() & !(RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE) != 0;
+        Ok(Self { volume: Arc::new(Ext2Volume::new(cache, sb, read_only)?) })
+    }
+
+    /// Open the ext2 filesystem on a `Read + Write + Seek` storage, like a host file or a
+    /// [File](crate::File) inside the VFS, available with the `std` feature
+    #[cfg(feature = "std")]
+    pub fn from_storage<T>(storage: T) -> CNFSResult<Self>
+    where
+        T: std::io::Read + std::io::Write + std::io::Seek + Send + 'static,
+    {
+        Self::open(Arc::new(crate::block::FileBlockDevice::new(storage, 512)?))
+    }
+
+    /// Check if the superblock read from the start of a volume belongs to ext2
+    pub fn probe(superblock: &[u8]) -> bool
+    {
+        let magic = SUPERBLOCK_OFFSET as usize + 56;
+        superblock.len() >= magic + 2 && le16(superblock, magic) == EXT2_MAGIC
+    }
+
+    /// Returns true if the volume has features that only allow reading it
+    pub fn read_only(&self) -> bool
+    {
+        self.volume.read_only
+    }
+
+    /// Returns the volume name
+    pub fn volume_name(&self) -> String
+    {
+        String::from_utf8_lossy(self.volume.sb.shared_access().volume_name()).into()
+    }
+}
+
+impl FileSystem for Ext2FileSystem
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(Ext2Inode { volume: self.volume.clone(), ino: ROOT_INO })
+    }
+
+    fn sync(&self) -> CNFSResult {
+        self.volume.cache.flush()
+    }
+
+    fn fs_type(&self) -> &str {
+        "ext2"
+    }
+}
+
+/// Builds ext2 filesystems from block devices for [mount_by_type](crate::mount_by_type), where
+/// it is registered as `ext2`
+pub struct Ext2Factory;
+
+impl FileSystemFactory for Ext2Factory
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::Device(device) => Ok(Arc::new(Ext2FileSystem::open(device.clone())?)),
+            _ => Err(InvalidArgument),
+        }
+    }
+
+    fn probe(&self, superblock: &[u8]) -> bool {
+        Ext2FileSystem::probe(superblock)
+    }
+}
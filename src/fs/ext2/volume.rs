@@ -0,0 +1,592 @@
+use super::layout::*;
+use crate::block::{BlockDevice, BufferCache};
+use crate::error::CNFSError::{FSInternal, InvalidArgument, NoSpace, PathNotFound, ReadOnly};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Returns the current time as stored in the 32-bit timestamps of ext2
+pub fn now() -> u32
+{
+    crate::vfs::now() as u32
+}
+
+pub fn corrupted() -> crate::CNFSError
+{
+    FSInternal("corrupted ext2 filesystem".into())
+}
+
+/// Location of a directory entry
+pub struct EntryPos
+{
+    pub inode: u32,
+    block: u32,
+    offset: usize,
+    prev: Option<usize>,
+}
+
+/// An ext2 volume, holding the superblock and group descriptors in memory
+///
+/// Every change to them is written through the buffer cache at once.
+pub struct Ext2Volume
+{
+    pub cache: BufferCache,
+    pub sb: UPCell<SuperBlock>,
+    groups: UPCell<Vec<GroupDesc>>,
+    pub block_size: usize,
+    pub read_only: bool,
+    filetype: bool,
+}
+
+impl Ext2Volume
+{
+    pub fn new(cache: BufferCache, sb: SuperBlock, read_only: bool) -> CNFSResult<Self>
+    {
+        let block_size = sb.block_size();
+        let count = sb.group_count() as usize;
+        let mut table = vec![0_u8; count * GROUP_DESC_SIZE];
+        cache.read_at((sb.first_data_block() as u64 + 1) * block_size as u64, &mut table)?;
+        let groups = table.chunks(GROUP_DESC_SIZE)
+            .map(|desc| GroupDesc(desc.try_into().unwrap())).collect();
+        let filetype = sb.feature_incompat() & INCOMPAT_FILETYPE != 0;
+        Ok(Self {
+            cache,
+            sb: unsafe { UPCell::new(sb) },
+            groups: unsafe { UPCell::new(groups) },
+            block_size,
+            read_only,
+            filetype,
+        })
+    }
+
+    pub fn check_writable(&self) -> CNFSResult
+    {
+        if self.read_only { Err(ReadOnly) } else { Ok(()) }
+    }
+
+    fn block_offset(&self, block: u32) -> u64
+    {
+        block as u64 * self.block_size as u64
+    }
+
+    fn write_super(&self) -> CNFSResult
+    {
+        self.cache.write_at(SUPERBLOCK_OFFSET, &self.sb.shared_access().0)
+    }
+
+    fn write_group(&self, group: usize) -> CNFSResult
+    {
+        let table = self.block_offset(self.sb.shared_access().first_data_block() + 1);
+        let offset = table + (group * GROUP_DESC_SIZE) as u64;
+        self.cache.write_at(offset, &self.groups.shared_access()[group].0)
+    }
+
+    // ---- Inodes ----
+
+    fn inode_offset(&self, ino: u32) -> CNFSResult<u64>
+    {
+        let sb = self.sb.shared_access();
+        if ino == 0 || ino > sb.inodes_count() { return Err(corrupted()); }
+        let group = ((ino - 1) / sb.inodes_per_group()) as usize;
+        let index = ((ino - 1) % sb.inodes_per_group()) as u64;
+        let table = self.groups.shared_access().get(group).ok_or_else(corrupted)?.inode_table();
+        Ok(self.block_offset(table) + index * sb.inode_size() as u64)
+    }
+
+    pub fn read_inode(&self, ino: u32) -> CNFSResult<DiskInode>
+    {
+        let mut inode = DiskInode([0; 128]);
+        self.cache.read_at(self.inode_offset(ino)?, &mut inode.0)?;
+        Ok(inode)
+    }
+
+    pub fn write_inode(&self, ino: u32, inode: &DiskInode) -> CNFSResult
+    {
+        self.cache.write_at(self.inode_offset(ino)?, &inode.0)
+    }
+
+    /// Returns the group holding the inode
+    pub fn inode_group(&self, ino: u32) -> u32
+    {
+        (ino - 1) / self.sb.shared_access().inodes_per_group()
+    }
+
+    // ---- Bitmaps ----
+
+    /// Set the first clear bit among `bits` bits of a bitmap block, returning its index
+    fn take_bit(&self, bitmap: u32, bits: u32) -> CNFSResult<Option<u32>>
+    {
+        let mut data = vec![0_u8; (bits as usize).div_ceil(8)];
+        let offset = self.block_offset(bitmap);
+        self.cache.read_at(offset, &mut data)?;
+        for bit in 0..bits
+        {
+            let (byte, mask) = ((bit / 8) as usize, 1_u8 << (bit % 8));
+            if data[byte] & mask == 0
+            {
+                self.cache.write_at(offset + byte as u64, &[data[byte] | mask])?;
+                return Ok(Some(bit));
+            }
+        }
+        Ok(None)
+    }
+
+    fn clear_bit(&self, bitmap: u32, bit: u32) -> CNFSResult
+    {
+        let offset = self.block_offset(bitmap) + (bit / 8) as u64;
+        let mut byte = [0_u8];
+        self.cache.read_at(offset, &mut byte)?;
+        self.cache.write_at(offset, &[byte[0] & !(1 << (bit % 8))])
+    }
+
+    /// Allocate a block, trying the goal group first
+    pub fn alloc_block(&self, goal: u32) -> CNFSResult<u32>
+    {
+        let (count, first, per_group, total) = {
+            let sb = self.sb.shared_access();
+            (sb.group_count(), sb.first_data_block(), sb.blocks_per_group(), sb.blocks_count())
+        };
+        for i in 0..count
+        {
+            let group = (goal + i) % count;
+            let bitmap = {
+                let groups = self.groups.shared_access();
+                if groups[group as usize].free_blocks_count() == 0 { continue; }
+                groups[group as usize].block_bitmap()
+            };
+            let bits = min(per_group, total - first - group * per_group);
+            if let Some(bit) = self.take_bit(bitmap, bits)?
+            {
+                {
+                    let mut groups = self.groups.exclusive_access();
+                    let desc = &mut groups[group as usize];
+                    desc.set_free_blocks_count(desc.free_blocks_count() - 1);
+                    let mut sb = self.sb.exclusive_access();
+                    let free = sb.free_blocks_count();
+                    sb.set_free_blocks_count(free.saturating_sub(1));
+                }
+                self.write_group(group as usize)?;
+                self.write_super()?;
+                let block = first + group * per_group + bit;
+                self.cache.write_at(self.block_offset(block), &vec![0_u8; self.block_size])?;
+                return Ok(block);
+            }
+        }
+        Err(NoSpace)
+    }
+
+    pub fn free_block(&self, block: u32) -> CNFSResult
+    {
+        let (first, per_group) = {
+            let sb = self.sb.shared_access();
+            (sb.first_data_block(), sb.blocks_per_group())
+        };
+        if block < first { return Err(corrupted()); }
+        let group = ((block - first) / per_group) as usize;
+        let bitmap = self.groups.shared_access().get(group).ok_or_else(corrupted)?.block_bitmap();
+        self.clear_bit(bitmap, (block - first) % per_group)?;
+        {
+            let mut groups = self.groups.exclusive_access();
+            let desc = &mut groups[group];
+            desc.set_free_blocks_count(desc.free_blocks_count() + 1);
+            let mut sb = self.sb.exclusive_access();
+            let free = sb.free_blocks_count();
+            sb.set_free_blocks_count(free + 1);
+        }
+        self.write_group(group)?;
+        self.write_super()
+    }
+
+    /// Allocate an inode, trying the goal group first
+    pub fn alloc_inode(&self, goal: u32, is_dir: bool) -> CNFSResult<u32>
+    {
+        let (count, per_group, first_ino) = {
+            let sb = self.sb.shared_access();
+            (sb.group_count(), sb.inodes_per_group(), sb.first_ino())
+        };
+        for i in 0..count
+        {
+            let group = (goal + i) % count;
+            let bitmap = {
+                let groups = self.groups.shared_access();
+                if groups[group as usize].free_inodes_count() == 0 { continue; }
+                groups[group as usize].inode_bitmap()
+            };
+            let Some(bit) = self.take_bit(bitmap, per_group)? else { continue; };
+            let ino = group * per_group + bit + 1;
+            if ino < first_ino { return Err(corrupted()); }
+            {
+                let mut groups = self.groups.exclusive_access();
+                let desc = &mut groups[group as usize];
+                desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+                if is_dir { desc.set_used_dirs_count(desc.used_dirs_count() + 1); }
+                let mut sb = self.sb.exclusive_access();
+                let free = sb.free_inodes_count();
+                sb.set_free_inodes_count(free.saturating_sub(1));
+            }
+            self.write_group(group as usize)?;
+            self.write_super()?;
+            // Clear the whole on-disk inode, including the space after the first 128 bytes
+            let inode_size = self.sb.shared_access().inode_size();
+            self.cache.write_at(self.inode_offset(ino)?, &vec![0_u8; inode_size])?;
+            return Ok(ino);
+        }
+        Err(NoSpace)
+    }
+
+    pub fn free_inode(&self, ino: u32, is_dir: bool) -> CNFSResult
+    {
+        let per_group = self.sb.shared_access().inodes_per_group();
+        let group = ((ino - 1) / per_group) as usize;
+        let bitmap = self.groups.shared_access()[group].inode_bitmap();
+        self.clear_bit(bitmap, (ino - 1) % per_group)?;
+        {
+            let mut groups = self.groups.exclusive_access();
+            let desc = &mut groups[group];
+            desc.set_free_inodes_count(desc.free_inodes_count() + 1);
+            if is_dir { desc.set_used_dirs_count(desc.used_dirs_count().saturating_sub(1)); }
+            let mut sb = self.sb.exclusive_access();
+            let free = sb.free_inodes_count();
+            sb.set_free_inodes_count(free + 1);
+        }
+        self.write_group(group)?;
+        self.write_super()
+    }
+
+    // ---- Block mapping ----
+
+    fn read_ptr(&self, block: u32, index: u64) -> CNFSResult<u32>
+    {
+        let mut ptr = [0_u8; 4];
+        self.cache.read_at(self.block_offset(block) + index * 4, &mut ptr)?;
+        Ok(u32::from_le_bytes(ptr))
+    }
+
+    fn write_ptr(&self, block: u32, index: u64, ptr: u32) -> CNFSResult
+    {
+        self.cache.write_at(self.block_offset(block) + index * 4, &ptr.to_le_bytes())
+    }
+
+    /// Returns the block holding the `index`-th block of the inode, or 0 for a hole.
+    ///
+    /// With `alloc`, missing data and indirect blocks are allocated and the inode is updated,
+    /// but it is up to the caller to write it back.
+    pub fn map_block(&self, ino: u32, inode: &mut DiskInode, index: u64, alloc: bool) -> CNFSResult<u32>
+    {
+        let ptrs = (self.block_size / 4) as u64;
+        let mut path = [0_u64; 3];
+        let (slot, depth) = if index < DIRECT_BLOCKS as u64 {
+            (index as usize, 0)
+        } else {
+            let mut rest = index - DIRECT_BLOCKS as u64;
+            let mut span = ptrs;
+            let mut depth = 1;
+            while rest >= span
+            {
+                rest -= span;
+                span *= ptrs;
+                depth += 1;
+                if depth > 3 { return if alloc { Err(NoSpace) } else { Ok(0) }; }
+            }
+            for level in (0..depth).rev()
+            {
+                path[level] = rest % ptrs;
+                rest /= ptrs;
+            }
+            (DIRECT_BLOCKS + depth - 1, depth)
+        };
+
+        let goal = self.inode_group(ino);
+        let sectors = (self.block_size / 512) as u32;
+        let mut block = inode.block(slot);
+        if block == 0
+        {
+            if !alloc { return Ok(0); }
+            block = self.alloc_block(goal)?;
+            inode.set_block(slot, block);
+            inode.set_blocks(inode.blocks() + sectors);
+        }
+        for &i in &path[..depth]
+        {
+            let mut next = self.read_ptr(block, i)?;
+            if next == 0
+            {
+                if !alloc { return Ok(0); }
+                next = self.alloc_block(goal)?;
+                self.write_ptr(block, i, next)?;
+                inode.set_blocks(inode.blocks() + sectors);
+            }
+            block = next;
+        }
+        Ok(block)
+    }
+
+    fn free_tree(&self, block: u32, depth: u32) -> CNFSResult
+    {
+        if block == 0 { return Ok(()); }
+        if depth > 0
+        {
+            let mut data = vec![0_u8; self.block_size];
+            self.cache.read_at(self.block_offset(block), &mut data)?;
+            for ptr in data.chunks(4).map(|p| u32::from_le_bytes(p.try_into().unwrap()))
+            {
+                self.free_tree(ptr, depth - 1)?;
+            }
+        }
+        self.free_block(block)
+    }
+
+    /// Free every block of the inode
+    pub fn free_blocks(&self, inode: &mut DiskInode) -> CNFSResult
+    {
+        if inode.is_fast_symlink() { return Ok(()); }
+        for slot in 0..DIRECT_BLOCKS + 3
+        {
+            let depth = slot.saturating_sub(DIRECT_BLOCKS - 1) as u32;
+            self.free_tree(inode.block(slot), depth)?;
+            inode.set_block(slot, 0);
+        }
+        inode.set_blocks(0);
+        inode.set_size(0);
+        Ok(())
+    }
+
+    // ---- Data ----
+
+    pub fn read_data(&self, ino: u32, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        let mut inode = self.read_inode(ino)?;
+        let size = inode.size();
+        if offset >= size { return Ok(0); }
+        let len = min(buffer.len() as u64, size - offset) as usize;
+        if inode.is_fast_symlink()
+        {
+            let target = inode.block_bytes();
+            buffer[..len].copy_from_slice(&target[offset as usize..offset as usize + len]);
+            return Ok(len);
+        }
+        let block_size = self.block_size as u64;
+        let mut done = 0;
+        while done < len
+        {
+            let pos = offset + done as u64;
+            let start = pos % block_size;
+            let n = min(block_size - start, (len - done) as u64) as usize;
+            match self.map_block(ino, &mut inode, pos / block_size, false)? {
+                0 => buffer[done..done + n].fill(0),
+                block => self.cache.read_at(self.block_offset(block) + start, &mut buffer[done..done + n])?,
+            }
+            done += n;
+        }
+        Ok(len)
+    }
+
+    pub fn write_data(&self, ino: u32, offset: u64, buffer: &[u8]) -> CNFSResult<usize>
+    {
+        self.check_writable()?;
+        let mut inode = self.read_inode(ino)?;
+        // The inode has been removed while still in use
+        if inode.links_count() == 0 { return Err(crate::CNFSError::PathNotFound); }
+        if !inode.is_reg() { return Err(InvalidArgument); }
+        let block_size = self.block_size as u64;
+        let mut done = 0;
+        let mut result = Ok(());
+        while done < buffer.len()
+        {
+            let pos = offset + done as u64;
+            let start = pos % block_size;
+            let n = min(block_size - start, (buffer.len() - done) as u64) as usize;
+            let written = self.map_block(ino, &mut inode, pos / block_size, true).and_then(|block| {
+                self.cache.write_at(self.block_offset(block) + start, &buffer[done..done + n])
+            });
+            if let Err(err) = written
+            {
+                result = Err(err);
+                break;
+            }
+            done += n;
+        }
+        let end = offset + done as u64;
+        if end > inode.size() { inode.set_size(end); }
+        let time = now();
+        inode.set_mtime(time);
+        inode.set_ctime(time);
+        self.write_inode(ino, &inode)?;
+        match result {
+            Err(err) if done == 0 => Err(err),
+            _ => Ok(done),
+        }
+    }
+
+    // ---- Directories ----
+
+    /// Run `f` on every entry of a directory, until it returns true
+    fn scan_dir(&self, ino: u32, mut f: impl FnMut(&DirEntry, &EntryPos) -> bool)
+                -> CNFSResult<Option<EntryPos>>
+    {
+        let mut inode = self.read_inode(ino)?;
+        let blocks = inode.size().div_ceil(self.block_size as u64);
+        let mut data = vec![0_u8; self.block_size];
+        for index in 0..blocks
+        {
+            let block = self.map_block(ino, &mut inode, index, false)?;
+            if block == 0 { continue; }
+            self.cache.read_at(self.block_offset(block), &mut data)?;
+            let mut offset = 0;
+            let mut prev = None;
+            while offset < self.block_size
+            {
+                let entry = parse_dir_entry(&data, offset).ok_or_else(corrupted)?;
+                let pos = EntryPos { inode: entry.inode, block, offset, prev };
+                if f(&entry, &pos) { return Ok(Some(pos)); }
+                prev = Some(offset);
+                offset += entry.rec_len;
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn find_entry(&self, dir: u32, name: &str) -> CNFSResult<Option<EntryPos>>
+    {
+        self.scan_dir(dir, |entry, _| entry.inode != 0 && entry.name == name.as_bytes())
+    }
+
+    pub fn is_empty_dir(&self, dir: u32) -> CNFSResult<bool>
+    {
+        let found = self.scan_dir(dir, |entry, _| {
+            entry.inode != 0 && entry.name != b"." && entry.name != b".."
+        })?;
+        Ok(found.is_none())
+    }
+
+    /// Returns the name, inode and file type of every entry of a directory but `.` and `..`
+    pub fn list_dir(&self, dir: u32) -> CNFSResult<Vec<(Vec<u8>, u32, u8)>>
+    {
+        let mut entries = Vec::new();
+        self.scan_dir(dir, |entry, _| {
+            if entry.inode != 0 && entry.name != b"." && entry.name != b".."
+            {
+                let file_type = if self.filetype { entry.file_type } else { 0 };
+                entries.push((entry.name.to_vec(), entry.inode, file_type));
+            }
+            false
+        })?;
+        Ok(entries)
+    }
+
+    fn file_type(&self, is_dir: bool) -> u8
+    {
+        match (self.filetype, is_dir) {
+            (false, _) => 0,
+            (true, true) => FT_DIR,
+            (true, false) => FT_REG_FILE,
+        }
+    }
+
+    /// Write the `.` and `..` entries of a new directory into its first block
+    pub fn init_dir(&self, block: u32, ino: u32, parent: u32) -> CNFSResult
+    {
+        let mut data = vec![0_u8; self.block_size];
+        let file_type = self.file_type(true);
+        write_dir_entry(&mut data, 0, ino, 12, b".", file_type);
+        write_dir_entry(&mut data, 12, parent, self.block_size - 12, b"..", file_type);
+        self.cache.write_at(self.block_offset(block), &data)
+    }
+
+    /// Add an entry to a directory, growing it by a block if no entry has enough room
+    pub fn add_entry(&self, dir: u32, name: &str, ino: u32, is_dir: bool) -> CNFSResult
+    {
+        self.insert_entry(dir, name, ino, self.file_type(is_dir))
+    }
+
+    /// Give the entry `old_name` of a directory the name `new_name`, keeping its file type
+    pub fn rename_entry(&self, dir: u32, old_name: &str, new_name: &str) -> CNFSResult
+    {
+        let mut file_type = 0;
+        let pos = self.scan_dir(dir, |entry, _| {
+            let found = entry.inode != 0 && entry.name == old_name.as_bytes();
+            if found { file_type = entry.file_type; }
+            found
+        })?.ok_or(PathNotFound)?;
+        self.insert_entry(dir, new_name, pos.inode, file_type)?;
+        // The new entry may have been carved out of the old one's room, so look it up again
+        let pos = self.find_entry(dir, old_name)?.ok_or_else(corrupted)?;
+        self.remove_entry(dir, &pos)
+    }
+
+    fn insert_entry(&self, dir: u32, name: &str, ino: u32, file_type: u8) -> CNFSResult
+    {
+        let needed = dir_entry_len(name.len());
+        let mut data = vec![0_u8; self.block_size];
+        let found = self.scan_dir(dir, |entry, _| {
+            let used = if entry.inode == 0 { 0 } else { dir_entry_len(entry.name.len()) };
+            entry.rec_len.saturating_sub(used) >= needed
+        })?;
+
+        let mut inode = self.read_inode(dir)?;
+        match found {
+            Some(pos) => {
+                let offset = self.block_offset(pos.block);
+                self.cache.read_at(offset, &mut data)?;
+                let entry = parse_dir_entry(&data, pos.offset).ok_or_else(corrupted)?;
+                let rec_len = entry.rec_len;
+                if entry.inode == 0 {
+                    write_dir_entry(&mut data, pos.offset, ino, rec_len, name.as_bytes(), file_type);
+                } else {
+                    let used = dir_entry_len(entry.name.len());
+                    set_le16(&mut data, pos.offset + 4, used as u16);
+                    write_dir_entry(&mut data, pos.offset + used, ino, rec_len - used,
+                                    name.as_bytes(), file_type);
+                }
+                self.cache.write_at(offset, &data)?;
+            }
+            None => {
+                let index = inode.size().div_ceil(self.block_size as u64);
+                let block = self.map_block(dir, &mut inode, index, true);
+                let block = match block {
+                    Ok(block) => block,
+                    Err(err) => {
+                        self.write_inode(dir, &inode)?;
+                        return Err(err);
+                    }
+                };
+                write_dir_entry(&mut data, 0, ino, self.block_size, name.as_bytes(), file_type);
+                self.cache.write_at(self.block_offset(block), &data)?;
+                inode.set_size((index + 1) * self.block_size as u64);
+            }
+        }
+        self.touch_dir(&mut inode);
+        self.write_inode(dir, &inode)
+    }
+
+    /// Remove the entry at `pos`, merging it into the previous entry of the block
+    pub fn remove_entry(&self, dir: u32, pos: &EntryPos) -> CNFSResult
+    {
+        let offset = self.block_offset(pos.block);
+        let mut data = vec![0_u8; self.block_size];
+        self.cache.read_at(offset, &mut data)?;
+        match pos.prev {
+            Some(prev) => {
+                let rec_len = le16(&data, pos.offset + 4);
+                let prev_len = le16(&data, prev + 4);
+                set_le16(&mut data, prev + 4, prev_len + rec_len);
+            }
+            None => set_le32(&mut data, pos.offset, 0),
+        }
+        self.cache.write_at(offset, &data)?;
+        let mut inode = self.read_inode(dir)?;
+        self.touch_dir(&mut inode);
+        self.write_inode(dir, &inode)
+    }
+
+    /// Update the times of a modified directory, which is no longer indexed
+    fn touch_dir(&self, inode: &mut DiskInode)
+    {
+        let time = now();
+        inode.set_mtime(time);
+        inode.set_ctime(time);
+        inode.set_flags(inode.flags() & !INDEX_FL);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::block::BlockStorage;
+use crate::error::CNFSError::{AlreadyExisted, DirectoryNotEmpty, InvalidArgument, IsADirectory, NoSpace,
+                              NotADirectory, PathNotFound};
+use crate::error::{CNFSError, CNFSResult};
+use crate::sync::UPCell;
+use crate::vfs::{DirEntry, FileSystem, FileSystemFactory, Inode, InodeRef, InodeType, Metadata, MountSource};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use fatfs::{Dir, FsOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Returns [NoSpace] for an error raised while allocating if the volume has no free cluster left
+fn alloc_error<T: Read + Write + Seek>(fs: &fatfs::FileSystem<T>, err: std::io::Error) -> CNFSError
+{
+    match fs.stats() {
+        Ok(stats) if stats.free_clusters() == 0 => NoSpace,
+        _ => err.into(),
+    }
+}
+
+/// Storage shared by the fatfs filesystem and the volume, so the volume can flush it
+struct FatStorage<T: Write>
+{
+    inner: Arc<UPCell<T>>,
+}
+
+impl<T: Read + Write> Read for FatStorage<T>
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.exclusive_access().read(buf)
+    }
+}
+
+impl<T: Write> Write for FatStorage<T>
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.exclusive_access().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.exclusive_access().flush()
+    }
+}
+
+impl<T: Write + Seek> Seek for FatStorage<T>
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.exclusive_access().seek(pos)
+    }
+}
+
+impl<T: Write> Drop for FatStorage<T>
+{
+    fn drop(&mut self) {
+        // fatfs writes the FS info sector and clears the dirty flag when it is dropped, right
+        // before its storage, and nothing is left to report a failure to
+        let _ = self.inner.exclusive_access().flush();
+    }
+}
+
+/// A FAT volume shared by all inodes
+///
+/// The fatfs filesystem is opened once on the storage and kept until the volume is dropped,
+/// which unmounts it.
+struct FatVolume<T: Read + Write + Seek>
+{
+    fs: UPCell<fatfs::FileSystem<FatStorage<T>>>,
+    storage: Arc<UPCell<T>>,
+}
+
+// fatfs keeps references to its default code page converter and time provider, which have no
+// state, so the filesystem can be sent to another thread with its storage.
+unsafe impl<T: Read + Write + Seek + Send> Send for FatVolume<T> {}
+
+impl<T: Read + Write + Seek> FatVolume<T>
+{
+    /// Open the fatfs filesystem on the storage
+    fn open(mut storage: T) -> CNFSResult<Self>
+    {
+        storage.seek(SeekFrom::Start(0))?;
+        let storage = Arc::new(unsafe { UPCell::new(storage) });
+        let fs = fatfs::FileSystem::new(FatStorage { inner: storage.clone() }, FsOptions::new())?;
+        Ok(Self { fs: unsafe { UPCell::new(fs) }, storage })
+    }
+
+    /// Run `f` on the fatfs filesystem
+    fn with_fs<R>(&self, f: impl FnOnce(&fatfs::FileSystem<FatStorage<T>>) -> CNFSResult<R>) -> CNFSResult<R>
+    {
+        f(&self.fs.shared_access())
+    }
+
+    /// Run `f` on the directory at `path`, relative to the root directory
+    fn with_dir<R>(&self, path: &str, f: impl FnOnce(Dir<'_, FatStorage<T>>) -> CNFSResult<R>) -> CNFSResult<R>
+    {
+        self.with_fs(|fs| {
+            let dir = if path.is_empty() { fs.root_dir() } else { fs.root_dir().open_dir(path)? };
+            f(dir)
+        })
+    }
+
+    /// Run `f` on the file at `path`, relative to the root directory
+    fn with_file<R>(&self, path: &str, f: impl FnOnce(&mut fatfs::File<'_, FatStorage<T>>) -> CNFSResult<R>)
+                    -> CNFSResult<R>
+    {
+        self.with_fs(|fs| f(&mut fs.root_dir().open_file(path)?))
+    }
+
+    /// Flush the storage
+    fn flush(&self) -> CNFSResult
+    {
+        Ok(self.storage.exclusive_access().flush()?)
+    }
+}
+
+/// Inode of a FAT filesystem
+///
+/// It is addressed by its path from the root directory and the file or directory is opened
+/// again by every operation, so it never borrows from the filesystem.
+pub struct FatInode<T: Read + Write + Seek>
+{
+    volume: Arc<FatVolume<T>>,
+    path: String,
+    inode_type: InodeType,
+}
+
+impl<T: Read + Write + Seek> FatInode<T>
+{
+    fn child_path(&self, name: &str) -> String
+    {
+        if self.path.is_empty() { name.into() } else { format!("{}/{}", self.path, name) }
+    }
+}
+
+impl<T: Read + Write + Seek + Send + 'static> Inode for FatInode<T>
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        if self.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        self.volume.with_file(&self.path, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut nread = 0;
+            while nread < buffer.len()
+            {
+                let bytes = file.read(&mut buffer[nread..])?;
+                if bytes == 0 { break; }
+                nread += bytes;
+            }
+            Ok(nread)
+        })
+    }
+
+    fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        if self.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        self.volume.with_fs(|fs| {
+            let mut file = fs.root_dir().open_file(&self.path)?;
+            // fatfs can't seek past the end, so fill the hole first
+            let mut size = file.seek(SeekFrom::End(0))?;
+            let zeros = [0_u8; 512];
+            while size < offset
+            {
+                let len = core::cmp::min(zeros.len() as u64, offset - size) as usize;
+                let bytes = file.write(&zeros[..len]).map_err(|err| alloc_error(fs, err))?;
+                if bytes == 0 { return Err(NoSpace); }
+                size += bytes as u64;
+            }
+            file.seek(SeekFrom::Start(offset))?;
+            file.write(buffer).map_err(|err| alloc_error(fs, err))
+        })
+    }
+
+    fn sync(&self) -> CNFSResult {
+        if self.inode_type == InodeType::Dir { return Ok(()); }
+        self.volume.with_file(&self.path, |file| Ok(file.flush()?))
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        if self.inode_type == InodeType::File { return Err(NotADirectory); }
+        let inode_type = self.volume.with_dir(&self.path, |dir| {
+            // Opening a name as the wrong type fails without telling why, so try both
+            match dir.open_file(name) {
+                Ok(_) => Ok(InodeType::File),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Err(PathNotFound),
+                Err(err) => dir.open_dir(name).map(|_| InodeType::Dir).map_err(|_| err.into()),
+            }
+        })?;
+        Ok(Arc::new(FatInode {
+            volume: self.volume.clone(),
+            path: self.child_path(name),
+            inode_type,
+        }))
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType) -> CNFSResult<InodeRef> {
+        if matches!(inode_type, InodeType::CharDevice | InodeType::Fifo | InodeType::Symlink)
+        {
+            return Err(InvalidArgument);
+        }
+        match self.lookup(name) {
+            Ok(_) => return Err(AlreadyExisted),
+            Err(PathNotFound) => {}
+            Err(err) => return Err(err),
+        }
+        self.volume.with_fs(|fs| {
+            let dir = if self.path.is_empty() { fs.root_dir() } else { fs.root_dir().open_dir(&self.path)? };
+            match inode_type {
+                InodeType::Dir => dir.create_dir(name).map(|_| ()),
+                _ => dir.create_file(name).map(|_| ()),
+            }.map_err(|err| alloc_error(fs, err))
+        })?;
+        Ok(Arc::new(FatInode {
+            volume: self.volume.clone(),
+            path: self.child_path(name),
+            inode_type,
+        }))
+    }
+
+    fn remove(&self, name: &str) -> CNFSResult {
+        if self.inode_type == InodeType::File { return Err(NotADirectory); }
+        let child = self.lookup(name)?;
+        if child.metadata()?.inode_type == InodeType::Dir && !child.read_dir()?.is_empty()
+        {
+            return Err(DirectoryNotEmpty);
+        }
+        self.volume.with_dir(&self.path, |dir| Ok(dir.remove(name)?))
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> CNFSResult {
+        self.lookup(old_name)?;
+        match self.lookup(new_name) {
+            Ok(_) => return Err(AlreadyExisted),
+            Err(PathNotFound) => {}
+            Err(err) => return Err(err),
+        }
+        self.volume.with_dir(&self.path, |dir| Ok(dir.rename(old_name, &dir, new_name)?))
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        if self.inode_type == InodeType::Dir { return Ok(Metadata::new(InodeType::Dir, 0)); }
+        let size = self.volume.with_file(&self.path, |file| Ok(file.seek(SeekFrom::End(0))?))?;
+        Ok(Metadata::new(InodeType::File, size))
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        if self.inode_type == InodeType::File { return Err(NotADirectory); }
+        self.volume.with_dir(&self.path, |dir| {
+            let mut entries = Vec::new();
+            for entry in dir.iter()
+            {
+                let entry = entry?;
+                let name = entry.file_name();
+                if name == "." || name == ".." { continue; }
+                let inode_type = if entry.is_dir() { InodeType::Dir } else { InodeType::File };
+                entries.push(DirEntry { name, inode_type });
+            }
+            Ok(entries)
+        })
+    }
+}
+
+/// FAT12/16/32 filesystem, available with the `fatfs` feature
+///
+/// The storage can be anything implementing `Read + Write + Seek`, like a host file or a
+/// [File](crate::File) inside the VFS.
+///
+/// ```rust
+///  use cnfs::{mount, umount, exists, FatFileSystem, MountOptions, Path};
+///  use std::sync::Arc;
+///  let image = std::fs::read("tests/resources/fat_1.img").unwrap();
+///  let fs = FatFileSystem::open(std::io::Cursor::new(image)).unwrap();
+///  mount(Arc::new(fs), Path::new("/"), MountOptions::new()).unwrap();
+///  assert!(!exists(&Path::new("/cnss")).unwrap());
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct FatFileSystem<T: Read + Write + Seek>
+{
+    volume: Arc<FatVolume<T>>,
+}
+
+impl<T: Read + Write + Seek + Send + 'static> FatFileSystem<T>
+{
+    /// Open the FAT filesystem on the storage
+    pub fn open(storage: T) -> CNFSResult<Self>
+    {
+        Ok(Self { volume: Arc::new(FatVolume::open(storage)?) })
+    }
+
+    /// Returns the volume label
+    pub fn volume_label(&self) -> String
+    {
+        self.volume.fs.shared_access().volume_label()
+    }
+}
+
+impl FatFileSystem<BlockStorage>
+{
+    /// Check if the boot sector read from the start of a volume belongs to FAT
+    ///
+    /// The boot signature and the BIOS parameter block are checked, which tells FAT apart
+    /// from exFAT and NTFS volumes having the same signature.
+    pub fn probe(superblock: &[u8]) -> bool
+    {
+        if superblock.len() < 512 || superblock[510..512] != [0x55, 0xAA]
+            || !matches!(superblock[0], 0xEB | 0xE9)
+        {
+            return false;
+        }
+        let bytes_per_sector = u16::from_le_bytes([superblock[11], superblock[12]]);
+        let sectors_per_cluster = superblock[13];
+        let reserved_sectors = u16::from_le_bytes([superblock[14], superblock[15]]);
+        (512..=4096).contains(&bytes_per_sector) && bytes_per_sector.is_power_of_two()
+            && sectors_per_cluster.is_power_of_two() && reserved_sectors != 0 && superblock[16] != 0
+    }
+}
+
+impl<T: Read + Write + Seek + Send + 'static> FileSystem for FatFileSystem<T>
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(FatInode {
+            volume: self.volume.clone(),
+            path: String::new(),
+            inode_type: InodeType::Dir,
+        })
+    }
+
+    fn sync(&self) -> CNFSResult {
+        self.volume.flush()
+    }
+
+    fn fs_type(&self) -> &str {
+        "vfat"
+    }
+}
+
+/// Builds FAT filesystems from block devices for [mount_by_type](crate::mount_by_type), where
+/// it is registered as `vfat`
+pub struct FatFactory;
+
+impl FileSystemFactory for FatFactory
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::Device(device) => {
+                Ok(Arc::new(FatFileSystem::open(BlockStorage::new(device.clone()))?))
+            }
+            _ => Err(InvalidArgument),
+        }
+    }
+
+    fn probe(&self, superblock: &[u8]) -> bool {
+        FatFileSystem::probe(superblock)
+    }
+}
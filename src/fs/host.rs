@@ -0,0 +1,292 @@
+use crate::error::CNFSError::{AlreadyExisted, InvalidArgument, InvalidPath, IsADirectory, NotADirectory,
+                              PathNotFound};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::{DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn to_inode_type(metadata: &std::fs::Metadata) -> InodeType
+{
+    if metadata.is_dir() { InodeType::Dir } else { InodeType::File }
+}
+
+/// Returns the owner and permission bits of a host file, which are only known on Unix
+#[cfg(unix)]
+fn to_permissions(metadata: &std::fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>)
+{
+    use std::os::unix::fs::MetadataExt;
+    (Some(metadata.uid()), Some(metadata.gid()), Some(metadata.mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn to_permissions(_metadata: &std::fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>)
+{
+    (None, None, None)
+}
+
+/// Returns whether an opened host file is the one at the path
+#[cfg(unix)]
+fn same_file(file: &File, path: &PathBuf) -> std::io::Result<bool>
+{
+    use std::os::unix::fs::MetadataExt;
+    let (opened, current) = (file.metadata()?, std::fs::metadata(path)?);
+    Ok(opened.dev() == current.dev() && opened.ino() == current.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_file: &File, _path: &PathBuf) -> std::io::Result<bool>
+{
+    Ok(true)
+}
+
+/// Returns the last status change time of a host file, which is only known on Unix
+#[cfg(unix)]
+fn to_changed(metadata: &std::fs::Metadata) -> Option<u64>
+{
+    use std::os::unix::fs::MetadataExt;
+    u64::try_from(metadata.ctime()).ok()
+}
+
+#[cfg(not(unix))]
+fn to_changed(_metadata: &std::fs::Metadata) -> Option<u64>
+{
+    None
+}
+
+fn to_secs(time: std::io::Result<SystemTime>) -> Option<u64>
+{
+    time.ok()?.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Inode of a host filesystem
+///
+/// A file keeps its host file handle opened from the first read or write until it is dropped.
+pub struct HostInode
+{
+    root: Arc<PathBuf>,
+    path: PathBuf,
+    inode_type: InodeType,
+    handle: UPCell<Option<File>>,
+}
+
+impl HostInode
+{
+    fn new(root: Arc<PathBuf>, path: PathBuf, inode_type: InodeType, handle: Option<File>) -> Arc<Self>
+    {
+        Arc::new(Self { root, path, inode_type, handle: unsafe { UPCell::new(handle) } })
+    }
+
+    /// Returns the host path of a child, refusing names that would leave this directory
+    fn child_path(&self, name: &str) -> CNFSResult<PathBuf>
+    {
+        if self.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+            return Err(InvalidPath);
+        }
+        Ok(self.path.join(name))
+    }
+
+    /// Returns the host metadata of a child, following symlinks that stay inside the root
+    fn child_metadata(&self, path: &PathBuf) -> CNFSResult<std::fs::Metadata>
+    {
+        let metadata = std::fs::symlink_metadata(path)?;
+        if !metadata.file_type().is_symlink() { return Ok(metadata); }
+        // A dangling symlink or one escaping the root is treated as missing
+        let target = std::fs::canonicalize(path).map_err(|_| PathNotFound)?;
+        if !target.starts_with(self.root.as_path()) { return Err(PathNotFound); }
+        Ok(std::fs::metadata(target)?)
+    }
+
+    /// Returns the host path of the inode with symlinks resolved, which must still be inside
+    /// the root, since they may have changed since the lookup
+    fn real_path(&self) -> CNFSResult<PathBuf>
+    {
+        let path = std::fs::canonicalize(&self.path).map_err(|_| PathNotFound)?;
+        if path.starts_with(self.root.as_path()) { Ok(path) } else { Err(PathNotFound) }
+    }
+
+    /// Run `f` on the cached host file handle, opening it first if needed
+    fn with_handle<R>(&self, f: impl FnOnce(&mut File) -> std::io::Result<R>) -> CNFSResult<R>
+    {
+        if self.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        let mut handle = self.handle.exclusive_access();
+        if handle.is_none()
+        {
+            let path = self.real_path()?;
+            let file = match OpenOptions::new().read(true).write(true).open(&path) {
+                Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                    OpenOptions::new().read(true).open(&path)?
+                }
+                file => file?,
+            };
+            // A directory on the way may have been swapped for a symlink while opening
+            if !same_file(&file, &self.real_path()?)? { return Err(PathNotFound); }
+            *handle = Some(file);
+        }
+        Ok(f(handle.as_mut().unwrap())?)
+    }
+}
+
+impl Inode for HostInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        self.with_handle(|file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read(buffer)
+        })
+    }
+
+    fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        self.with_handle(|file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write(buffer)
+        })
+    }
+
+    fn sync(&self) -> CNFSResult {
+        if self.inode_type == InodeType::Dir { return Ok(()); }
+        match &*self.handle.shared_access() {
+            Some(file) => Ok(file.sync_data()?),
+            None => Ok(()),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        let path = self.child_path(name)?;
+        let metadata = self.child_metadata(&path)?;
+        Ok(HostInode::new(self.root.clone(), path, to_inode_type(&metadata), None))
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType) -> CNFSResult<InodeRef> {
+        let path = self.child_path(name)?;
+        let handle = match inode_type {
+            InodeType::Dir => {
+                std::fs::create_dir(&path)?;
+                None
+            }
+            InodeType::File => {
+                Some(OpenOptions::new().read(true).write(true).create_new(true).open(&path)?)
+            }
+            _ => return Err(InvalidArgument),
+        };
+        Ok(HostInode::new(self.root.clone(), path, inode_type, handle))
+    }
+
+    fn remove(&self, name: &str) -> CNFSResult {
+        let path = self.child_path(name)?;
+        // A symlink is removed itself, never its target
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            Ok(std::fs::remove_dir(&path)?)
+        } else {
+            Ok(std::fs::remove_file(&path)?)
+        }
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> CNFSResult {
+        let (old_path, new_path) = (self.child_path(old_name)?, self.child_path(new_name)?);
+        // The host would silently replace an existing target
+        if std::fs::symlink_metadata(&new_path).is_ok() { return Err(AlreadyExisted); }
+        Ok(std::fs::rename(old_path, new_path)?)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let metadata = std::fs::metadata(self.real_path()?)?;
+        let (uid, gid, mode) = to_permissions(&metadata);
+        Ok(Metadata {
+            inode_type: to_inode_type(&metadata),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            accessed: to_secs(metadata.accessed()),
+            modified: to_secs(metadata.modified()),
+            created: to_secs(metadata.created()),
+            changed: to_changed(&metadata),
+            uid,
+            gid,
+            mode,
+        })
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, mode: u32) -> CNFSResult {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(std::fs::set_permissions(self.real_path()?, std::fs::Permissions::from_mode(mode & 0o7777))?)
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult {
+        Ok(std::os::unix::fs::chown(self.real_path()?, Some(uid), Some(gid))?)
+    }
+
+    fn set_modified(&self, modified: u64) -> CNFSResult {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(modified);
+        Ok(File::open(self.real_path()?)?.set_modified(time)?)
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        if self.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(self.real_path()?)?
+        {
+            let entry = entry?;
+            // Names that are not UTF-8 can't be looked up, so they are left out
+            let Ok(name) = entry.file_name().into_string() else { continue };
+            match self.child_metadata(&entry.path()) {
+                Ok(metadata) => entries.push(DirEntry { name, inode_type: to_inode_type(&metadata) }),
+                Err(PathNotFound) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Host filesystem rooted at a host directory, available with the `std` feature
+///
+/// Paths can't leave the root directory, neither by `..` nor by following symlinks.
+///
+/// ```rust
+///  use cnfs::{mount, umount, exists, HostFs, MountOptions, Path};
+///  use std::sync::Arc;
+///  let fs = HostFs::new("tests").unwrap();
+///  mount(Arc::new(fs), Path::new("/"), MountOptions::new()).unwrap();
+///  assert!(exists(&Path::new("/resources/fat_1.img")).unwrap());
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct HostFs
+{
+    root: Arc<PathBuf>,
+    name: String,
+}
+
+impl HostFs
+{
+    /// New a host filesystem rooted at the host directory
+    pub fn new(root: impl Into<PathBuf>) -> CNFSResult<Self>
+    {
+        let root = std::fs::canonicalize(root.into())?;
+        if !root.is_dir() { return Err(InvalidArgument); }
+        let name = root.to_string_lossy().into();
+        Ok(Self { root: Arc::new(root), name })
+    }
+}
+
+impl FileSystem for HostFs
+{
+    fn root_inode(&self) -> InodeRef {
+        HostInode::new(self.root.clone(), self.root.as_ref().clone(), InodeType::Dir, None)
+    }
+
+    fn fs_type(&self) -> &str {
+        "hostfs"
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
@@ -0,0 +1,25 @@
+mod dev;
+mod ext2;
+#[cfg(feature = "fatfs")]
+mod fat;
+#[cfg(feature = "std")]
+mod host;
+mod overlay;
+mod proc;
+mod ramfs;
+mod tar;
+mod zip;
+
+pub use dev::{register_device, unregister_device, DevFs, Device};
+pub use ext2::{Ext2Factory, Ext2FileSystem};
+#[cfg(feature = "fatfs")]
+pub use fat::{FatFactory, FatFileSystem};
+#[cfg(feature = "std")]
+pub use host::HostFs;
+pub use overlay::OverlayFs;
+pub use proc::ProcFs;
+pub use ramfs::RamFs;
+pub use tar::{TarFactory, TarFs};
+#[cfg(feature = "std")]
+pub(crate) use tar::{components as tar_components, TarParser, BLOCK_SIZE as TAR_BLOCK_SIZE};
+pub use zip::{ZipFactory, ZipFs};
@@ -0,0 +1,399 @@
+use crate::error::CNFSError::{AlreadyExisted, DirectoryNotEmpty, InvalidPath, IsADirectory,
+                              NoSpace, NotADirectory, NotImplemented, PathNotFound};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::{DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Prefix of the upper layer files hiding a lower entry of the same name
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Upper layer file marking a directory that hides the lower directory of the same name
+const OPAQUE: &str = ".wh..wh..opq";
+/// Size of the chunks a file is copied up in
+const COPY_CHUNK_SIZE: usize = 4096;
+
+fn whiteout(name: &str) -> String
+{
+    format!("{WHITEOUT_PREFIX}{name}")
+}
+
+/// Look up a name, returning None if it doesn't exist
+fn find(dir: &InodeRef, name: &str) -> CNFSResult<Option<InodeRef>>
+{
+    match dir.lookup(name) {
+        Ok(inode) => Ok(Some(inode)),
+        Err(PathNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Copy the whole data of a file
+fn copy_data(from: &InodeRef, to: &InodeRef) -> CNFSResult
+{
+    let mut buffer = vec![0_u8; COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    loop
+    {
+        let len = from.read(offset, &mut buffer)?;
+        if len == 0 { return Ok(()); }
+        let mut written = 0;
+        while written < len
+        {
+            match to.write(offset + written as u64, &buffer[written..len])? {
+                0 => return Err(NoSpace),
+                bytes => written += bytes,
+            }
+        }
+        offset += len as u64;
+    }
+}
+
+/// Copy the owner and permission bits of an inode, if both layers keep them
+fn copy_permissions(from: &InodeRef, to: &InodeRef) -> CNFSResult
+{
+    let metadata = from.metadata()?;
+    let copied = match (metadata.uid, metadata.gid, metadata.mode) {
+        (Some(uid), Some(gid), Some(mode)) => to.set_owner(uid, gid).and_then(|_| to.set_mode(mode)),
+        _ => Ok(()),
+    };
+    match copied {
+        Err(NotImplemented) => Ok(()),
+        result => result,
+    }
+}
+
+/// Copy the extended attributes of an inode, if both layers keep them
+fn copy_xattrs(from: &InodeRef, to: &InodeRef) -> CNFSResult
+{
+    let names = match from.list_xattr() {
+        Err(NotImplemented) => return Ok(()),
+        names => names?,
+    };
+    for name in names
+    {
+        match to.set_xattr(&name, &from.get_xattr(&name)?) {
+            Err(NotImplemented) => return Ok(()),
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Inode of an overlay filesystem
+///
+/// It is the merge of the inodes of the same path in both layers. The upper one is only made
+/// when the inode is first modified, by copying the lower one up with its parent directories.
+pub struct OverlayInode
+{
+    this: Weak<OverlayInode>,
+    parent: Option<Arc<OverlayInode>>,
+    name: String,
+    inode_type: InodeType,
+    lower: Option<InodeRef>,
+    upper: UPCell<Option<InodeRef>>,
+}
+
+impl OverlayInode
+{
+    fn new(parent: Option<Arc<OverlayInode>>, name: &str, inode_type: InodeType, lower: Option<InodeRef>,
+           upper: Option<InodeRef>) -> Arc<Self>
+    {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent,
+            name: name.into(),
+            inode_type,
+            lower,
+            upper: unsafe { UPCell::new(upper) },
+        })
+    }
+
+    /// Returns the upper inode, looking it up in case it was copied up through another inode
+    fn upper(&self) -> CNFSResult<Option<InodeRef>>
+    {
+        if let Some(upper) = &*self.upper.shared_access() { return Ok(Some(upper.clone())); }
+        let Some(parent) = &self.parent else { return Ok(None) };
+        let Some(dir) = parent.upper()? else { return Ok(None) };
+        let upper = find(&dir, &self.name)?;
+        *self.upper.exclusive_access() = upper.clone();
+        Ok(upper)
+    }
+
+    /// Returns the upper inode, copying the lower one up first if needed
+    fn copy_up(&self) -> CNFSResult<InodeRef>
+    {
+        if let Some(upper) = self.upper()? { return Ok(upper); }
+        let parent = self.parent.as_ref().expect("The root of an overlay is in the upper layer");
+        let dir = parent.copy_up()?;
+        let upper = dir.create(&self.name, self.inode_type)?;
+        if let Some(lower) = &self.lower
+        {
+            let copied = match self.inode_type {
+                InodeType::File => copy_data(lower, &upper),
+                _ => Ok(()),
+            }.and_then(|_| copy_permissions(lower, &upper)).and_then(|_| copy_xattrs(lower, &upper));
+            if let Err(err) = copied
+            {
+                let _ = dir.remove(&self.name);
+                return Err(err);
+            }
+        }
+        *self.upper.exclusive_access() = Some(upper.clone());
+        Ok(upper)
+    }
+
+    /// Look up a child in both layers
+    fn child(&self, name: &str) -> CNFSResult<Arc<OverlayInode>>
+    {
+        if self.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        if name.starts_with(WHITEOUT_PREFIX) { return Err(PathNotFound); }
+        let mut upper = None;
+        let mut hide_lower = false;
+        if let Some(dir) = self.upper()?
+        {
+            upper = find(&dir, name)?;
+            hide_lower = find(&dir, &whiteout(name))?.is_some() || find(&dir, OPAQUE)?.is_some();
+        }
+        let lower = match &self.lower {
+            Some(dir) if !hide_lower => find(dir, name)?,
+            _ => None,
+        };
+        let (inode_type, lower) = match (&upper, lower) {
+            (Some(upper), lower) => {
+                let inode_type = upper.metadata()?.inode_type;
+                // Only directories are merged, an upper file hides anything below it
+                let lower = match lower {
+                    Some(lower) if inode_type == InodeType::Dir
+                        && lower.metadata()?.inode_type == InodeType::Dir => Some(lower),
+                    _ => None,
+                };
+                (inode_type, lower)
+            }
+            (None, Some(lower)) => (lower.metadata()?.inode_type, Some(lower)),
+            (None, None) => return Err(PathNotFound),
+        };
+        Ok(OverlayInode::new(self.this.upgrade(), name, inode_type, lower, upper))
+    }
+}
+
+impl Inode for OverlayInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        if self.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        match (self.upper()?, &self.lower) {
+            (Some(upper), _) => upper.read(offset, buffer),
+            (None, Some(lower)) => lower.read(offset, buffer),
+            (None, None) => Err(PathNotFound),
+        }
+    }
+
+    fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        if self.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        self.copy_up()?.write(offset, buffer)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        match self.upper()? {
+            Some(upper) => upper.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        Ok(self.child(name)?)
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType) -> CNFSResult<InodeRef> {
+        if name.starts_with(WHITEOUT_PREFIX) { return Err(InvalidPath); }
+        match self.child(name) {
+            Ok(_) => return Err(AlreadyExisted),
+            Err(PathNotFound) => {}
+            Err(err) => return Err(err),
+        }
+        let dir = self.copy_up()?;
+        let whiteout = whiteout(name);
+        let whited_out = find(&dir, &whiteout)?.is_some();
+        let upper = dir.create(name, inode_type)?;
+        if whited_out
+        {
+            // A new directory replacing a removed one must not show the lower content
+            if inode_type == InodeType::Dir { upper.create(OPAQUE, InodeType::File)?; }
+            dir.remove(&whiteout)?;
+        }
+        Ok(OverlayInode::new(self.this.upgrade(), name, inode_type, None, Some(upper)))
+    }
+
+    fn remove(&self, name: &str) -> CNFSResult {
+        let child = self.child(name)?;
+        if child.inode_type == InodeType::Dir && !child.read_dir()?.is_empty()
+        {
+            return Err(DirectoryNotEmpty);
+        }
+        let dir = self.copy_up()?;
+        if let Some(upper) = child.upper()?
+        {
+            if child.inode_type == InodeType::Dir
+            {
+                // Only whiteouts are left in it
+                for entry in upper.read_dir()?
+                {
+                    upper.remove(&entry.name)?;
+                }
+            }
+            dir.remove(name)?;
+        }
+        if child.lower.is_some()
+        {
+            dir.create(&whiteout(name), InodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        match (self.upper()?, &self.lower) {
+            (Some(upper), _) => upper.metadata(),
+            (None, Some(lower)) => lower.metadata(),
+            (None, None) => Err(PathNotFound),
+        }
+    }
+
+    fn set_modified(&self, modified: u64) -> CNFSResult {
+        self.copy_up()?.set_modified(modified)
+    }
+
+    fn set_mode(&self, mode: u32) -> CNFSResult {
+        self.copy_up()?.set_mode(mode)
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult {
+        self.copy_up()?.set_owner(uid, gid)
+    }
+
+    fn get_xattr(&self, name: &str) -> CNFSResult<Vec<u8>> {
+        match (self.upper()?, &self.lower) {
+            (Some(upper), _) => upper.get_xattr(name),
+            (None, Some(lower)) => lower.get_xattr(name),
+            (None, None) => Err(PathNotFound),
+        }
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8]) -> CNFSResult {
+        self.copy_up()?.set_xattr(name, value)
+    }
+
+    fn list_xattr(&self) -> CNFSResult<Vec<String>> {
+        match (self.upper()?, &self.lower) {
+            (Some(upper), _) => upper.list_xattr(),
+            (None, Some(lower)) => lower.list_xattr(),
+            (None, None) => Err(PathNotFound),
+        }
+    }
+
+    fn remove_xattr(&self, name: &str) -> CNFSResult {
+        self.copy_up()?.remove_xattr(name)
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        if self.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        let mut entries = BTreeMap::new();
+        let mut whiteouts = BTreeSet::new();
+        let mut opaque = false;
+        if let Some(upper) = self.upper()?
+        {
+            for entry in upper.read_dir()?
+            {
+                if entry.name == OPAQUE {
+                    opaque = true;
+                } else if let Some(name) = entry.name.strip_prefix(WHITEOUT_PREFIX) {
+                    whiteouts.insert(String::from(name));
+                } else {
+                    entries.insert(entry.name, entry.inode_type);
+                }
+            }
+        }
+        if let (Some(lower), false) = (&self.lower, opaque)
+        {
+            for entry in lower.read_dir()?
+            {
+                if !whiteouts.contains(&entry.name)
+                {
+                    entries.entry(entry.name).or_insert(entry.inode_type);
+                }
+            }
+        }
+        Ok(entries.into_iter().map(|(name, inode_type)| DirEntry { name, inode_type }).collect())
+    }
+}
+
+/// Overlay filesystem
+///
+/// It shows a writable upper filesystem over a lower one that is never modified. Lookups fall
+/// through to the lower layer, the first write to a lower file copies it up, and removing a
+/// lower entry leaves a whiteout in the upper layer. Whiteouts are files named `.wh.<name>`
+/// and a directory holding `.wh..wh..opq` hides the lower directory of the same name, so any
+/// filesystem can be the upper layer, and those names can't be used in the overlay.
+///
+/// ```rust
+///  use cnfs::{mount, read_to_end, umount, write_all, MountOptions, OverlayFs, Path, RamFs};
+///  use std::sync::Arc;
+///  let lower = Arc::new(RamFs::new());
+///  mount(lower.clone(), Path::new("/"), MountOptions::new()).unwrap();
+///  write_all(&Path::new("/cnss"), b"cnss").unwrap();
+///  umount(Path::new("/")).unwrap();
+///
+///  mount(Arc::new(OverlayFs::new(lower.clone(), Arc::new(RamFs::new()))), Path::new("/"),
+///        MountOptions::new()).unwrap();
+///  write_all(&Path::new("/cnss"), b"cnss{overlay}").unwrap();
+///  assert_eq!(read_to_end(&Path::new("/cnss")).unwrap(), b"cnss{overlay}");
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct OverlayFs
+{
+    lower: Arc<dyn FileSystem>,
+    upper: Arc<dyn FileSystem>,
+    root: Arc<OverlayInode>,
+}
+
+impl OverlayFs
+{
+    /// New an overlay of the upper filesystem on the lower one
+    pub fn new(lower: Arc<dyn FileSystem>, upper: Arc<dyn FileSystem>) -> Self
+    {
+        let root = OverlayInode::new(None, "", InodeType::Dir, Some(lower.root_inode()),
+                                     Some(upper.root_inode()));
+        Self { lower, upper, root }
+    }
+
+    /// Returns the lower filesystem
+    pub fn lower(&self) -> &Arc<dyn FileSystem>
+    {
+        &self.lower
+    }
+
+    /// Returns the upper filesystem
+    pub fn upper(&self) -> &Arc<dyn FileSystem>
+    {
+        &self.upper
+    }
+}
+
+impl FileSystem for OverlayFs
+{
+    fn root_inode(&self) -> InodeRef {
+        self.root.clone()
+    }
+
+    fn sync(&self) -> CNFSResult {
+        self.upper.sync()
+    }
+
+    fn fs_type(&self) -> &str {
+        "overlay"
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::config;
+use crate::error::CNFSError::{IsADirectory, NotADirectory, PathNotFound, ReadOnly};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::usrlyr::{FileMode, OPEN_FILES};
+use crate::vfs::{escape, proc_mounts, Dentry, DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata, DCACHE,
+                 DCACHE_STATS};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::fmt::Write;
+
+/// Files of a procfs
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ProcFile
+{
+    Dcache,
+    Mounts,
+    OpenFiles,
+    PageCache,
+}
+
+/// Names of the files, in the order they are listed
+const FILES: [(&str, ProcFile); 4] = [
+    ("dcache", ProcFile::Dcache),
+    ("mounts", ProcFile::Mounts),
+    ("open_files", ProcFile::OpenFiles),
+    ("pagecache", ProcFile::PageCache),
+];
+
+impl ProcFile
+{
+    /// Render the current state
+    fn generate(self) -> String
+    {
+        match self {
+            ProcFile::Dcache => dcache(),
+            ProcFile::Mounts => proc_mounts(),
+            ProcFile::OpenFiles => open_files(),
+            ProcFile::PageCache => page_cache(),
+        }
+    }
+}
+
+fn dcache() -> String
+{
+    let size: usize = DCACHE.shared_access().values().map(|v| v.len()).sum();
+    let stats = *DCACHE_STATS.shared_access();
+    let mut ret = String::new();
+    let _ = writeln!(ret, "size {size}");
+    let _ = writeln!(ret, "limit {}", config().dcache_size);
+    let _ = writeln!(ret, "hits {}", stats.hits);
+    let _ = writeln!(ret, "misses {}", stats.misses);
+    ret
+}
+
+fn open_files() -> String
+{
+    let mut ret = String::new();
+    for file in OPEN_FILES.shared_access().iter()
+    {
+        let mode = match (file.mode.contains(FileMode::read), file.mode.contains(FileMode::write)) {
+            (true, true) => "rw",
+            (false, true) => "w",
+            _ => "r",
+        };
+        let _ = writeln!(ret, "{} {} {}", escape(&file.dentry.path.to_string()), *file.offset.shared_access(), mode);
+    }
+    ret
+}
+
+fn page_cache() -> String
+{
+    let mut dentries: Vec<Arc<Dentry>> = Vec::new();
+    let cached = DCACHE.shared_access().values().flatten().cloned().collect::<Vec<_>>();
+    let opened = OPEN_FILES.shared_access().iter().map(|f| f.dentry.clone()).collect::<Vec<_>>();
+    for dentry in cached.into_iter().chain(opened)
+    {
+        if !dentries.iter().any(|d| Arc::ptr_eq(&d.inode.0, &dentry.inode.0)) { dentries.push(dentry); }
+    }
+    dentries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut ret = String::new();
+    for dentry in dentries
+    {
+        // An inode being read or written right now is skipped
+        let Some(inode) = dentry.inode.0.try_shared_access() else { continue };
+        let (pages, dirty, bytes) = inode.cache_usage();
+        if pages != 0
+        {
+            let _ = writeln!(ret, "{} {pages} {dirty} {bytes}", escape(&dentry.path.to_string()));
+        }
+    }
+    ret
+}
+
+/// Inode of a procfs, the root directory or one of its files
+pub struct ProcInode
+{
+    file: Option<ProcFile>,
+    /// Contents generated by the last read from the start, which the following reads continue
+    snapshot: UPCell<Option<String>>,
+}
+
+impl ProcInode
+{
+    fn new(file: Option<ProcFile>) -> Self
+    {
+        Self { file, snapshot: unsafe { UPCell::new(None) } }
+    }
+}
+
+impl Inode for ProcInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        let file = self.file.ok_or(IsADirectory)?;
+        let mut snapshot = self.snapshot.exclusive_access();
+        if offset == 0 || snapshot.is_none() { *snapshot = Some(file.generate()); }
+        let data = snapshot.as_ref().unwrap();
+        if offset >= data.len() as u64 { return Ok(0); }
+        let len = min(buffer.len(), data.len() - offset as usize);
+        buffer[..len].copy_from_slice(&data.as_bytes()[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+
+    fn write(&self, _offset: u64, _buffer: &[u8]) -> CNFSResult<usize> {
+        Err(ReadOnly)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        if self.file.is_some() { return Err(NotADirectory); }
+        let &(_, file) = FILES.iter().find(|(n, _)| *n == name).ok_or(PathNotFound)?;
+        Ok(Arc::new(ProcInode::new(Some(file))))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType) -> CNFSResult<InodeRef> {
+        Err(ReadOnly)
+    }
+
+    fn remove(&self, _name: &str) -> CNFSResult {
+        Err(ReadOnly)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        Ok(match self.file {
+            Some(file) => Metadata::new(InodeType::File, file.generate().len() as u64).with_permissions(0, 0, 0o444),
+            None => Metadata::new(InodeType::Dir, 0).with_permissions(0, 0, 0o555),
+        })
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        if self.file.is_some() { return Err(NotADirectory); }
+        Ok(FILES.iter().map(|(name, _)| DirEntry { name: String::from(*name), inode_type: InodeType::File }).collect())
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Read-only filesystem of files describing the state of the VFS
+///
+/// The contents are generated when a file is read from the start, so they never go through
+/// the page cache, and reads further on continue from the same snapshot. It holds:
+///
+/// - `mounts`: the mount table, like [proc_mounts]
+/// - `dcache`: the number of cached dentries, the cache limit, and the lookups that hit or
+///   missed the cache, one `name value` pair per line
+/// - `pagecache`: one `path pages dirty bytes` line per inode with cached pages
+/// - `open_files`: one `path offset mode` line per opened [File](crate::File), where the
+///   mode is `r`, `w` or `rw`
+///
+/// Spaces and other separators in paths are escaped like in `/proc/mounts`.
+///
+/// ```rust
+///  use cnfs::{create_directory, mount, read_to_end, umount, MountOptions, Path, ProcFs, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  create_directory(&Path::new("/proc")).unwrap();
+///  mount(Arc::new(ProcFs::new()), Path::new("/proc"), MountOptions::new()).unwrap();
+///  let mounts = read_to_end(&Path::new("/proc/mounts")).unwrap();
+///  assert!(String::from_utf8(mounts).unwrap().ends_with("proc /proc proc rw 0 0\n"));
+///  umount(Path::new("/proc")).unwrap();
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+#[derive(Default)]
+pub struct ProcFs;
+
+impl ProcFs
+{
+    /// New a procfs
+    pub fn new() -> Self
+    {
+        Self
+    }
+}
+
+impl FileSystem for ProcFs
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(ProcInode::new(None))
+    }
+
+    fn fs_type(&self) -> &str {
+        "proc"
+    }
+}
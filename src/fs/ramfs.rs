@@ -0,0 +1,387 @@
+use crate::error::CNFSError::{AlreadyExisted, DirectoryNotEmpty, InvalidArgument, IsADirectory, NoAttribute, NoSpace,
+                              NotADirectory, PathNotFound};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::{now, DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Space used by a ramfs and its limits
+struct Usage
+{
+    max_bytes: Option<usize>,
+    max_inodes: Option<usize>,
+    bytes: UPCell<usize>,
+    inodes: UPCell<usize>,
+}
+
+impl Usage
+{
+    /// Reserve an inode
+    fn alloc_inode(&self) -> CNFSResult
+    {
+        let mut inodes = self.inodes.exclusive_access();
+        if self.max_inodes.is_some_and(|max| *inodes >= max) { return Err(NoSpace); }
+        *inodes += 1;
+        Ok(())
+    }
+
+    /// Reserve up to `bytes` bytes, returning how many bytes were reserved
+    fn alloc_bytes(&self, bytes: usize) -> usize
+    {
+        let mut used = self.bytes.exclusive_access();
+        let reserved = match self.max_bytes {
+            Some(max) => min(bytes, max.saturating_sub(*used)),
+            None => bytes,
+        };
+        *used += reserved;
+        reserved
+    }
+
+    fn free_bytes(&self, bytes: usize)
+    {
+        let mut used = self.bytes.exclusive_access();
+        *used = used.saturating_sub(bytes);
+    }
+
+    fn free_inode(&self)
+    {
+        let mut inodes = self.inodes.exclusive_access();
+        *inodes = inodes.saturating_sub(1);
+    }
+}
+
+/// Timestamps of an inode, in seconds since the Unix epoch
+#[derive(Clone, Copy)]
+struct RamTimes
+{
+    accessed: u64,
+    modified: u64,
+    created: u64,
+    changed: u64,
+}
+
+enum RamData
+{
+    File(Vec<u8>),
+    Dir(BTreeMap<String, Arc<RamInode>>),
+}
+
+/// Inode of a ramfs
+///
+/// The space it takes is given back when the last reference is dropped, so a removed file
+/// keeps its data while it is still opened.
+pub struct RamInode
+{
+    usage: Arc<Usage>,
+    data: UPCell<RamData>,
+    /// Permission bits
+    mode: UPCell<u32>,
+    /// User and group IDs of the owner
+    owner: UPCell<(u32, u32)>,
+    /// Extended attributes
+    xattrs: UPCell<BTreeMap<String, Vec<u8>>>,
+    times: UPCell<RamTimes>,
+}
+
+impl RamInode
+{
+    fn inode_type(&self) -> InodeType
+    {
+        match &*self.data.shared_access() {
+            RamData::File(_) => InodeType::File,
+            RamData::Dir(_) => InodeType::Dir,
+        }
+    }
+
+    fn new(usage: Arc<Usage>, inode_type: InodeType) -> CNFSResult<Arc<Self>>
+    {
+        let data = match inode_type {
+            InodeType::Dir => RamData::Dir(BTreeMap::new()),
+            InodeType::File => RamData::File(Vec::new()),
+            // Devices and FIFOs are kept by the VFS, and nothing else is stored here
+            _ => return Err(InvalidArgument),
+        };
+        usage.alloc_inode()?;
+        Ok(Arc::new(Self::with_data(usage, data)))
+    }
+
+    fn with_data(usage: Arc<Usage>, data: RamData) -> Self
+    {
+        let mode = if matches!(data, RamData::Dir(_)) { 0o755 } else { 0o644 };
+        let time = now();
+        Self {
+            usage,
+            data: unsafe { UPCell::new(data) },
+            mode: unsafe { UPCell::new(mode) },
+            owner: unsafe { UPCell::new((0, 0)) },
+            xattrs: unsafe { UPCell::new(BTreeMap::new()) },
+            times: unsafe { UPCell::new(RamTimes { accessed: time, modified: time, created: time, changed: time }) },
+        }
+    }
+
+    fn touch_accessed(&self)
+    {
+        self.times.exclusive_access().accessed = now();
+    }
+
+    /// Update the times after the data changed
+    fn touch_modified(&self)
+    {
+        let mut times = self.times.exclusive_access();
+        times.modified = now();
+        times.changed = times.modified;
+    }
+
+    /// Update the time after the metadata changed
+    fn touch_changed(&self)
+    {
+        self.times.exclusive_access().changed = now();
+    }
+}
+
+impl Inode for RamInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        match &*self.data.shared_access() {
+            RamData::File(content) => {
+                if offset >= content.len() as u64 { return Ok(0); }
+                let offset = offset as usize;
+                let bytes = min(buffer.len(), content.len() - offset);
+                buffer[..bytes].copy_from_slice(&content[offset..offset + bytes]);
+                self.touch_accessed();
+                Ok(bytes)
+            }
+            RamData::Dir(_) => Err(IsADirectory),
+        }
+    }
+
+    fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        match &mut *self.data.exclusive_access() {
+            RamData::File(content) => {
+                let offset = usize::try_from(offset).map_err(|_| NoSpace)?;
+                let end = offset.checked_add(buffer.len()).ok_or(NoSpace)?;
+                let bytes = if end > content.len() {
+                    let grown = self.usage.alloc_bytes(end - content.len());
+                    let new_len = content.len() + grown;
+                    if new_len <= offset {
+                        self.usage.free_bytes(grown);
+                        return if buffer.is_empty() { Ok(0) } else { Err(NoSpace) };
+                    }
+                    content.resize(new_len, 0);
+                    new_len - offset
+                } else {
+                    buffer.len()
+                };
+                content[offset..offset + bytes].copy_from_slice(&buffer[..bytes]);
+                self.touch_modified();
+                Ok(bytes)
+            }
+            RamData::Dir(_) => Err(IsADirectory),
+        }
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        match &*self.data.shared_access() {
+            RamData::Dir(entries) => {
+                entries.get(name).map(|i| i.clone() as InodeRef).ok_or(PathNotFound)
+            }
+            RamData::File(_) => Err(NotADirectory),
+        }
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType) -> CNFSResult<InodeRef> {
+        match &mut *self.data.exclusive_access() {
+            RamData::Dir(entries) => {
+                if entries.contains_key(name) { return Err(AlreadyExisted); }
+                let inode = RamInode::new(self.usage.clone(), inode_type)?;
+                entries.insert(name.into(), inode.clone());
+                self.touch_modified();
+                Ok(inode)
+            }
+            RamData::File(_) => Err(NotADirectory),
+        }
+    }
+
+    fn remove(&self, name: &str) -> CNFSResult {
+        match &mut *self.data.exclusive_access() {
+            RamData::Dir(entries) => {
+                let inode = entries.get(name).ok_or(PathNotFound)?;
+                if let RamData::Dir(children) = &*inode.data.shared_access() {
+                    if !children.is_empty() { return Err(DirectoryNotEmpty); }
+                }
+                entries.remove(name);
+                self.touch_modified();
+                Ok(())
+            }
+            RamData::File(_) => Err(NotADirectory),
+        }
+    }
+
+    fn rename(&self, old_name: &str, new_name: &str) -> CNFSResult {
+        match &mut *self.data.exclusive_access() {
+            RamData::Dir(entries) => {
+                if entries.contains_key(new_name) { return Err(AlreadyExisted); }
+                let inode = entries.remove(old_name).ok_or(PathNotFound)?;
+                entries.insert(new_name.into(), inode);
+                self.touch_modified();
+                Ok(())
+            }
+            RamData::File(_) => Err(NotADirectory),
+        }
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let metadata = match &*self.data.shared_access() {
+            RamData::File(content) => Metadata::new(InodeType::File, content.len() as u64),
+            RamData::Dir(_) => Metadata::new(InodeType::Dir, 0),
+        };
+        let (uid, gid) = *self.owner.shared_access();
+        let times = *self.times.shared_access();
+        Ok(Metadata {
+            accessed: Some(times.accessed),
+            modified: Some(times.modified),
+            created: Some(times.created),
+            changed: Some(times.changed),
+            ..metadata.with_permissions(uid, gid, *self.mode.shared_access())
+        })
+    }
+
+    fn set_mode(&self, mode: u32) -> CNFSResult {
+        *self.mode.exclusive_access() = mode & 0o7777;
+        self.touch_changed();
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult {
+        *self.owner.exclusive_access() = (uid, gid);
+        self.touch_changed();
+        Ok(())
+    }
+
+    fn set_modified(&self, modified: u64) -> CNFSResult {
+        self.touch_changed();
+        self.times.exclusive_access().modified = modified;
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> CNFSResult<Vec<u8>> {
+        self.xattrs.shared_access().get(name).cloned().ok_or(NoAttribute)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8]) -> CNFSResult {
+        self.xattrs.exclusive_access().insert(name.into(), value.into());
+        self.touch_changed();
+        Ok(())
+    }
+
+    fn list_xattr(&self) -> CNFSResult<Vec<String>> {
+        Ok(self.xattrs.shared_access().keys().cloned().collect())
+    }
+
+    fn remove_xattr(&self, name: &str) -> CNFSResult {
+        self.xattrs.exclusive_access().remove(name).ok_or(NoAttribute)?;
+        self.touch_changed();
+        Ok(())
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        match &*self.data.shared_access() {
+            RamData::Dir(entries) => {
+                self.touch_accessed();
+                Ok(entries.iter().map(|(name, inode)| {
+                    DirEntry { name: name.clone(), inode_type: inode.inode_type() }
+                }).collect())
+            }
+            RamData::File(_) => Err(NotADirectory),
+        }
+    }
+}
+
+impl Drop for RamInode
+{
+    fn drop(&mut self) {
+        if let RamData::File(content) = &*self.data.shared_access() {
+            self.usage.free_bytes(content.len());
+        }
+        self.usage.free_inode();
+    }
+}
+
+/// In-memory filesystem
+///
+/// ```rust
+///  use cnfs::{mount, read_to_end, umount, write_all, MountOptions, Path, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  write_all(&Path::new("/cnss"), b"cnss").unwrap();
+///  assert_eq!(read_to_end(&Path::new("/cnss")).unwrap(), b"cnss");
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct RamFs
+{
+    root: Arc<RamInode>,
+}
+
+impl RamFs
+{
+    /// New an unlimited ramfs
+    pub fn new() -> Self
+    {
+        Self::with_limits(None, None)
+    }
+
+    /// New a ramfs holding at most `max_bytes` bytes of file data and `max_inodes` inodes,
+    /// including the root directory.
+    pub fn with_limits(max_bytes: Option<usize>, max_inodes: Option<usize>) -> Self
+    {
+        let usage = Arc::new(Usage {
+            max_bytes,
+            max_inodes,
+            bytes: unsafe { UPCell::new(0) },
+            inodes: unsafe { UPCell::new(0) },
+        });
+        // The root directory is always there
+        *usage.inodes.exclusive_access() += 1;
+        let root = Arc::new(RamInode::with_data(usage, RamData::Dir(BTreeMap::new())));
+        Self { root }
+    }
+
+    /// Returns the number of bytes of file data stored
+    pub fn used_bytes(&self) -> usize
+    {
+        *self.root.usage.bytes.shared_access()
+    }
+
+    /// Returns the number of inodes in use, including the root directory
+    pub fn used_inodes(&self) -> usize
+    {
+        *self.root.usage.inodes.shared_access()
+    }
+}
+
+impl Default for RamFs
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for RamFs
+{
+    fn root_inode(&self) -> InodeRef {
+        self.root.clone()
+    }
+
+    fn fs_type(&self) -> &str {
+        "ramfs"
+    }
+}
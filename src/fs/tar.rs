@@ -0,0 +1,467 @@
+use crate::block::ReadAt;
+use crate::error::CNFSError::{FSInternal, InvalidArgument, IsADirectory, NotADirectory, PathNotFound, ReadOnly,
+                              UnknownFileSystem};
+use crate::error::CNFSResult;
+use crate::vfs::{DirEntry, FileSystem, FileSystemFactory, Inode, InodeRef, InodeType, Metadata, MountSource};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::str;
+
+/// Size of a header and of the blocks data is padded to
+pub(crate) const BLOCK_SIZE: u64 = 512;
+/// Offset of the `ustar` magic in a header
+const MAGIC_OFFSET: usize = 257;
+/// Largest extended header or GNU long name that is read
+const MAX_EXTENDED_SIZE: u64 = 1 << 20;
+
+/// Data of an archive entry
+#[derive(Clone)]
+enum TarData
+{
+    /// Bytes stored in the archive
+    Archive { offset: u64, size: u64 },
+    /// Bytes kept in memory, like the target of a symbolic link
+    Inline(Vec<u8>),
+}
+
+impl TarData
+{
+    fn size(&self) -> u64
+    {
+        match self {
+            TarData::Archive { size, .. } => *size,
+            TarData::Inline(data) => data.len() as u64,
+        }
+    }
+}
+
+struct TarNode
+{
+    inode_type: InodeType,
+    modified: Option<u64>,
+    data: TarData,
+    children: BTreeMap<String, usize>,
+}
+
+impl TarNode
+{
+    fn new(inode_type: InodeType, modified: Option<u64>, data: TarData) -> Self
+    {
+        Self { inode_type, modified, data, children: BTreeMap::new() }
+    }
+}
+
+/// Headers of an archive indexed at mount time
+struct TarIndex
+{
+    source: Box<dyn ReadAt>,
+    /// The root is the first node
+    nodes: Vec<TarNode>,
+}
+
+/// Fields of the extended headers preceding an entry
+#[derive(Default)]
+struct Extended
+{
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    modified: Option<u64>,
+}
+
+/// Header of an entry, with the extended headers preceding it applied
+pub(crate) struct TarHeader
+{
+    pub entry_type: u8,
+    pub path: String,
+    pub link: String,
+    /// Size of the data following the header
+    pub size: u64,
+    pub modified: Option<u64>,
+}
+
+impl TarHeader
+{
+    /// Check if it is an extended header, whose data applies to the next entry
+    pub fn is_extended(&self) -> bool
+    {
+        matches!(self.entry_type, b'x' | b'g' | b'L' | b'K')
+    }
+}
+
+/// Parser of the headers of an archive, which keeps the extended headers until the entry
+/// they apply to
+#[derive(Default)]
+pub(crate) struct TarParser
+{
+    extended: Extended,
+}
+
+impl TarParser
+{
+    /// Parse the header block at an offset, returning None at the zero block ending the
+    /// archive
+    pub fn header(&mut self, block: &[u8], offset: u64) -> CNFSResult<Option<TarHeader>>
+    {
+        if block.iter().all(|&b| b == 0) { return Ok(None); }
+        if !checksum_ok(block) { return Err(FSInternal(format!("bad tar header at offset {offset}"))); }
+        let entry_type = block[156];
+        let mut size = number(&block[124..136]).ok_or_else(|| FSInternal(format!("bad tar size at offset {offset}")))?;
+        let name = text(&block[..100]);
+        let link = text(&block[157..257]);
+        let modified = number(&block[136..148]);
+        let mut header = TarHeader { entry_type, path: name, link, size, modified };
+        if header.is_extended()
+        {
+            if size > MAX_EXTENDED_SIZE
+            {
+                return Err(FSInternal(format!("tar extended header at offset {offset} is too large")));
+            }
+            return Ok(Some(header));
+        }
+        let Extended { path, link, size: extended_size, modified } = core::mem::take(&mut self.extended);
+        // A pax size only applies to the data of a file
+        if matches!(entry_type, b'0' | b'\0' | b'7') { size = extended_size.unwrap_or(size); }
+        header.size = size;
+        header.path = path.unwrap_or_else(|| {
+            // Only POSIX ustar headers have a prefix, GNU ones keep other fields there
+            if &block[MAGIC_OFFSET..MAGIC_OFFSET + 6] != b"ustar\0" { return header.path; }
+            match text(&block[345..500]) {
+                prefix if prefix.is_empty() => header.path,
+                prefix => format!("{prefix}/{}", header.path),
+            }
+        });
+        header.link = link.unwrap_or(header.link);
+        header.modified = modified.or(header.modified);
+        Ok(Some(header))
+    }
+
+    /// Keep the data of an extended header for the next entry
+    pub fn extend(&mut self, header: &TarHeader, data: &[u8])
+    {
+        match header.entry_type {
+            b'x' => parse_pax(data, &mut self.extended),
+            b'L' => self.extended.path = Some(text(data)),
+            b'K' => self.extended.link = Some(text(data)),
+            // Global pax headers hold defaults for the whole archive, which are not used
+            _ => {}
+        }
+    }
+}
+
+/// Parse a numeric field, in octal or in the base-256 GNU extension
+fn number(field: &[u8]) -> Option<u64>
+{
+    if field.first().is_some_and(|b| b & 0x80 != 0)
+    {
+        let mut value: u64 = (field[0] & 0x7f) as u64;
+        for &b in &field[1..]
+        {
+            value = value.checked_mul(256)? | b as u64;
+        }
+        return Some(value);
+    }
+    let text = str::from_utf8(field).ok()?.trim_matches(|c| c == '\0' || c == ' ');
+    if text.is_empty() { return Some(0); }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Returns a NUL-terminated field as a string
+fn text(field: &[u8]) -> String
+{
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into()
+}
+
+/// Check the checksum of a header, which sums its bytes with the checksum field as spaces
+fn checksum_ok(header: &[u8]) -> bool
+{
+    let Some(expected) = number(&header[148..156]) else { return false };
+    let sum: u64 = header.iter().enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 }).sum();
+    sum == expected
+}
+
+/// Apply the records of a pax extended header, like `30 mtime=1700000000.123456789\n`
+fn parse_pax(data: &[u8], extended: &mut Extended)
+{
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ')
+    {
+        let Some(len) = str::from_utf8(&rest[..space]).ok().and_then(|s| s.parse::<usize>().ok()) else { return };
+        if len <= space + 1 || len > rest.len() { return; }
+        let record = &rest[space + 1..len];
+        rest = &rest[len..];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        let Some(eq) = record.iter().position(|&b| b == b'=') else { continue };
+        let value = String::from_utf8_lossy(&record[eq + 1..]).into_owned();
+        match &record[..eq] {
+            b"path" => extended.path = Some(value),
+            b"linkpath" => extended.link = Some(value),
+            b"size" => extended.size = value.parse().ok(),
+            b"mtime" => extended.modified = value.split('.').next().and_then(|s| s.parse().ok()),
+            _ => {}
+        }
+    }
+}
+
+/// Split an archive path into its components, returning None if it goes up with `..`
+pub(crate) fn components(path: &str) -> Option<Vec<&str>>
+{
+    let parts: Vec<_> = path.split('/').filter(|s| !s.is_empty() && *s != ".").collect();
+    if parts.contains(&"..") { None } else { Some(parts) }
+}
+
+impl TarIndex
+{
+    /// Read the headers of the whole archive
+    fn new(source: Box<dyn ReadAt>) -> CNFSResult<Self>
+    {
+        let root = TarNode::new(InodeType::Dir, None, TarData::Inline(Vec::new()));
+        let mut index = Self { source, nodes: vec![root] };
+        let size = index.source.size();
+        let mut block = [0_u8; BLOCK_SIZE as usize];
+        let mut offset = 0;
+        let mut parser = TarParser::default();
+        while offset + BLOCK_SIZE <= size
+        {
+            index.read_exact(offset, &mut block)?;
+            let header = match parser.header(&block, offset) {
+                Err(_) if offset == 0 => return Err(UnknownFileSystem),
+                header => header?,
+            };
+            let Some(header) = header else { break };
+            let data_offset = offset + BLOCK_SIZE;
+            if data_offset.checked_add(header.size).is_none_or(|end| end > size)
+            {
+                return Err(FSInternal(format!("tar entry at offset {offset} is truncated")));
+            }
+            offset = header.size.div_ceil(BLOCK_SIZE).checked_mul(BLOCK_SIZE)
+                .and_then(|padded| data_offset.checked_add(padded))
+                .ok_or_else(|| FSInternal(format!("tar entry at offset {offset} is too large")))?;
+            if header.is_extended()
+            {
+                let mut data = vec![0_u8; header.size as usize];
+                index.read_exact(data_offset, &mut data)?;
+                parser.extend(&header, &data);
+                continue;
+            }
+            let TarHeader { entry_type, path, link, size: entry_size, modified } = header;
+            let node = match entry_type {
+                b'0' | b'\0' | b'7' => TarNode::new(InodeType::File, modified,
+                                                    TarData::Archive { offset: data_offset, size: entry_size }),
+                b'1' => {
+                    // A hard link shares the data of an earlier file
+                    let target = components(&link).and_then(|parts| index.find(&parts));
+                    match target.map(|i| &index.nodes[i]) {
+                        Some(target) if target.inode_type == InodeType::File => {
+                            TarNode::new(InodeType::File, modified, target.data.clone())
+                        }
+                        _ => continue,
+                    }
+                }
+                b'2' => TarNode::new(InodeType::Symlink, modified, TarData::Inline(link.into_bytes())),
+                b'5' => TarNode::new(InodeType::Dir, modified, TarData::Inline(Vec::new())),
+                // Devices, FIFOs and unknown types
+                _ => continue,
+            };
+            if let Some(parts) = components(&path) { index.insert(&parts, node); }
+        }
+        Ok(index)
+    }
+
+    fn read_exact(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult
+    {
+        if self.source.read_at(offset, buffer)? < buffer.len()
+        {
+            return Err(FSInternal(format!("tar archive is truncated at offset {offset}")));
+        }
+        Ok(())
+    }
+
+    fn find(&self, parts: &[&str]) -> Option<usize>
+    {
+        parts.iter().try_fold(0, |node, part| self.nodes[node].children.get(*part).copied())
+    }
+
+    /// Put a node at a path, making the missing parent directories and replacing an
+    /// earlier entry of the same path
+    fn insert(&mut self, parts: &[&str], node: TarNode)
+    {
+        let Some((name, parents)) = parts.split_last() else {
+            // The root itself, like `./`
+            if node.inode_type == InodeType::Dir { self.nodes[0].modified = node.modified; }
+            return;
+        };
+        let mut dir = 0;
+        for part in parents
+        {
+            dir = match self.nodes[dir].children.get(*part) {
+                Some(&child) if self.nodes[child].inode_type == InodeType::Dir => child,
+                Some(_) => return,
+                None => {
+                    self.nodes.push(TarNode::new(InodeType::Dir, None, TarData::Inline(Vec::new())));
+                    let child = self.nodes.len() - 1;
+                    self.nodes[dir].children.insert(String::from(*part), child);
+                    child
+                }
+            };
+        }
+        match self.nodes[dir].children.get(*name) {
+            Some(&old) if self.nodes[old].inode_type == InodeType::Dir && node.inode_type == InodeType::Dir => {
+                self.nodes[old].modified = node.modified;
+            }
+            _ => {
+                self.nodes.push(node);
+                let child = self.nodes.len() - 1;
+                self.nodes[dir].children.insert(String::from(*name), child);
+            }
+        }
+    }
+}
+
+/// Inode of a tar filesystem
+pub struct TarInode
+{
+    index: Arc<TarIndex>,
+    node: usize,
+}
+
+impl TarInode
+{
+    fn node(&self) -> &TarNode
+    {
+        &self.index.nodes[self.node]
+    }
+}
+
+impl Inode for TarInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        let node = self.node();
+        if node.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        let size = node.data.size();
+        if offset >= size { return Ok(0); }
+        let len = min(buffer.len() as u64, size - offset) as usize;
+        match &node.data {
+            TarData::Archive { offset: start, .. } => {
+                self.index.read_exact(start + offset, &mut buffer[..len])?;
+            }
+            TarData::Inline(data) => {
+                buffer[..len].copy_from_slice(&data[offset as usize..offset as usize + len]);
+            }
+        }
+        Ok(len)
+    }
+
+    fn write(&self, _offset: u64, _buffer: &[u8]) -> CNFSResult<usize> {
+        Err(ReadOnly)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        let node = self.node();
+        if node.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        let &child = node.children.get(name).ok_or(PathNotFound)?;
+        Ok(Arc::new(TarInode { index: self.index.clone(), node: child }))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType) -> CNFSResult<InodeRef> {
+        Err(ReadOnly)
+    }
+
+    fn remove(&self, _name: &str) -> CNFSResult {
+        Err(ReadOnly)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let node = self.node();
+        let mut metadata = Metadata::new(node.inode_type, node.data.size());
+        metadata.modified = node.modified;
+        Ok(metadata)
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        let node = self.node();
+        if node.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        Ok(node.children.iter()
+            .map(|(name, &child)| DirEntry { name: name.clone(), inode_type: self.index.nodes[child].inode_type })
+            .collect())
+    }
+}
+
+/// Read-only filesystem over a ustar or pax tar archive
+///
+/// The headers are indexed when the filesystem is made, and file data is read straight from
+/// the archive, which can be any [ReadAt] source like bytes in memory or a block device.
+/// Pax and GNU long names, hard links and base-256 sizes are supported. Symbolic links read
+/// as their target, entries with `..` in their path are skipped, and a later entry replaces
+/// an earlier one of the same path.
+///
+/// ```rust
+///  use cnfs::{mount, read_to_end, umount, MountOptions, Path, TarFs};
+///  use std::sync::Arc;
+///  let archive = std::fs::read("tests/resources/bundle.tar").unwrap();
+///  mount(Arc::new(TarFs::new(archive).unwrap()), Path::new("/"), MountOptions::new().read_only(true)).unwrap();
+///  assert_eq!(read_to_end(&Path::new("/version")).unwrap(), b"cnss");
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct TarFs
+{
+    index: Arc<TarIndex>,
+}
+
+impl TarFs
+{
+    /// Index the archive in the source
+    pub fn new<T: ReadAt + 'static>(source: T) -> CNFSResult<Self>
+    {
+        Ok(Self { index: Arc::new(TarIndex::new(Box::new(source))?) })
+    }
+
+    /// Check if the start of a source is a ustar or pax archive
+    pub fn probe(superblock: &[u8]) -> bool
+    {
+        superblock.len() >= BLOCK_SIZE as usize && &superblock[MAGIC_OFFSET..MAGIC_OFFSET + 5] == b"ustar"
+            && checksum_ok(&superblock[..BLOCK_SIZE as usize])
+    }
+}
+
+impl FileSystem for TarFs
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(TarInode { index: self.index.clone(), node: 0 })
+    }
+
+    fn fs_type(&self) -> &str {
+        "tar"
+    }
+}
+
+/// Builds tar filesystems from block devices for [mount_by_type](crate::mount_by_type), where
+/// it is registered as `tar`
+pub struct TarFactory;
+
+impl FileSystemFactory for TarFactory
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::Device(device) => Ok(Arc::new(TarFs::new(device.clone())?)),
+            _ => Err(InvalidArgument),
+        }
+    }
+
+    fn probe(&self, superblock: &[u8]) -> bool {
+        TarFs::probe(superblock)
+    }
+}
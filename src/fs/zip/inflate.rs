@@ -0,0 +1,349 @@
+use crate::block::{crc32_update, ReadAt};
+use crate::error::CNFSError::FSInternal;
+use crate::error::CNFSResult;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+/// Size of the window back references can reach
+const WINDOW_SIZE: usize = 32768;
+/// Size of the chunks compressed data is read in
+const INPUT_CHUNK_SIZE: u64 = 4096;
+/// Longest code in bits
+const MAX_BITS: usize = 15;
+
+/// Base lengths and extra bits of the length symbols from 257
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67,
+                                83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5,
+                                5, 0];
+/// Base distances and extra bits of the distance symbols
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769,
+                                  1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11,
+                                  11, 12, 12, 13, 13];
+/// Order the code length code lengths of a dynamic block are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn corrupted(what: &str) -> crate::error::CNFSError
+{
+    FSInternal(String::from("corrupted deflate stream: ") + what)
+}
+
+/// Canonical Huffman code, decoded bit by bit
+struct Huffman
+{
+    /// Number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman
+{
+    /// Build a code from the code length of every symbol, where 0 means unused
+    fn new(lengths: &[u8]) -> CNFSResult<Self>
+    {
+        let mut counts = [0_u16; MAX_BITS + 1];
+        for &len in lengths
+        {
+            counts[len as usize] += 1;
+        }
+        // Incomplete codes are allowed, like a distance code with a single symbol
+        let mut left: i32 = 1;
+        for &count in &counts[1..]
+        {
+            left = left * 2 - count as i32;
+            if left < 0 { return Err(corrupted("over-subscribed code")); }
+        }
+        let mut offsets = [0_u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS
+        {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0_u16; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate()
+        {
+            if len != 0
+            {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Self { counts, symbols })
+    }
+
+    /// Codes of the blocks compressed with fixed Huffman codes
+    fn fixed() -> (Self, Self)
+    {
+        let mut lengths = [8_u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        (Self::new(&lengths).unwrap(), Self::new(&[5; 30]).unwrap())
+    }
+}
+
+#[derive(Clone)]
+enum Block
+{
+    /// Before the header of a block
+    Header,
+    /// In a stored block, with the number of bytes left
+    Stored(u16),
+    /// In a compressed block, with its literal/length and distance codes
+    Compressed(Arc<Huffman>, Arc<Huffman>),
+    /// After the last block
+    End,
+}
+
+/// Reader of the bits of a compressed stream, from the least significant bit of each byte
+#[derive(Clone)]
+struct BitReader
+{
+    /// Offset of the next compressed byte to read into `input`
+    next: u64,
+    /// End of the compressed data
+    end: u64,
+    input: Vec<u8>,
+    input_pos: usize,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl BitReader
+{
+    fn byte(&mut self, source: &dyn ReadAt) -> CNFSResult<u8>
+    {
+        if self.input_pos == self.input.len()
+        {
+            let len = min(INPUT_CHUNK_SIZE, self.end.saturating_sub(self.next)) as usize;
+            self.input.resize(len, 0);
+            if len == 0 || source.read_at(self.next, &mut self.input)? < len
+            {
+                return Err(corrupted("unexpected end"));
+            }
+            self.next += len as u64;
+            self.input_pos = 0;
+        }
+        self.input_pos += 1;
+        Ok(self.input[self.input_pos - 1])
+    }
+
+    /// Take `count` bits, up to 16
+    fn take(&mut self, source: &dyn ReadAt, count: u32) -> CNFSResult<u32>
+    {
+        while self.bit_count < count
+        {
+            self.bits |= (self.byte(source)? as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary
+    fn align(&mut self)
+    {
+        self.bits >>= self.bit_count % 8;
+        self.bit_count -= self.bit_count % 8;
+    }
+
+    fn decode(&mut self, source: &dyn ReadAt, code: &Huffman) -> CNFSResult<u16>
+    {
+        let (mut value, mut first, mut index) = (0_i32, 0_i32, 0_i32);
+        for len in 1..=MAX_BITS
+        {
+            value |= self.take(source, 1)? as i32;
+            let count = code.counts[len] as i32;
+            if value - first < count
+            {
+                return Ok(code.symbols[(index + value - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            value <<= 1;
+        }
+        Err(corrupted("invalid code"))
+    }
+
+    /// Read the codes of a dynamic block
+    fn dynamic_codes(&mut self, source: &dyn ReadAt) -> CNFSResult<(Huffman, Huffman)>
+    {
+        let literals = self.take(source, 5)? as usize + 257;
+        let distances = self.take(source, 5)? as usize + 1;
+        let code_lengths = self.take(source, 4)? as usize + 4;
+        if literals > 286 || distances > 30 { return Err(corrupted("too many codes")); }
+        let mut lengths = [0_u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_lengths]
+        {
+            lengths[symbol] = self.take(source, 3)? as u8;
+        }
+        let length_code = Huffman::new(&lengths)?;
+        let mut lengths = vec![0_u8; literals + distances];
+        let mut i = 0;
+        while i < lengths.len()
+        {
+            let symbol = self.decode(source, &length_code)?;
+            let (len, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 if i > 0 => (lengths[i - 1], 3 + self.take(source, 2)? as usize),
+                17 => (0, 3 + self.take(source, 3)? as usize),
+                18 => (0, 11 + self.take(source, 7)? as usize),
+                _ => return Err(corrupted("repeat without a length")),
+            };
+            if i + repeat > lengths.len() { return Err(corrupted("too many lengths")); }
+            lengths[i..i + repeat].fill(len);
+            i += repeat;
+        }
+        if lengths[256] == 0 { return Err(corrupted("no end of block code")); }
+        Ok((Huffman::new(&lengths[..literals])?, Huffman::new(&lengths[literals..])?))
+    }
+}
+
+/// The last bytes yielded, which back references copy from
+#[derive(Clone)]
+struct Window
+{
+    data: Vec<u8>,
+    /// Number of bytes yielded
+    position: u64,
+}
+
+impl Window
+{
+    fn push(&mut self, byte: u8, buffer: &mut [u8], nread: &mut usize)
+    {
+        self.data[self.position as usize % WINDOW_SIZE] = byte;
+        self.position += 1;
+        buffer[*nread] = byte;
+        *nread += 1;
+    }
+
+    fn back(&self, distance: usize) -> u8
+    {
+        self.data[(self.position - distance as u64) as usize % WINDOW_SIZE]
+    }
+}
+
+/// Decompressor of a raw deflate stream, which yields the data in order
+///
+/// Cloning it saves its whole state, so the decompression can later restart from there.
+#[derive(Clone)]
+pub(crate) struct Inflater
+{
+    reader: BitReader,
+    window: Window,
+    block: Block,
+    last: bool,
+    /// Length and distance of the back reference being copied
+    copy: (usize, usize),
+    /// CRC32 of the bytes yielded
+    crc: u32,
+}
+
+impl Inflater
+{
+    /// New a decompressor of the stream stored from `start` to `end` in the source
+    pub fn new(start: u64, end: u64) -> Self
+    {
+        Self {
+            reader: BitReader { next: start, end, input: Vec::new(), input_pos: 0, bits: 0, bit_count: 0 },
+            window: Window { data: vec![0; WINDOW_SIZE], position: 0 },
+            block: Block::Header,
+            last: false,
+            copy: (0, 0),
+            crc: 0,
+        }
+    }
+
+    /// Returns the number of bytes yielded
+    pub fn position(&self) -> u64
+    {
+        self.window.position
+    }
+
+    /// Returns the CRC32 of the bytes yielded
+    pub fn crc(&self) -> u32
+    {
+        self.crc
+    }
+
+    /// Decompress into the buffer, returning how many bytes were yielded, which is less than
+    /// the length of the buffer only at the end of the stream
+    pub fn read(&mut self, source: &dyn ReadAt, buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        let reader = &mut self.reader;
+        let window = &mut self.window;
+        let mut nread = 0;
+        while nread < buffer.len()
+        {
+            if self.copy.0 > 0
+            {
+                self.copy.0 -= 1;
+                window.push(window.back(self.copy.1), buffer, &mut nread);
+                continue;
+            }
+            match &self.block {
+                Block::End => break,
+                Block::Header if self.last => self.block = Block::End,
+                Block::Header => {
+                    self.last = reader.take(source, 1)? == 1;
+                    self.block = match reader.take(source, 2)? {
+                        0 => {
+                            // Stored blocks start at a byte boundary
+                            reader.align();
+                            let len = reader.take(source, 16)?;
+                            if len != !reader.take(source, 16)? & 0xffff
+                            {
+                                return Err(corrupted("bad stored block length"));
+                            }
+                            Block::Stored(len as u16)
+                        }
+                        1 => {
+                            let (literals, distances) = Huffman::fixed();
+                            Block::Compressed(Arc::new(literals), Arc::new(distances))
+                        }
+                        2 => {
+                            let (literals, distances) = reader.dynamic_codes(source)?;
+                            Block::Compressed(Arc::new(literals), Arc::new(distances))
+                        }
+                        _ => return Err(corrupted("bad block type")),
+                    };
+                }
+                Block::Stored(0) => self.block = Block::Header,
+                &Block::Stored(left) => {
+                    let byte = reader.take(source, 8)? as u8;
+                    self.block = Block::Stored(left - 1);
+                    window.push(byte, buffer, &mut nread);
+                }
+                Block::Compressed(literals, distances) => {
+                    let symbol = reader.decode(source, literals)? as usize;
+                    if symbol < 256
+                    {
+                        window.push(symbol as u8, buffer, &mut nread);
+                    } else if symbol == 256 {
+                        self.block = Block::Header;
+                    } else {
+                        let symbol = symbol - 257;
+                        if symbol >= LENGTH_BASE.len() { return Err(corrupted("bad length")); }
+                        let len = LENGTH_BASE[symbol] as usize
+                            + reader.take(source, LENGTH_EXTRA[symbol] as u32)? as usize;
+                        let symbol = reader.decode(source, distances)? as usize;
+                        if symbol >= DISTANCE_BASE.len() { return Err(corrupted("bad distance")); }
+                        let distance = DISTANCE_BASE[symbol] as usize
+                            + reader.take(source, DISTANCE_EXTRA[symbol] as u32)? as usize;
+                        if distance as u64 > window.position { return Err(corrupted("distance too far back")); }
+                        self.copy = (len, distance);
+                    }
+                }
+            }
+        }
+        self.crc = crc32_update(self.crc, &buffer[..nread]);
+        Ok(nread)
+    }
+}
@@ -0,0 +1,509 @@
+mod inflate;
+
+use crate::block::ReadAt;
+use crate::error::CNFSError::{FSInternal, InvalidArgument, IsADirectory, NotADirectory, NotImplemented,
+                              PathNotFound, ReadOnly, UnknownFileSystem};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::{DirEntry, FileSystem, FileSystemFactory, Inode, InodeRef, InodeType, Metadata, MountSource};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use inflate::Inflater;
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const EOCD_SIZE: usize = 22;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_EOCD_SIZE: usize = 56;
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+const CENTRAL_SIZE: usize = 46;
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+const LOCAL_SIZE: usize = 30;
+/// Longest comment after the end of central directory record
+const MAX_COMMENT_SIZE: usize = 65535;
+/// Extra field holding the 64-bit sizes and offset
+const ZIP64_EXTRA: u16 = 0x0001;
+/// Extra field holding Unix timestamps
+const TIMESTAMP_EXTRA: u16 = 0x5455;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 1;
+/// Distance between the saved decompressor states of a deflated entry
+const CHECKPOINT_INTERVAL: u64 = 1 << 20;
+/// Size of the buffer decompressed data is skipped through
+const SKIP_CHUNK_SIZE: usize = 4096;
+
+fn le16(data: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(data: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64
+{
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Convert an MS-DOS date and time, taken as UTC, to seconds since the Unix epoch
+fn dos_time(date: u16, time: u16) -> Option<u64>
+{
+    let (year, month, day) = (1980 + (date >> 9) as i64, ((date >> 5) & 15) as i64, (date & 31) as i64);
+    if !(1..=12).contains(&month) || day == 0 { return None; }
+    // Days from the epoch of a proleptic Gregorian date
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let days = era * 146097 + year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year - 719468;
+    let seconds = (time >> 11) as i64 * 3600 + ((time >> 5) & 63) as i64 * 60 + (time & 31) as i64 * 2;
+    Some((days * 86400 + seconds) as u64)
+}
+
+/// Split an archive path into its components, returning None if it goes up with `..`
+fn components(path: &str) -> Option<Vec<&str>>
+{
+    let parts: Vec<_> = path.split(['/', '\\']).filter(|s| !s.is_empty() && *s != ".").collect();
+    if parts.contains(&"..") { None } else { Some(parts) }
+}
+
+/// Where the data of an entry is and how it is stored
+struct ZipEntry
+{
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+}
+
+struct ZipNode
+{
+    inode_type: InodeType,
+    modified: Option<u64>,
+    entry: Option<ZipEntry>,
+    children: BTreeMap<String, usize>,
+}
+
+impl ZipNode
+{
+    fn dir(modified: Option<u64>) -> Self
+    {
+        Self { inode_type: InodeType::Dir, modified, entry: None, children: BTreeMap::new() }
+    }
+}
+
+/// Central directory of an archive indexed at mount time
+struct ZipIndex
+{
+    source: Box<dyn ReadAt>,
+    /// The root is the first node
+    nodes: Vec<ZipNode>,
+}
+
+impl ZipIndex
+{
+    /// Read the central directory of the whole archive
+    fn new(source: Box<dyn ReadAt>) -> CNFSResult<Self>
+    {
+        let mut index = Self { source, nodes: vec![ZipNode::dir(None)] };
+        let (count, mut offset, cd_size) = index.end_of_central_directory()?;
+        if offset.checked_add(cd_size).is_none_or(|end| end > index.source.size())
+        {
+            return Err(FSInternal(String::from("zip central directory is out of the archive")));
+        }
+        let mut directory = vec![0_u8; cd_size as usize];
+        index.read_exact(offset, &mut directory)?;
+        let mut pos = 0;
+        for _ in 0..count
+        {
+            if pos + CENTRAL_SIZE > directory.len() || le32(&directory, pos) != CENTRAL_SIGNATURE
+            {
+                return Err(FSInternal(format!("bad zip central directory entry at offset {offset}")));
+            }
+            let record = &directory[pos..];
+            let (name_len, extra_len, comment_len) =
+                (le16(record, 28) as usize, le16(record, 30) as usize, le16(record, 32) as usize);
+            let len = CENTRAL_SIZE + name_len + extra_len + comment_len;
+            if pos + len > directory.len()
+            {
+                return Err(FSInternal(format!("bad zip central directory entry at offset {offset}")));
+            }
+            let name = String::from_utf8_lossy(&record[CENTRAL_SIZE..CENTRAL_SIZE + name_len]).into_owned();
+            let extra = &record[CENTRAL_SIZE + name_len..CENTRAL_SIZE + name_len + extra_len];
+            let mut entry = ZipEntry {
+                method: le16(record, 10),
+                flags: le16(record, 8),
+                crc: le32(record, 16),
+                compressed_size: le32(record, 20) as u64,
+                size: le32(record, 24) as u64,
+                header_offset: le32(record, 42) as u64,
+            };
+            let mut modified = dos_time(le16(record, 14), le16(record, 12));
+            Self::parse_extra(extra, &mut entry, &mut modified);
+            if let Some(parts) = components(&name)
+            {
+                let node = if name.ends_with(['/', '\\']) {
+                    ZipNode::dir(modified)
+                } else {
+                    ZipNode { inode_type: InodeType::File, modified, entry: Some(entry), children: BTreeMap::new() }
+                };
+                index.insert(&parts, node);
+            }
+            pos += len;
+            offset += len as u64;
+        }
+        Ok(index)
+    }
+
+    /// Find the end of central directory record, returning the number of entries and the
+    /// offset and size of the central directory
+    fn end_of_central_directory(&self) -> CNFSResult<(u64, u64, u64)>
+    {
+        let size = self.source.size();
+        let tail_size = min(size, (EOCD_SIZE + MAX_COMMENT_SIZE) as u64) as usize;
+        if tail_size < EOCD_SIZE { return Err(UnknownFileSystem); }
+        let tail_offset = size - tail_size as u64;
+        let mut tail = vec![0_u8; tail_size];
+        self.read_exact(tail_offset, &mut tail)?;
+        // The record is followed by a comment, so it is searched from the end
+        let pos = (0..=tail_size - EOCD_SIZE).rev()
+            .find(|&i| le32(&tail, i) == EOCD_SIGNATURE && i + EOCD_SIZE + le16(&tail, i + 20) as usize <= tail_size)
+            .ok_or(UnknownFileSystem)?;
+        let eocd = &tail[pos..];
+        let (count, cd_size, cd_offset) = (le16(eocd, 10) as u64, le32(eocd, 12) as u64, le32(eocd, 16) as u64);
+        if count != 0xffff && cd_size != 0xffff_ffff && cd_offset != 0xffff_ffff
+        {
+            return Ok((count, cd_offset, cd_size));
+        }
+        let eocd_offset = tail_offset + pos as u64;
+        let mut locator = [0_u8; ZIP64_LOCATOR_SIZE];
+        if eocd_offset < ZIP64_LOCATOR_SIZE as u64 { return Ok((count, cd_offset, cd_size)); }
+        self.read_exact(eocd_offset - ZIP64_LOCATOR_SIZE as u64, &mut locator)?;
+        if le32(&locator, 0) != ZIP64_LOCATOR_SIGNATURE { return Ok((count, cd_offset, cd_size)); }
+        let mut eocd64 = [0_u8; ZIP64_EOCD_SIZE];
+        self.read_exact(le64(&locator, 8), &mut eocd64)?;
+        if le32(&eocd64, 0) != ZIP64_EOCD_SIGNATURE
+        {
+            return Err(FSInternal(String::from("bad zip64 end of central directory record")));
+        }
+        Ok((le64(&eocd64, 32), le64(&eocd64, 48), le64(&eocd64, 40)))
+    }
+
+    /// Apply the zip64 sizes and the Unix modification time of the extra fields
+    fn parse_extra(mut extra: &[u8], entry: &mut ZipEntry, modified: &mut Option<u64>)
+    {
+        while extra.len() >= 4
+        {
+            let (id, len) = (le16(extra, 0), le16(extra, 2) as usize);
+            let Some(data) = extra.get(4..4 + len) else { return };
+            match id {
+                ZIP64_EXTRA => {
+                    // Only the fields that overflowed in the record are present, in this order
+                    let mut fields = data.chunks_exact(8).map(|field| le64(field, 0));
+                    for value in [&mut entry.size, &mut entry.compressed_size, &mut entry.header_offset]
+                    {
+                        if *value == 0xffff_ffff
+                        {
+                            match fields.next() {
+                                Some(field) => *value = field,
+                                None => break,
+                            }
+                        }
+                    }
+                }
+                TIMESTAMP_EXTRA if len >= 5 && data[0] & 1 != 0 => *modified = Some(le32(data, 1) as u64),
+                _ => {}
+            }
+            extra = &extra[4 + len..];
+        }
+    }
+
+    fn read_exact(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult
+    {
+        if self.source.read_at(offset, buffer)? < buffer.len()
+        {
+            return Err(FSInternal(format!("zip archive is truncated at offset {offset}")));
+        }
+        Ok(())
+    }
+
+    /// Put a node at a path, making the missing parent directories and replacing an
+    /// earlier entry of the same path
+    fn insert(&mut self, parts: &[&str], node: ZipNode)
+    {
+        let Some((name, parents)) = parts.split_last() else { return };
+        let mut dir = 0;
+        for part in parents
+        {
+            dir = match self.nodes[dir].children.get(*part) {
+                Some(&child) if self.nodes[child].inode_type == InodeType::Dir => child,
+                Some(_) => return,
+                None => {
+                    self.nodes.push(ZipNode::dir(None));
+                    let child = self.nodes.len() - 1;
+                    self.nodes[dir].children.insert(String::from(*part), child);
+                    child
+                }
+            };
+        }
+        match self.nodes[dir].children.get(*name) {
+            Some(&old) if self.nodes[old].inode_type == InodeType::Dir && node.inode_type == InodeType::Dir => {
+                self.nodes[old].modified = node.modified;
+            }
+            _ => {
+                self.nodes.push(node);
+                let child = self.nodes.len() - 1;
+                self.nodes[dir].children.insert(String::from(*name), child);
+            }
+        }
+    }
+}
+
+/// Decompression state of a deflated entry
+#[derive(Default)]
+struct Stream
+{
+    /// Where the last read stopped, so sequential reads go on from there
+    current: Option<Inflater>,
+    /// States at every [CHECKPOINT_INTERVAL] bytes, so reads going back don't restart
+    /// from the beginning
+    checkpoints: Vec<Inflater>,
+}
+
+/// Inode of a zip filesystem
+///
+/// A deflated entry keeps its decompressor, and its decompressed pages are kept by the page
+/// cache of the VFS inode holding it, so sequential reads and rereads don't decompress again.
+pub struct ZipInode
+{
+    index: Arc<ZipIndex>,
+    node: usize,
+    /// Offset of the entry data, read from its local header on the first read
+    data_offset: UPCell<Option<u64>>,
+    stream: UPCell<Stream>,
+}
+
+impl ZipInode
+{
+    fn new(index: Arc<ZipIndex>, node: usize) -> Self
+    {
+        Self {
+            index,
+            node,
+            data_offset: unsafe { UPCell::new(None) },
+            stream: unsafe { UPCell::new(Stream::default()) },
+        }
+    }
+
+    fn node(&self) -> &ZipNode
+    {
+        &self.index.nodes[self.node]
+    }
+
+    fn data_offset(&self, entry: &ZipEntry) -> CNFSResult<u64>
+    {
+        if let Some(offset) = *self.data_offset.shared_access() { return Ok(offset); }
+        let mut header = [0_u8; LOCAL_SIZE];
+        self.index.read_exact(entry.header_offset, &mut header)?;
+        if le32(&header, 0) != LOCAL_SIGNATURE
+        {
+            return Err(FSInternal(format!("bad zip local header at offset {}", entry.header_offset)));
+        }
+        let header_size = LOCAL_SIZE + le16(&header, 26) as usize + le16(&header, 28) as usize;
+        let size = self.index.source.size();
+        let offset = entry.header_offset.checked_add(header_size as u64)
+            .filter(|offset| offset.checked_add(entry.compressed_size).is_some_and(|end| end <= size))
+            .ok_or_else(|| FSInternal(format!("zip entry at offset {} is truncated", entry.header_offset)))?;
+        *self.data_offset.exclusive_access() = Some(offset);
+        Ok(offset)
+    }
+
+    /// Read decompressed data, going on from the last read or the nearest checkpoint before
+    /// the offset
+    fn read_deflated(&self, entry: &ZipEntry, start: u64, offset: u64, buffer: &mut [u8]) -> CNFSResult
+    {
+        let source = self.index.source.as_ref();
+        let mut stream = self.stream.exclusive_access();
+        let mut inflater = match stream.current.take() {
+            Some(current) if current.position() <= offset => current,
+            _ => stream.checkpoints.iter().rev().find(|c| c.position() <= offset).cloned()
+                .unwrap_or_else(|| Inflater::new(start, start + entry.compressed_size)),
+        };
+        let mut skip = vec![0_u8; SKIP_CHUNK_SIZE];
+        let mut nread = 0;
+        while nread < buffer.len()
+        {
+            let position = inflater.position();
+            let next_checkpoint = (position / CHECKPOINT_INTERVAL + 1) * CHECKPOINT_INTERVAL;
+            let bytes = if position < offset {
+                let len = min(min(skip.len() as u64, offset - position), next_checkpoint - position) as usize;
+                inflater.read(source, &mut skip[..len])?
+            } else {
+                let len = min((buffer.len() - nread) as u64, next_checkpoint - position) as usize;
+                let bytes = inflater.read(source, &mut buffer[nread..nread + len])?;
+                nread += bytes;
+                bytes
+            };
+            if bytes == 0 { return Err(FSInternal(String::from("zip entry is shorter than its size"))); }
+            let position = inflater.position();
+            if position.is_multiple_of(CHECKPOINT_INTERVAL)
+                && position / CHECKPOINT_INTERVAL == stream.checkpoints.len() as u64 + 1
+            {
+                stream.checkpoints.push(inflater.clone());
+            }
+        }
+        if inflater.position() == entry.size && inflater.crc() != entry.crc
+        {
+            return Err(FSInternal(String::from("zip entry has a wrong CRC32")));
+        }
+        stream.current = Some(inflater);
+        Ok(())
+    }
+}
+
+impl Inode for ZipInode
+{
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        let node = self.node();
+        let Some(entry) = &node.entry else { return Err(IsADirectory) };
+        if offset >= entry.size { return Ok(0); }
+        let len = min(buffer.len() as u64, entry.size - offset) as usize;
+        if entry.flags & FLAG_ENCRYPTED != 0 { return Err(NotImplemented); }
+        match entry.method {
+            METHOD_STORED => {
+                if offset + len as u64 > entry.compressed_size
+                {
+                    return Err(FSInternal(String::from("zip entry is shorter than its size")));
+                }
+                let start = self.data_offset(entry)?;
+                self.index.read_exact(start + offset, &mut buffer[..len])?;
+            }
+            METHOD_DEFLATED => {
+                let start = self.data_offset(entry)?;
+                self.read_deflated(entry, start, offset, &mut buffer[..len])?;
+            }
+            _ => return Err(NotImplemented),
+        }
+        Ok(len)
+    }
+
+    fn write(&self, _offset: u64, _buffer: &[u8]) -> CNFSResult<usize> {
+        Err(ReadOnly)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> CNFSResult<InodeRef> {
+        let node = self.node();
+        if node.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        let &child = node.children.get(name).ok_or(PathNotFound)?;
+        Ok(Arc::new(ZipInode::new(self.index.clone(), child)))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType) -> CNFSResult<InodeRef> {
+        Err(ReadOnly)
+    }
+
+    fn remove(&self, _name: &str) -> CNFSResult {
+        Err(ReadOnly)
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let node = self.node();
+        let mut metadata = Metadata::new(node.inode_type, node.entry.as_ref().map_or(0, |e| e.size));
+        metadata.modified = node.modified;
+        Ok(metadata)
+    }
+
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>> {
+        let node = self.node();
+        if node.inode_type != InodeType::Dir { return Err(NotADirectory); }
+        Ok(node.children.iter()
+            .map(|(name, &child)| DirEntry { name: name.clone(), inode_type: self.index.nodes[child].inode_type })
+            .collect())
+    }
+}
+
+/// Read-only filesystem over a zip archive
+///
+/// The central directory is indexed when the filesystem is made, and the directory tree is
+/// built from the entry names. Stored and deflated entries can be read, including zip64
+/// archives, while encrypted entries and other compression methods fail with
+/// [NotImplemented](crate::CNFSError::NotImplemented). A deflated entry can be read at any
+/// offset: its decompressor goes on from the last read and saves its state every 1 MiB, so
+/// going back only decompresses from the nearest saved state.
+///
+/// ```rust
+///  use cnfs::{mount, read_to_end, umount, MountOptions, Path, ZipFs};
+///  use std::sync::Arc;
+///  let archive = std::fs::read("tests/resources/plugin.zip").unwrap();
+///  mount(Arc::new(ZipFs::new(archive).unwrap()), Path::new("/"), MountOptions::new().read_only(true)).unwrap();
+///  assert_eq!(read_to_end(&Path::new("/plugin/manifest.txt")).unwrap(), b"cnss{plugin}");
+///  umount(Path::new("/")).unwrap();
+/// ```
+///
+pub struct ZipFs
+{
+    index: Arc<ZipIndex>,
+}
+
+impl ZipFs
+{
+    /// Index the archive in the source
+    pub fn new<T: ReadAt + 'static>(source: T) -> CNFSResult<Self>
+    {
+        Ok(Self { index: Arc::new(ZipIndex::new(Box::new(source))?) })
+    }
+
+    /// Check if the start of a source is a zip archive
+    ///
+    /// Only archives starting with a local file header or an empty archive are recognized,
+    /// not self-extracting ones.
+    pub fn probe(superblock: &[u8]) -> bool
+    {
+        superblock.len() >= 4 && matches!(le32(superblock, 0), LOCAL_SIGNATURE | EOCD_SIGNATURE)
+    }
+}
+
+impl FileSystem for ZipFs
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(ZipInode::new(self.index.clone(), 0))
+    }
+
+    fn fs_type(&self) -> &str {
+        "zip"
+    }
+}
+
+/// Builds ZIP filesystems from block devices for [mount_by_type](crate::mount_by_type), where
+/// it is registered as `zip`
+pub struct ZipFactory;
+
+impl FileSystemFactory for ZipFactory
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::Device(device) => Ok(Arc::new(ZipFs::new(device.clone())?)),
+            _ => Err(InvalidArgument),
+        }
+    }
+
+    fn probe(&self, superblock: &[u8]) -> bool {
+        ZipFs::probe(superblock)
+    }
+}
@@ -6,12 +6,19 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+mod block;
 mod error;
+mod fs;
 mod usrlyr;
 mod vfs;
 mod sync;
 mod config;
 
+pub use block::*;
+pub use config::{config, init, VfsConfig};
 pub use error::*;
+pub use fs::*;
 pub use usrlyr::*;
 pub use vfs::*;
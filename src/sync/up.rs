@@ -21,4 +21,7 @@ impl<T> UPCell<T> {
 
     /// Shared access to the inner data.
     pub fn shared_access(&self) -> Ref<'_, T> { self.inner.borrow() }
+
+    /// Shared access to the inner data, unless it is exclusively borrowed.
+    pub fn try_shared_access(&self) -> Option<Ref<'_, T>> { self.inner.try_borrow().ok() }
 }
\ No newline at end of file
@@ -0,0 +1,341 @@
+//! Tar archives of VFS subtrees, available with the `std` feature
+//!
+//! ```rust
+//!  use cnfs::{archive, create_directory, mount, read_to_end, umount, write_all, MountOptions, Path, RamFs};
+//!  use std::sync::Arc;
+//!  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+//!  create_directory(&Path::new("/src")).unwrap();
+//!  write_all(&Path::new("/src/cnss"), b"cnss").unwrap();
+//!  let mut tarball = Vec::new();
+//!  archive::export_tar(&Path::new("/src"), &mut tarball).unwrap();
+//!
+//!  create_directory(&Path::new("/dst")).unwrap();
+//!  archive::import_tar(tarball.as_slice(), &Path::new("/dst")).unwrap();
+//!  assert_eq!(read_to_end(&Path::new("/dst/cnss")).unwrap(), b"cnss");
+//!  umount(Path::new("/")).unwrap();
+//! ```
+//!
+
+use crate::error::CNFSError::{InvalidArgument, IsADirectory, NotADirectory, NotImplemented};
+use crate::error::CNFSResult;
+use crate::fs::{tar_components, TarParser, TAR_BLOCK_SIZE};
+use crate::usrlyr::{create_directory, exists, metadata, open, read_dir, remove, set_modified, FileMode};
+use crate::vfs::{mount_point_of, InodeType, Metadata, Path};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::cmp::min;
+use std::io::{Read, Write};
+
+/// Size of the chunks file data is copied in
+const COPY_CHUNK_SIZE: usize = 4096;
+/// Largest value of the 12-byte octal fields
+const MAX_OCTAL: u64 = 0o77777777777;
+/// Name of the pax headers, which readers that don't know them extract as a file
+const PAX_HEADER_NAME: &str = "././@PaxHeader";
+
+bitflags! {
+    /// Flags of [export_tar_with_flags]
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct ArchiveFlags: u32 {
+        /// Don't descend into filesystems mounted under the exported directory, which are
+        /// archived as empty directories
+        const ONE_FILE_SYSTEM = 0b00000001;
+    }
+}
+
+/// Write a tar archive of the file or directory at the given path.
+///
+/// Directories are archived with everything under them, including the filesystems mounted
+/// there, and the entry names are relative to the path. Names too long for a ustar header and
+/// files larger than 8 GiB use pax extended headers.
+pub fn export_tar<W: Write>(path: &Path, writer: W) -> CNFSResult
+{
+    export_tar_with_flags(path, writer, ArchiveFlags::empty())
+}
+
+/// Write a tar archive of the file or directory at the given path, with flags.
+pub fn export_tar_with_flags<W: Write>(path: &Path, mut writer: W, flags: ArchiveFlags) -> CNFSResult
+{
+    let metadata = metadata(path)?;
+    match metadata.inode_type {
+        InodeType::Dir => export_dir(path, "", &mount_point_of(path)?, flags, &mut writer)?,
+        InodeType::File => export_file(path, &path[path.len() - 1], &metadata, &mut writer)?,
+        _ => return Err(InvalidArgument),
+    }
+    // The end of the archive is marked by two zero blocks
+    writer.write_all(&[0; 2 * TAR_BLOCK_SIZE as usize])?;
+    Ok(writer.flush()?)
+}
+
+fn export_dir(dir: &Path, prefix: &str, mount: &Path, flags: ArchiveFlags, writer: &mut dyn Write)
+              -> CNFSResult
+{
+    for entry in read_dir(dir)?
+    {
+        let path = dir.join(&entry.name);
+        let name = format!("{prefix}{}", entry.name);
+        let metadata = metadata(&path)?;
+        match metadata.inode_type {
+            InodeType::Dir => {
+                let name = name + "/";
+                write_header(writer, &name, b'5', 0, metadata.modified)?;
+                if flags.contains(ArchiveFlags::ONE_FILE_SYSTEM) && mount_point_of(&path)? != *mount
+                {
+                    continue;
+                }
+                export_dir(&path, &name, mount, flags, writer)?;
+            }
+            InodeType::File => export_file(&path, &name, &metadata, writer)?,
+            // Devices and FIFOs are streams without data to archive, and other inodes are left out
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn export_file(path: &Path, name: &str, metadata: &Metadata, writer: &mut dyn Write) -> CNFSResult
+{
+    write_header(writer, name, b'0', metadata.size, metadata.modified)?;
+    let mut file = open(path, FileMode::read)?;
+    let mut buffer = vec![0_u8; COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < metadata.size
+    {
+        let len = min(buffer.len() as u64, metadata.size - offset) as usize;
+        let bytes = match file.read_at(offset, &mut buffer[..len])? {
+            // A file that shrank since its size was written is padded with zeros
+            0 => {
+                buffer[..len].fill(0);
+                len
+            }
+            bytes => bytes,
+        };
+        writer.write_all(&buffer[..bytes])?;
+        offset += bytes as u64;
+    }
+    write_padding(writer, metadata.size)
+}
+
+/// Write zeros up to the end of the block holding the end of the data
+fn write_padding(writer: &mut dyn Write, size: u64) -> CNFSResult
+{
+    let padding = (size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE - size) as usize;
+    Ok(writer.write_all(&[0; TAR_BLOCK_SIZE as usize][..padding])?)
+}
+
+/// Write an octal number, ended by a NUL, filling the field
+fn octal(field: &mut [u8], value: u64)
+{
+    let end = field.len() - 1;
+    field[..end].copy_from_slice(format!("{value:0end$o}").as_bytes());
+    field[end] = 0;
+}
+
+/// Make a pax record, whose length includes the digits of the length itself
+fn pax_record(key: &str, value: &str) -> String
+{
+    let body = format!(" {key}={value}\n");
+    let mut len = body.len();
+    while len != body.len() + len.to_string().len()
+    {
+        len = body.len() + len.to_string().len();
+    }
+    format!("{len}{body}")
+}
+
+/// Split a name into the prefix and name fields of a ustar header, at a `/`
+fn split_name(name: &str) -> Option<(&str, &str)>
+{
+    if name.len() <= 100 { return Some(("", name)); }
+    name.match_indices('/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|(prefix, rest)| prefix.len() <= 155 && !rest.is_empty() && rest.len() <= 100)
+}
+
+fn ustar_header(name: &[u8], prefix: &[u8], entry_type: u8, size: u64, modified: u64)
+                -> [u8; TAR_BLOCK_SIZE as usize]
+{
+    let mut block = [0_u8; TAR_BLOCK_SIZE as usize];
+    let name = &name[..min(name.len(), 100)];
+    block[..name.len()].copy_from_slice(name);
+    octal(&mut block[100..108], if entry_type == b'5' { 0o755 } else { 0o644 });
+    octal(&mut block[108..116], 0);
+    octal(&mut block[116..124], 0);
+    octal(&mut block[124..136], size);
+    octal(&mut block[136..148], modified);
+    block[156] = entry_type;
+    block[257..265].copy_from_slice(b"ustar\x0000");
+    block[345..345 + prefix.len()].copy_from_slice(prefix);
+    block[148..156].fill(b' ');
+    let checksum: u64 = block.iter().map(|&b| b as u64).sum();
+    octal(&mut block[148..155], checksum);
+    block
+}
+
+/// Write the header of an entry, preceded by a pax header if the ustar one can't hold it
+fn write_header(writer: &mut dyn Write, name: &str, entry_type: u8, size: u64, modified: Option<u64>)
+    -> CNFSResult
+{
+    let mut records = String::new();
+    let (prefix, short_name) = split_name(name).unwrap_or_else(|| {
+        records += &pax_record("path", name);
+        ("", name)
+    });
+    if size > MAX_OCTAL { records += &pax_record("size", &size.to_string()); }
+    let modified = min(modified.unwrap_or(0), MAX_OCTAL);
+    if !records.is_empty()
+    {
+        let len = records.len() as u64;
+        writer.write_all(&ustar_header(PAX_HEADER_NAME.as_bytes(), b"", b'x', len, modified))?;
+        writer.write_all(records.as_bytes())?;
+        write_padding(writer, len)?;
+    }
+    let size = if size > MAX_OCTAL { 0 } else { size };
+    let header = ustar_header(short_name.as_bytes(), prefix.as_bytes(), entry_type, size, modified);
+    Ok(writer.write_all(&header)?)
+}
+
+/// Extract a tar archive into the directory at the given path.
+///
+/// Directories are created as needed and existing files are replaced. Hard links are
+/// extracted as copies and symbolic links as files holding their target, and other special
+/// files are skipped, like entries going out of the directory with `..`. Modification times
+/// are restored on filesystems that keep them.
+pub fn import_tar<R: Read>(mut reader: R, path: &Path) -> CNFSResult
+{
+    if metadata(path)?.inode_type != InodeType::Dir { return Err(NotADirectory); }
+    let mut parser = TarParser::default();
+    let mut block = [0_u8; TAR_BLOCK_SIZE as usize];
+    let mut offset = 0;
+    let mut dirs = Vec::new();
+    // An archive may end without its zero blocks
+    while read_block(&mut reader, &mut block)?
+    {
+        let Some(header) = parser.header(&block, offset)? else { break };
+        let padded = header.size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+        offset += TAR_BLOCK_SIZE + padded;
+        if header.is_extended()
+        {
+            let mut data = vec![0_u8; header.size as usize];
+            reader.read_exact(&mut data)?;
+            skip(&mut reader, padded - header.size)?;
+            parser.extend(&header, &data);
+            continue;
+        }
+        let Some(parts) = tar_components(&header.path).filter(|parts| !parts.is_empty()) else {
+            skip(&mut reader, padded)?;
+            continue;
+        };
+        let target = parts.iter().fold(path.clone(), |target, part| target.join(part));
+        make_parents(path, &target)?;
+        let consumed = match header.entry_type {
+            b'0' | b'\0' | b'7' => {
+                extract_file(&mut reader, header.size, header.modified, &target)?;
+                header.size
+            }
+            b'1' => {
+                let source = tar_components(&header.link)
+                    .map(|parts| parts.iter().fold(path.clone(), |source, part| source.join(part)));
+                match source {
+                    Some(source) if exists(&source)? && metadata(&source)?.inode_type == InodeType::File => {
+                        let mut file = open(&source, FileMode::read)?;
+                        let size = file.metadata()?.size;
+                        extract_file(&mut file, size, header.modified, &target)?;
+                    }
+                    // The target is not in the archive
+                    _ => {}
+                }
+                0
+            }
+            b'2' => {
+                extract_file(&mut header.link.as_bytes(), header.link.len() as u64, header.modified, &target)?;
+                0
+            }
+            b'5' => {
+                if !exists(&target)? { create_directory(&target)?; }
+                if metadata(&target)?.inode_type != InodeType::Dir { return Err(NotADirectory); }
+                dirs.push((target, header.modified));
+                0
+            }
+            // Devices, FIFOs and unknown types
+            _ => 0,
+        };
+        skip(&mut reader, padded - consumed)?;
+    }
+    // Adding entries changes the times of the directories, so they are restored at last
+    for (dir, modified) in dirs.iter().rev()
+    {
+        restore_modified(dir, *modified)?;
+    }
+    Ok(())
+}
+
+/// Read a whole block, returning false at the end of the reader
+fn read_block(reader: &mut dyn Read, block: &mut [u8]) -> CNFSResult<bool>
+{
+    let mut nread = 0;
+    while nread < block.len()
+    {
+        match reader.read(&mut block[nread..])? {
+            0 if nread == 0 => return Ok(false),
+            0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            bytes => nread += bytes,
+        }
+    }
+    Ok(true)
+}
+
+fn skip(reader: &mut dyn Read, len: u64) -> CNFSResult
+{
+    let copied = std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    if copied < len { return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()); }
+    Ok(())
+}
+
+/// Create the missing directories between the root and the parent of the target
+fn make_parents(root: &Path, target: &Path) -> CNFSResult
+{
+    for len in root.len() + 1..target.len()
+    {
+        let dir = Path::from(&target[..len]);
+        if !exists(&dir)? {
+            create_directory(&dir)?;
+        } else if metadata(&dir)?.inode_type != InodeType::Dir {
+            return Err(NotADirectory);
+        }
+    }
+    Ok(())
+}
+
+/// Write `size` bytes of the reader to a new file, replacing an existing one
+fn extract_file(reader: &mut dyn Read, size: u64, modified: Option<u64>, target: &Path) -> CNFSResult
+{
+    if exists(target)?
+    {
+        if metadata(target)?.inode_type == InodeType::Dir { return Err(IsADirectory); }
+        remove(target)?;
+    }
+    let mut file = open(target, FileMode::write)?;
+    let mut buffer = vec![0_u8; COPY_CHUNK_SIZE];
+    let mut left = size;
+    while left > 0
+    {
+        let len = min(buffer.len() as u64, left) as usize;
+        reader.read_exact(&mut buffer[..len])?;
+        file.write_all(&buffer[..len])?;
+        left -= len as u64;
+    }
+    drop(file);
+    restore_modified(target, modified)
+}
+
+fn restore_modified(path: &Path, modified: Option<u64>) -> CNFSResult
+{
+    match modified.map(|modified| set_modified(path, modified)) {
+        Some(Err(NotImplemented)) | None => Ok(()),
+        Some(result) => result,
+    }
+}
@@ -1,13 +1,15 @@
-use crate::config::DEFAULT_FILE_BUFFER_SIZE;
+use crate::error::CNFSError::InvalidArgument;
 use crate::error::CNFSResult;
-use crate::vfs::Dentry;
+use crate::sync::UPCell;
+use crate::vfs::{notify, Dentry, EventMask, FileLock, LockType, Metadata, PipeEnd};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::bitflags;
-
+use lazy_static::lazy_static;
 
 bitflags! {
     /// Open File Mode
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
     pub struct FileMode: u32 {
         /// read
         const read = 0b00000001;
@@ -16,6 +18,26 @@ bitflags! {
     }
 }
 
+/// An entry of the open file table
+pub(crate) struct OpenFile
+{
+    pub dentry: Arc<Dentry>,
+    pub mode: FileMode,
+    /// The offset of the file, shared with it
+    pub offset: Arc<UPCell<u64>>,
+}
+
+lazy_static! {
+    /// Every opened file, in the order they were opened
+    pub(crate) static ref OPEN_FILES: UPCell<Vec<OpenFile>> = unsafe{UPCell::new(Vec::new())};
+}
+
+/// Returns the end of `len` bytes from `start`, where a zero `len` reaches the end of file
+fn range_end(start: u64, len: u64) -> CNFSResult<u64>
+{
+    if len == 0 { Ok(u64::MAX) } else { start.checked_add(len).ok_or(InvalidArgument) }
+}
+
 /// File Interface
 pub struct File {
     pub(crate) dentry: Arc<Dentry>,
@@ -23,19 +45,71 @@ pub struct File {
     pub(crate) buffer: Vec<u8>,
     pub(crate) max_buffer_size: usize,
     pub(crate) offset: u64,
+    /// Writes skip the IO buffer, for inodes that are never cached like devices
+    unbuffered: bool,
+    /// Where the offset is published for the open file table
+    shared_offset: Arc<UPCell<u64>>,
+    /// The end of the pipe held open by the file, if it is opened on a pipe
+    pipe_end: Option<PipeEnd>,
 }
 
 impl File
 {
     pub(super) fn new(dentry: Arc<Dentry>, mode: FileMode) -> Self
     {
+        let max_buffer_size = dentry.mount.config.file_buffer_size;
+        let unbuffered = dentry.inode().uncached().is_some();
+        let pipe = dentry.inode().pipe();
+        let pipe_end = pipe.map(|p| p.open(mode.contains(FileMode::read), mode.contains(FileMode::write)));
+        for mount in dentry.mounts()
+        {
+            *mount.open_files.exclusive_access() += 1;
+        }
+        let shared_offset = Arc::new(unsafe { UPCell::new(0) });
+        OPEN_FILES.exclusive_access().push(OpenFile { dentry: dentry.clone(), mode, offset: shared_offset.clone() });
         Self {
             dentry,
             mode,
             buffer: Vec::new(),
-            max_buffer_size: DEFAULT_FILE_BUFFER_SIZE,
+            max_buffer_size,
             offset: 0,
+            unbuffered,
+            shared_offset,
+            pipe_end,
+        }
+    }
+
+    /// Move the file offset forward
+    fn advance(&mut self, bytes: usize)
+    {
+        self.offset += bytes as u64;
+        *self.shared_offset.exclusive_access() = self.offset;
+    }
+
+    /// Write to the dentry, reporting the modification to the watches
+    fn write_dentry(&self, offset: u64, src: &[u8]) -> CNFSResult<usize>
+    {
+        let bytes = self.dentry.write(offset, src)?;
+        if bytes > 0 { notify(&self.dentry, EventMask::MODIFY); }
+        Ok(bytes)
+    }
+
+    /// Returns the size of the IO buffer.
+    pub fn max_buffer_size(&self) -> usize
+    {
+        self.max_buffer_size
+    }
+
+    /// Set the size of the IO buffer, synchronizing the buffered data if it no longer fits.
+    pub fn set_max_buffer_size(&mut self, size: usize) -> CNFSResult
+    {
+        if size == 0 { return Err(InvalidArgument); }
+        if self.buffer.len() > size
+        {
+            self.sync()?;
         }
+        self.max_buffer_size = size;
+        Ok(())
     }
 
     /// Attempts to write an entire buffer into a file.
@@ -52,6 +126,23 @@ impl File
     /// Write a buffer into a file, returning how many bytes were written.
     pub fn write(&mut self, src: &[u8]) -> CNFSResult<usize>
     {
+        if self.pipe_end.is_some() && !self.mode.contains(FileMode::write) { return Err(InvalidArgument); }
+        self.dentry.check_writable()?;
+        if self.unbuffered
+        {
+            let bytes = self.write_dentry(self.offset, src)?;
+            self.advance(bytes);
+            return Ok(bytes);
+        }
+        if self.dentry.mount.options().synchronous
+        {
+            self.sync()?;
+            let bytes = self.write_dentry(self.offset, src)?;
+            self.advance(bytes);
+            self.dentry.inode_mut().sync()?;
+            return Ok(bytes);
+        }
+
         if self.buffer.len() + src.len() > self.max_buffer_size
         {
             self.sync()?;
@@ -61,11 +152,11 @@ impl File
         while src.len() - nwritten > self.max_buffer_size
         {
             assert!(self.buffer.is_empty());
-            match self.dentry.inode_mut().write(self.offset, &src[nwritten..])
+            match self.write_dentry(self.offset, &src[nwritten..])
             {
                 Ok(bytes) => {
                     nwritten += bytes;
-                    self.offset += bytes as u64;
+                    self.advance(bytes);
                 }
                 Err(err) => {
                     if nwritten != 0
@@ -85,6 +176,8 @@ impl File
     /// Pull some bytes from this file into the specified buffer, returning how many bytes were read.
     pub fn read(&mut self, dest: &mut [u8]) -> CNFSResult<usize>
     {
+        // Reading the write end of a pipe would take the data meant for its readers
+        if self.pipe_end.is_some() && !self.mode.contains(FileMode::read) { return Err(InvalidArgument); }
         if dest.len() <= self.buffer.len()
         {
             dest.copy_from_slice(&self.buffer[..dest.len()]);
@@ -96,13 +189,13 @@ impl File
 
             while nread < dest.len()
             {
-                match self.dentry.inode_mut().read(self.offset, &mut dest[nread..])
+                match self.dentry.read(self.offset, &mut dest[nread..])
                 {
                     Ok(bytes) => {
                         if bytes > 0
                         {
                             nread += bytes;
-                            self.offset += bytes as u64;
+                            self.advance(bytes);
                         } else if bytes == 0
                         {
                             return Ok(nread);
@@ -132,7 +225,7 @@ impl File
             {
                 Ok(bytes) => {
                     nread += bytes;
-                    if nread <= dest.len() {
+                    if nread < dest.len() {
                         dest.truncate(nread);
                         return Ok(());
                     } else {
@@ -144,15 +237,150 @@ impl File
         }
     }
 
+    /// Read at an offset without moving the file offset, returning how many bytes were read.
+    pub fn read_at(&mut self, offset: u64, dest: &mut [u8]) -> CNFSResult<usize>
+    {
+        self.sync()?;
+        self.dentry.read(offset, dest)
+    }
+
+    /// Write at an offset without moving the file offset, returning how many bytes were written.
+    ///
+    /// The data bypasses the IO buffer, so other files opened on the path see it at once.
+    pub fn write_at(&mut self, offset: u64, src: &[u8]) -> CNFSResult<usize>
+    {
+        if !self.mode.contains(FileMode::write) { return Err(InvalidArgument); }
+        self.dentry.check_writable()?;
+        self.sync()?;
+        let bytes = self.write_dentry(offset, src)?;
+        if self.dentry.mount.options().synchronous
+        {
+            self.dentry.inode_mut().sync()?;
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the metadata of the file, including the data still buffered.
+    pub fn metadata(&mut self) -> CNFSResult<Metadata>
+    {
+        self.sync()?;
+        self.dentry.metadata()
+    }
+
     /// Seek to an offset
     pub fn seek(&mut self, new_offset: u64) -> CNFSResult
     {
         self.sync()?;
         self.offset = new_offset;
+        *self.shared_offset.exclusive_access() = new_offset;
         Ok(())
     }
 
+    /// Returns the owner of the locks set through the file
+    fn lock_owner(&self) -> usize
+    {
+        Arc::as_ptr(&self.shared_offset) as usize
+    }
+
+    /// Returns the lock on `len` bytes from `start`, checking the file mode allows it
+    fn range_lock(&self, start: u64, len: u64, lock_type: LockType) -> CNFSResult<FileLock>
+    {
+        let end = range_end(start, len)?;
+        let mode = if lock_type == LockType::Shared { FileMode::read } else { FileMode::write };
+        if !self.mode.contains(mode) { return Err(InvalidArgument); }
+        Ok(FileLock { start, end, lock_type })
+    }
+
+    /// Lock the whole file for reading, failing with [WouldBlock](crate::CNFSError::WouldBlock)
+    /// if another file locks it exclusively.
+    ///
+    /// Locks are advisory and kept on the inode, so they are seen by every file opened on it,
+    /// and are released when the file is dropped. A whole-file lock is a byte-range lock over
+    /// the entire file, replacing the locks the file holds.
+    pub fn try_lock_shared(&self) -> CNFSResult
+    {
+        let lock = FileLock { start: 0, end: u64::MAX, lock_type: LockType::Shared };
+        self.dentry.inode_mut().locks().lock(self.lock_owner(), lock)
+    }
+
+    /// Lock the whole file for writing, failing with [WouldBlock](crate::CNFSError::WouldBlock)
+    /// if another file locks any part of it.
+    ///
+    /// See [File::try_lock_shared] for how locks are kept.
+    pub fn try_lock_exclusive(&self) -> CNFSResult
+    {
+        let lock = FileLock { start: 0, end: u64::MAX, lock_type: LockType::Exclusive };
+        self.dentry.inode_mut().locks().lock(self.lock_owner(), lock)
+    }
+
+    /// Lock the whole file for reading, waiting until no other file locks it exclusively.
+    ///
+    /// Nothing else runs while the caller waits, so the holder of a conflicting lock could never
+    /// release it: instead of waiting forever, this fails with
+    /// [WouldBlock](crate::CNFSError::WouldBlock) at once, like [File::try_lock_shared].
+    pub fn lock_shared(&self) -> CNFSResult
+    {
+        self.try_lock_shared()
+    }
+
+    /// Lock the whole file for writing, waiting until no other file locks any part of it.
+    ///
+    /// Like [File::lock_shared], this fails with [WouldBlock](crate::CNFSError::WouldBlock)
+    /// instead of waiting for a lock that can't be released.
+    pub fn lock_exclusive(&self) -> CNFSResult
+    {
+        self.try_lock_exclusive()
+    }
+
+    /// Release every lock held by the file.
+    pub fn unlock(&self)
+    {
+        self.dentry.inode_mut().locks().unlock_all(self.lock_owner());
+    }
+
+    /// Lock `len` bytes from `start`, where a zero `len` reaches the end of file however it grows,
+    /// failing with [WouldBlock](crate::CNFSError::WouldBlock) if another file holds a
+    /// conflicting lock.
+    ///
+    /// Like `fcntl`, the lock replaces the locks the file holds on the range, splitting those
+    /// crossing its bounds, and the file must be opened for reading to set a shared lock or for
+    /// writing to set an exclusive one.
+    pub fn try_lock_range(&self, start: u64, len: u64, lock_type: LockType) -> CNFSResult
+    {
+        let lock = self.range_lock(start, len, lock_type)?;
+        self.dentry.inode_mut().locks().lock(self.lock_owner(), lock)
+    }
+
+    /// Lock `len` bytes from `start`, waiting until no other file holds a conflicting lock.
+    ///
+    /// Like [File::lock_shared], this fails with [WouldBlock](crate::CNFSError::WouldBlock)
+    /// instead of waiting for a lock that can't be released.
+    pub fn lock_range(&self, start: u64, len: u64, lock_type: LockType) -> CNFSResult
+    {
+        self.try_lock_range(start, len, lock_type)
+    }
+
+    /// Release the locks the file holds on `len` bytes from `start`, where a zero `len` reaches
+    /// the end of file.
+    pub fn unlock_range(&self, start: u64, len: u64) -> CNFSResult
+    {
+        let end = range_end(start, len)?;
+        self.dentry.inode_mut().locks().unlock(self.lock_owner(), start, end);
+        Ok(())
+    }
+
+    /// Returns a lock held by another file that would stop the given lock from being set.
+    pub fn conflicting_lock(&self, start: u64, len: u64, lock_type: LockType) -> CNFSResult<Option<FileLock>>
+    {
+        let end = range_end(start, len)?;
+        let lock = FileLock { start, end, lock_type };
+        Ok(self.dentry.inode_mut().locks().conflict(self.lock_owner(), &lock))
+    }
+
     /// Synchronize the data to filesystem.
+    ///
+    /// Errors when the file is dropped are kept on the inode and reported by the next umount,
+    /// so call it to handle them at once.
     pub fn sync(&mut self) -> CNFSResult
     {
         if self.buffer.is_empty() { return Ok(()); }
@@ -161,9 +389,9 @@ impl File
             let mut written: usize = 0;
             while written < self.buffer.len()
             {
-                written += self.dentry.inode_mut().write(self.offset,
-                                                         &self.buffer[written..])?;
-                self.offset += written as u64;
+                let bytes = self.write_dentry(self.offset, &self.buffer[written..])?;
+                written += bytes;
+                self.advance(bytes);
             }
         }
         self.buffer.clear();
@@ -174,10 +402,69 @@ impl File
 impl Drop for File
 {
     fn drop(&mut self) {
-        self.sync().expect("Failed to write to file.");
-        if *self.dentry.exist.shared_access()
+        let mut released = Vec::new();
+        for mount in self.dentry.mounts()
         {
-            self.dentry.inode_mut().sync().expect("Failed to write to file.");
+            let mut open_files = mount.open_files.exclusive_access();
+            *open_files -= 1;
+            if *open_files == 0 { released.push(mount.clone()); }
+        }
+        OPEN_FILES.exclusive_access().retain(|f| !Arc::ptr_eq(&f.offset, &self.shared_offset));
+        self.unlock();
+        if self.dentry.check_mounted().is_err() { return; }
+        let synced = self.sync();
+        if self.mode.contains(FileMode::write) && *self.dentry.exist.shared_access()
+        {
+            {
+                let mut inode = self.dentry.inode_mut();
+                // The data only reaches the storage at once on synchronous mounts
+                let written = if self.dentry.mount.options().synchronous { inode.sync() } else { inode.flush() };
+                // Nobody can be told now, so the next sync or umount reports it
+                if let Err(err) = synced.and(written) { inode.record_error(err); }
+            }
+            notify(&self.dentry, EventMask::CLOSE_WRITE);
         }
+        // A lazily unmounted filesystem is synchronized once its last file is closed
+        for mount in released.iter().filter(|m| *m.detached.shared_access())
+        {
+            let _ = mount.fs.sync();
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::io::Read for File
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Write back the buffered data first, so it is read from the right offset
+        self.sync()?;
+        Ok(File::read(self, buf)?)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for File
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(File::write(self, buf)?)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.sync()?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Seek for File
+{
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.sync()?;
+        let offset = match pos {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            std::io::SeekFrom::End(delta) => self.metadata()?.size.checked_add_signed(delta),
+        };
+        let offset = offset.ok_or(InvalidArgument)?;
+        File::seek(self, offset)?;
+        Ok(offset)
+    }
+}
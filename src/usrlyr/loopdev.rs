@@ -0,0 +1,105 @@
+use crate::block::{check_blocks, check_range, BlockDevice};
+use crate::error::CNFSError::{FSInternal, InvalidArgument, NoSpace};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::usrlyr::File;
+use crate::vfs::Path;
+
+/// Block device over a [File], to mount a filesystem image stored inside the VFS
+///
+/// The block count is fixed by the size of the file when the device is made, and trailing
+/// bytes that don't make up a whole block are not used. Blocks are read and written at their
+/// offsets through the page cache of the file, so other files opened on the image see the
+/// changes at once.
+///
+/// ```rust
+///  use cnfs::{mount, open, BlockDevice, FileMode, LoopDevice, MountOptions, Path, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  let mut image = open(&Path::new("/disk.img"), FileMode::read | FileMode::write).unwrap();
+///  image.write_all(&[0; 2048]).unwrap();
+///  let device = LoopDevice::new(image, 512).unwrap();
+///  device.write_at(1000, b"cnss").unwrap();
+///  assert_eq!(device.block_count(), 4);
+/// ```
+///
+pub struct LoopDevice
+{
+    block_size: usize,
+    block_count: u64,
+    file: UPCell<File>,
+}
+
+impl LoopDevice
+{
+    /// New a device over the file, with as many blocks as fit in it
+    ///
+    /// The device is read-only unless the file is opened for writing.
+    pub fn new(mut file: File, block_size: usize) -> CNFSResult<Self>
+    {
+        if block_size == 0 { return Err(InvalidArgument); }
+        let size = file.metadata()?.size;
+        Ok(Self { block_size, block_count: size / block_size as u64, file: unsafe { UPCell::new(file) } })
+    }
+
+    /// Returns the path of the image file
+    pub fn path(&self) -> Path
+    {
+        self.file.shared_access().dentry.path.clone()
+    }
+}
+
+impl BlockDevice for LoopDevice
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        self.read_at(block * self.block_size as u64, buffer)
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        check_blocks(self, block, buffer.len())?;
+        self.write_at(block * self.block_size as u64, buffer)
+    }
+
+    fn flush(&self) -> CNFSResult {
+        let file = self.file.exclusive_access();
+        file.dentry.inode_mut().sync()?;
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        let mut file = self.file.exclusive_access();
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            match file.read_at(offset + done as u64, &mut buffer[done..])? {
+                0 => return Err(FSInternal("the loop image has been truncated".into())),
+                bytes => done += bytes,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> CNFSResult {
+        check_range(self, offset, buffer.len())?;
+        let mut file = self.file.exclusive_access();
+        let mut done = 0;
+        while done < buffer.len()
+        {
+            match file.write_at(offset + done as u64, &buffer[done..])? {
+                0 => return Err(NoSpace),
+                bytes => done += bytes,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,35 @@
-use crate::error::CNFSError::PathNotFound;
+use crate::error::CNFSError::{Busy, InvalidArgument, PathNotFound, PermissionDenied};
+use crate::config::config;
 use crate::error::CNFSResult;
-use crate::usrlyr::{File, FileMode};
+use crate::usrlyr::{File, FileMode, LoopDevice, OPEN_FILES};
 use crate::vfs::*;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+/// Block size of the devices made by [mount_loop]
+const LOOP_BLOCK_SIZE: usize = 512;
+
 /// Opens a file at path with the given mode.
 pub fn open(path: &Path, mode: FileMode) -> CNFSResult<File>
 {
     let dentry = lookup_dentry(path);
     match dentry
     {
-        Ok(d) => Ok(File::new(d, mode)),
+        Ok(d) => {
+            if mode.contains(FileMode::write) { d.check_writable()?; }
+            let mut access = 0;
+            if mode.contains(FileMode::read) { access |= MAY_READ; }
+            if mode.contains(FileMode::write) { access |= MAY_WRITE; }
+            let metadata = d.permissions()?;
+            check_permission(&metadata, access)?;
+            // Links only hold the path they point to, which is not written through a file
+            if mode.contains(FileMode::write) && metadata.inode_type == InodeType::Symlink
+            {
+                return Err(InvalidArgument);
+            }
+            Ok(File::new(d, mode))
+        }
         Err(e) => {
             if e == PathNotFound && mode.contains(FileMode::write)
             {
@@ -22,6 +41,25 @@ pub fn open(path: &Path, mode: FileMode) -> CNFSResult<File>
     }
 }
 
+/// Make an anonymous pipe, returning the files opened on its read end and its write end.
+///
+/// The pipe holds up to [VfsConfig::pipe_buffer_size](crate::VfsConfig::pipe_buffer_size) bytes.
+/// Reads and writes never block: they fail with [WouldBlock](crate::CNFSError::WouldBlock)
+/// when the pipe is empty or full, and reading reaches the end of file once the write end is closed.
+pub fn pipe() -> CNFSResult<(File, File)>
+{
+    let dentry = anonymous_pipe(config().pipe_buffer_size);
+    Ok((File::new(dentry.clone(), FileMode::read), File::new(dentry, FileMode::write)))
+}
+
+/// Make a named pipe at the given path, which files opened on it communicate through like [pipe].
+///
+/// The pipe is kept by the VFS, so it lives until it is removed or its filesystem is unmounted.
+pub fn mkfifo(path: &Path) -> CNFSResult
+{
+    create_dentry(path, DentryType::Fifo).map(|_| ())
+}
+
 /// Close a file.
 pub fn close(file: File)
 {
@@ -61,11 +99,29 @@ pub fn create_directory(path: &Path) -> CNFSResult
 }
 
 /// Remove a file or directory at the given path.
+///
+/// A directory something is mounted on is [Busy](crate::CNFSError::Busy) until it is unmounted.
 pub fn remove(path: &Path) -> CNFSResult
 {
     remove_dentry(path)
 }
 
+/// Rename a file or directory at the given path to another name in the same directory.
+///
+/// Moving it to another directory is an [InvalidArgument](crate::CNFSError::InvalidArgument),
+/// and a name already taken is not replaced. A file or directory with files opened at or under
+/// it, or with something mounted at or under it, is [Busy](crate::CNFSError::Busy).
+pub fn rename(from: &Path, to: &Path) -> CNFSResult
+{
+    let origin = lookup_dentry(from)?.origin();
+    let opened = OPEN_FILES.shared_access().iter().any(|f| {
+        let o = f.dentry.alias_of.as_deref().unwrap_or(&f.dentry);
+        Arc::ptr_eq(&o.mount, &origin.mount) && o.path.starts_with(&origin.path)
+    });
+    if opened { return Err(Busy); }
+    rename_dentry(from, to)
+}
+
 /// Check if the path points at an existing file or directory.
 pub fn exists(path: &Path) -> CNFSResult<bool>
 {
@@ -82,4 +138,148 @@ pub fn exists(path: &Path) -> CNFSResult<bool>
             }
         }
     }
-}
\ No newline at end of file
+}
+/// Returns the metadata of the file or directory at the given path.
+pub fn metadata(path: &Path) -> CNFSResult<Metadata>
+{
+    lookup_dentry(path)?.metadata()
+}
+
+/// Returns the entries of the directory at the given path, sorted by name.
+///
+/// Filesystems mounted on its subdirectories don't change the entries.
+pub fn read_dir(path: &Path) -> CNFSResult<Vec<DirEntry>>
+{
+    let dentry = lookup_dentry(path)?;
+    check_permission(&dentry.permissions()?, MAY_READ)?;
+    let mut entries = dentry.read_dir()?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Set the last modification time of the file or directory at the given path, in seconds
+/// since the Unix epoch.
+///
+/// Only the owner and those allowed to write it can change it. Filesystems that don't keep it
+/// fail with [NotImplemented](crate::CNFSError::NotImplemented).
+pub fn set_modified(path: &Path, modified: u64) -> CNFSResult
+{
+    let dentry = lookup_dentry(path)?;
+    let metadata = dentry.permissions()?;
+    if metadata.uid != Some(credentials().uid) { check_permission(&metadata, MAY_WRITE)?; }
+    dentry.set_modified(modified)
+}
+
+/// Set the permission bits of the file or directory at the given path, like `0o644`.
+///
+/// Only the owner and the superuser can change them. Filesystems that don't keep them, which
+/// take them from the mount options, fail with [NotImplemented](crate::CNFSError::NotImplemented).
+pub fn chmod(path: &Path, mode: u32) -> CNFSResult
+{
+    if mode & !0o7777 != 0 { return Err(InvalidArgument); }
+    let dentry = lookup_dentry(path)?;
+    let credentials = credentials();
+    if !credentials.is_root() && dentry.permissions()?.uid != Some(credentials.uid) { return Err(PermissionDenied); }
+    dentry.set_mode(mode)
+}
+
+/// Change the owner of the file or directory at the given path, keeping the IDs given as `None`.
+///
+/// The superuser can give it to anyone, while its owner can only change its group to one
+/// they are in. Filesystems that don't keep owners fail with
+/// [NotImplemented](crate::CNFSError::NotImplemented).
+pub fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> CNFSResult
+{
+    let dentry = lookup_dentry(path)?;
+    let metadata = dentry.permissions()?;
+    let (old_uid, old_gid) = (metadata.uid.unwrap_or(0), metadata.gid.unwrap_or(0));
+    let (uid, gid) = (uid.unwrap_or(old_uid), gid.unwrap_or(old_gid));
+    let credentials = credentials();
+    if !credentials.is_root()
+        && (old_uid != credentials.uid || uid != old_uid || (gid != old_gid && !credentials.in_group(gid)))
+    {
+        return Err(PermissionDenied);
+    }
+    dentry.set_owner(uid, gid)
+}
+
+/// Check the current credentials may read or change the attribute of the given namespace
+fn check_xattr_access(dentry: &Dentry, namespace: XattrNamespace, access: u32) -> CNFSResult
+{
+    match namespace {
+        XattrNamespace::User => check_permission(&dentry.permissions()?, access),
+        XattrNamespace::Trusted if credentials().is_root() => Ok(()),
+        XattrNamespace::Trusted => Err(PermissionDenied),
+    }
+}
+
+/// Returns the value of an extended attribute of the file or directory at the given path.
+///
+/// Names are namespaced: `user.*` attributes need read permission on the inode, while
+/// `trusted.*` ones are only for the superuser. It fails with
+/// [NoAttribute](crate::CNFSError::NoAttribute) if the attribute doesn't exist.
+pub fn get_xattr(path: &Path, name: &str) -> CNFSResult<Vec<u8>>
+{
+    let namespace = xattr_namespace(name)?;
+    let dentry = lookup_dentry(path)?;
+    check_xattr_access(&dentry, namespace, MAY_READ)?;
+    dentry.get_xattr(name)
+}
+
+/// Set an extended attribute of the file or directory at the given path, replacing its value.
+///
+/// `user.*` attributes need write permission on the inode, and the value holds at most
+/// [XATTR_SIZE_MAX] bytes. Filesystems without native extended attributes, like FAT, have
+/// them kept in memory by the VFS until they are unmounted.
+///
+/// ```rust
+///  use cnfs::{get_xattr, mount, set_xattr, write_all, MountOptions, Path, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  write_all(&Path::new("/cnss"), b"cnss").unwrap();
+///  set_xattr(&Path::new("/cnss"), "user.origin", b"recruit").unwrap();
+///  assert_eq!(get_xattr(&Path::new("/cnss"), "user.origin").unwrap(), b"recruit");
+/// ```
+pub fn set_xattr(path: &Path, name: &str, value: &[u8]) -> CNFSResult
+{
+    let namespace = xattr_namespace(name)?;
+    if value.len() > XATTR_SIZE_MAX { return Err(InvalidArgument); }
+    let dentry = lookup_dentry(path)?;
+    check_xattr_access(&dentry, namespace, MAY_WRITE)?;
+    dentry.set_xattr(name, value)
+}
+
+/// Returns the names of the extended attributes of the file or directory at the given path,
+/// sorted, without the `trusted.*` ones unless called by the superuser.
+pub fn list_xattr(path: &Path) -> CNFSResult<Vec<String>>
+{
+    let dentry = lookup_dentry(path)?;
+    check_permission(&dentry.permissions()?, MAY_READ)?;
+    let root = credentials().is_root();
+    let mut names = dentry.list_xattr()?;
+    names.retain(|name| root || xattr_namespace(name) != Ok(XattrNamespace::Trusted));
+    names.sort();
+    Ok(names)
+}
+
+/// Remove an extended attribute of the file or directory at the given path.
+pub fn remove_xattr(path: &Path, name: &str) -> CNFSResult
+{
+    let namespace = xattr_namespace(name)?;
+    let dentry = lookup_dentry(path)?;
+    check_xattr_access(&dentry, namespace, MAY_WRITE)?;
+    dentry.remove_xattr(name)
+}
+
+/// Mount a filesystem image stored in a file inside the VFS at the given path.
+///
+/// The image is opened as a [LoopDevice], for reading only if the options say so, and given
+/// to [mount_by_type] as a [MountSource::Device], so `fs_type` can be `auto`. The image stays
+/// opened while the filesystem is mounted, so the filesystem holding it is busy until then.
+pub fn mount_loop(fs_type: &str, image: &Path, mnt_point: Path, options: MountOptions) -> CNFSResult
+{
+    check_root()?;
+    let mode = if options.read_only { FileMode::read } else { FileMode::read | FileMode::write };
+    let device = LoopDevice::new(open(image, mode)?, LOOP_BLOCK_SIZE)?;
+    mount_by_type(fs_type, MountSource::Device(Arc::new(device)), mnt_point, options)
+}
@@ -1,5 +1,10 @@
+#[cfg(feature = "std")]
+pub mod archive;
 mod file;
+mod loopdev;
 mod lyr;
 
 pub use file::{File, FileMode};
+pub(crate) use file::OPEN_FILES;
+pub use loopdev::LoopDevice;
 pub use lyr::*;
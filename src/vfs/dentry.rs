@@ -1,13 +1,19 @@
-use crate::config::DCACHE_SIZE;
-use crate::error::CNFSError::{InvalidPath, PathNotFound};
+use crate::config::config;
+use crate::error::CNFSError::{AlreadyExisted, Busy, DirectoryNotEmpty, InvalidArgument, InvalidPath,
+                              NoMountedFilesystem, NotImplemented, PathNotFound, PermissionDenied, ReadOnly};
 use crate::error::CNFSResult;
 use crate::sync::UPCell;
-use crate::vfs::mnt::MNTPOINT_TABLE;
+use crate::vfs::fs::{DirEntry, InodeRef, InodeType, Metadata};
+use crate::vfs::mnt::{Mount, MNTPOINT_TABLE};
+use crate::vfs::notify::{notify, rename_watches, EventMask};
 use crate::vfs::path::Path;
+use crate::vfs::perm::{check_permission, check_removable, credentials, is_root, MAY_EXEC, MAY_WRITE};
+use crate::vfs::pipe::{fifo_entries, find_fifo, make_fifo, remove_fifo, rename_fifos};
 use crate::vfs::vinode::{VInode, VInodeRef, VInodeType};
 use alloc::collections::BTreeMap;
-use alloc::string::{String, ToString};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::{Ref, RefMut};
 use lazy_static::lazy_static;
@@ -16,14 +22,79 @@ pub struct Dentry
 {
     pub path: Path,
     pub inode: VInodeRef,
-    pub exist: UPCell<bool>,
+    pub exist: Arc<UPCell<bool>>,
+    pub mount: Arc<Mount>,
+    /// The dentry in the bound filesystem if this one is seen through a bind mount
+    pub alias_of: Option<Arc<Dentry>>,
 }
 
 impl Dentry
 {
-    pub fn new(path: Path, inode: VInodeRef) -> Self
+    pub fn new(path: Path, inode: InodeRef, mount: Arc<Mount>) -> Self
     {
-        Dentry { path, inode, exist: unsafe { UPCell::new(true) } }
+        let inode = VInodeRef::new(inode, &mount.config);
+        Dentry { path, inode, exist: Arc::new(unsafe { UPCell::new(true) }), mount, alias_of: None }
+    }
+
+    /// Returns the root dentry of a mount
+    pub fn root(mount: &Arc<Mount>) -> Arc<Self>
+    {
+        match &mount.bind {
+            Some(origin) => Self::alias(origin, mount.point.clone(), mount.clone()),
+            None => Arc::new(Self::new(mount.point.clone(), mount.fs.root_inode(), mount.clone())),
+        }
+    }
+
+    /// Make a dentry seen at another path through a bind mount, sharing the inode
+    pub fn alias(origin: &Arc<Dentry>, path: Path, mount: Arc<Mount>) -> Arc<Self>
+    {
+        Arc::new(Dentry {
+            path,
+            inode: VInodeRef(origin.inode.0.clone()),
+            exist: origin.exist.clone(),
+            mount,
+            alias_of: Some(origin.clone()),
+        })
+    }
+
+    /// Returns the dentry in the filesystem that the inode belongs to
+    pub fn origin(self: &Arc<Self>) -> Arc<Dentry>
+    {
+        self.alias_of.clone().unwrap_or_else(|| self.clone())
+    }
+
+    /// Look up a child of the dentry
+    pub fn lookup_child(self: &Arc<Self>, name: &str) -> CNFSResult<Arc<Dentry>>
+    {
+        if let Some(origin) = &self.alias_of
+        {
+            let child = origin.lookup_child(name)?;
+            return Ok(Self::alias(&child, self.path.join(name), self.mount.clone()));
+        }
+        let path = self.path.join(name);
+        if let Some(cached) = find_dcache(&path, &self.mount)
+        {
+            DCACHE_STATS.exclusive_access().hits += 1;
+            return Ok(cached);
+        }
+        DCACHE_STATS.exclusive_access().misses += 1;
+        let child = Arc::new(Dentry::new(path, self.inode().lookup(name)?, self.mount.clone()));
+        insert_dcache(child.clone());
+        Ok(child)
+    }
+
+    /// Create a child of the dentry
+    pub fn create_child(self: &Arc<Self>, name: &str, inode_type: DentryType) -> CNFSResult<Arc<Dentry>>
+    {
+        if let Some(origin) = &self.alias_of
+        {
+            let child = origin.create_child(name, inode_type)?;
+            return Ok(Self::alias(&child, self.path.join(name), self.mount.clone()));
+        }
+        let inode = self.inode().create(name, inode_type)?;
+        let child = Arc::new(Dentry::new(self.path.join(name), inode, self.mount.clone()));
+        insert_dcache(child.clone());
+        Ok(child)
     }
 
     pub fn inode(&self) -> Ref<'_, VInode>
@@ -35,103 +106,324 @@ impl Dentry
     {
         self.inode.0.exclusive_access()
     }
+
+    /// Returns the mounts kept busy by the files opened on the dentry, which include the
+    /// bound one when it is seen through a bind mount
+    pub fn mounts(&self) -> impl Iterator<Item=&Arc<Mount>>
+    {
+        core::iter::once(&self.mount).chain(self.alias_of.as_ref().map(|origin| &origin.mount))
+    }
+
+    /// Fails if the mount of the dentry, or the one it is bound from, has been forcibly unmounted
+    pub fn check_mounted(&self) -> CNFSResult
+    {
+        if self.mounts().any(|mount| *mount.aborted.shared_access()) { Err(NoMountedFilesystem) } else { Ok(()) }
+    }
+
+    /// Fails if the dentry is on a read-only mount
+    pub fn check_writable(&self) -> CNFSResult
+    {
+        self.check_mounted()?;
+        if self.mount.options().read_only { Err(ReadOnly) } else { Ok(()) }
+    }
+
+    /// Read the inode data, through the page cache unless the mount or the inode disables it
+    pub fn read(&self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        self.check_mounted()?;
+        // Uncached inodes are called without holding the VInode, as they may look at it
+        let uncached = self.inode().uncached();
+        if let Some(inode) = uncached {
+            inode.read(offset, buffer)
+        } else if self.mount.options().no_cache {
+            self.inode_mut().read_direct(offset, buffer)
+        } else {
+            self.inode_mut().read(offset, buffer)
+        }
+    }
+
+    /// Returns the metadata of the inode, including the data still in the page cache
+    ///
+    /// The permissions the filesystem doesn't keep come from the options of the mount it is in.
+    pub fn metadata(&self) -> CNFSResult<Metadata>
+    {
+        self.check_mounted()?;
+        let uncached = self.inode().uncached();
+        let mut metadata = match uncached {
+            Some(inode) => inode.metadata(),
+            None => self.inode_mut().metadata(),
+        }?;
+        self.fill_permissions(&mut metadata);
+        Ok(metadata)
+    }
+
+    /// Returns the metadata of the inode for checking its permissions, leaving the page cache alone
+    pub fn permissions(&self) -> CNFSResult<Metadata>
+    {
+        self.check_mounted()?;
+        let mut metadata = self.inode().fs_metadata()?;
+        self.fill_permissions(&mut metadata);
+        Ok(metadata)
+    }
+
+    fn fill_permissions(&self, metadata: &mut Metadata)
+    {
+        let mount = self.alias_of.as_ref().map_or(&self.mount, |origin| &origin.mount);
+        mount.options().fill_permissions(metadata);
+    }
+
+    /// Set the permission bits of the inode
+    pub fn set_mode(&self, mode: u32) -> CNFSResult
+    {
+        self.check_writable()?;
+        self.inode().set_mode(mode)
+    }
+
+    /// Set the owner of the inode
+    pub fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult
+    {
+        self.check_writable()?;
+        self.inode().set_owner(uid, gid)
+    }
+
+    /// Returns the entries of the directory
+    pub fn read_dir(&self) -> CNFSResult<Vec<DirEntry>>
+    {
+        self.check_mounted()?;
+        let mut entries = self.inode().read_dir()?;
+        entries.extend(fifo_entries(self));
+        Ok(entries)
+    }
+
+    /// Set the last modification time of the inode
+    pub fn set_modified(&self, modified: u64) -> CNFSResult
+    {
+        self.check_writable()?;
+        self.inode_mut().set_modified(modified)
+    }
+
+    /// Returns the value of an extended attribute, kept by the VFS if the filesystem doesn't
+    pub fn get_xattr(self: &Arc<Self>, name: &str) -> CNFSResult<Vec<u8>>
+    {
+        self.check_mounted()?;
+        let native = self.inode().get_xattr(name);
+        match native {
+            Err(NotImplemented) => {
+                let origin = self.origin();
+                origin.mount.xattrs.get(&origin.path, name)
+            }
+            result => result,
+        }
+    }
+
+    /// Set an extended attribute, kept by the VFS if the filesystem doesn't
+    pub fn set_xattr(self: &Arc<Self>, name: &str, value: &[u8]) -> CNFSResult
+    {
+        self.check_writable()?;
+        let native = self.inode().set_xattr(name, value);
+        match native {
+            Err(NotImplemented) => {
+                let origin = self.origin();
+                origin.mount.xattrs.set(&origin.path, name, value);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the names of the extended attributes, kept by the VFS if the filesystem doesn't
+    pub fn list_xattr(self: &Arc<Self>) -> CNFSResult<Vec<String>>
+    {
+        self.check_mounted()?;
+        let native = self.inode().list_xattr();
+        match native {
+            Err(NotImplemented) => {
+                let origin = self.origin();
+                Ok(origin.mount.xattrs.list(&origin.path))
+            }
+            result => result,
+        }
+    }
+
+    /// Remove an extended attribute, kept by the VFS if the filesystem doesn't
+    pub fn remove_xattr(self: &Arc<Self>, name: &str) -> CNFSResult
+    {
+        self.check_writable()?;
+        let native = self.inode().remove_xattr(name);
+        match native {
+            Err(NotImplemented) => {
+                let origin = self.origin();
+                origin.mount.xattrs.remove(&origin.path, name)
+            }
+            result => result,
+        }
+    }
+
+    /// Write the inode data, through the page cache unless the mount or the inode disables it
+    pub fn write(&self, offset: u64, buffer: &[u8]) -> CNFSResult<usize>
+    {
+        self.check_mounted()?;
+        let uncached = self.inode().uncached();
+        if let Some(inode) = uncached {
+            inode.write(offset, buffer)
+        } else if self.mount.options().no_cache {
+            self.inode_mut().write_direct(offset, buffer)
+        } else {
+            self.inode_mut().write(offset, buffer)
+        }
+    }
+}
+
+/// Counters of the dentry cache lookups
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DcacheStats
+{
+    /// Dentries found in the cache
+    pub hits: u64,
+    /// Dentries looked up in the filesystem
+    pub misses: u64,
 }
 
 lazy_static! {
     pub static ref DCACHE: UPCell<BTreeMap<String, Vec<Arc<Dentry>>>> = unsafe{UPCell::new(BTreeMap::new())};
+    pub(crate) static ref DCACHE_STATS: UPCell<DcacheStats> = unsafe{UPCell::new(DcacheStats::default())};
 }
 
 pub(crate) fn insert_dcache(dentry: Arc<Dentry>)
 {
+    let dcache_size = config().dcache_size;
+    // Dropping a dentry may write its pages back, which can go through a loop device into
+    // another filesystem, so the dropped dentries are kept until the cache is released.
+    let mut dropped = Vec::new();
     let mut dcache = DCACHE.exclusive_access();
-    while dcache.len() >= DCACHE_SIZE
+    // Dentries still used outside the cache, like by open files, are kept, so every file
+    // opened on an inode shares its page cache and locks. The cache may grow past its size
+    // while they are.
+    if dcache.len() >= dcache_size
     {
-        dcache.pop_first();
+        let names: Vec<_> = dcache.keys().cloned().collect();
+        for name in names
+        {
+            if dcache.len() < dcache_size { break; }
+            let vec = dcache.get_mut(&name).unwrap();
+            dropped.push(vec.extract_if(.., |d| Arc::strong_count(d) == 1).collect());
+            if vec.is_empty() { dcache.remove(&name); }
+        }
     }
     let vec = dcache
-        .entry(dentry.path[dentry.path.len() - 1].clone()).or_insert(Vec::new());
-    if vec.iter().find(|x| { x.path == dentry.path }).is_none()
+        .entry(dentry.path[dentry.path.len() - 1].clone()).or_default();
+    if let Some(i) = vec.iter().position(|x| x.path == dentry.path)
     {
-        vec.push(dentry);
+        dropped.push(vec![vec.remove(i)]);
     }
+    vec.push(dentry);
+    drop(dcache);
+}
+
+/// Find a cached dentry of the given path in the given mount
+pub(crate) fn find_dcache(path: &Path, mount: &Arc<Mount>) -> Option<Arc<Dentry>>
+{
+    let dcache = DCACHE.shared_access();
+    dcache.get(path[path.len() - 1].as_str())?.iter()
+        .find(|d| d.path == *path && Arc::ptr_eq(&d.mount, mount)).cloned()
 }
 
 pub(crate) fn remove_dcache(path: &Path)
 {
     let mut dcache = DCACHE.exclusive_access();
-    let cached = dcache.get_mut(path[path.len() - 1].as_str());
-    if let Some(vec) = cached
+    let removed: Vec<_> = match dcache.get_mut(path[path.len() - 1].as_str()) {
+        Some(vec) => vec.extract_if(.., |d| d.path == *path).collect(),
+        None => Vec::new(),
+    };
+    drop(dcache);
+    drop(removed);
+}
+
+/// Remove every dentry whose origin is at or under the given origin from the cache, returning
+/// the removed ones, which includes the ones seen through bind mounts
+fn purge_dcache_origin(origin: &Dentry) -> Vec<Arc<Dentry>>
+{
+    let mut purged = Vec::new();
+    let mut dcache = DCACHE.exclusive_access();
+    for vec in dcache.values_mut()
+    {
+        let (gone, keep): (Vec<_>, Vec<_>) = vec.drain(..).partition(|d| {
+            let o = d.alias_of.as_deref().unwrap_or(d);
+            Arc::ptr_eq(&o.mount, &origin.mount) && o.path.starts_with(&origin.path)
+        });
+        *vec = keep;
+        purged.extend(gone);
+    }
+    dcache.retain(|_, vec| !vec.is_empty());
+    purged
+}
+
+/// Remove every dentry under the given path from the cache, returning the removed ones
+pub(crate) fn purge_dcache(prefix: &Path) -> Vec<Arc<Dentry>>
+{
+    let mut purged = Vec::new();
+    let mut dcache = DCACHE.exclusive_access();
+    for vec in dcache.values_mut()
     {
-        vec.retain(|d| { d.path != *path });
+        let (gone, keep): (Vec<_>, Vec<_>) = vec.drain(..).partition(|d| d.path.starts_with(prefix));
+        *vec = keep;
+        purged.extend(gone);
     }
+    dcache.retain(|_, vec| !vec.is_empty());
+    purged
 }
 
-/// Look up a dentry from the given path
+/// Look up a dentry from the given path, which needs search permission on every directory on the way
 pub(crate) fn lookup_dentry(path: &Path) -> CNFSResult<Arc<Dentry>>
 {
-    if path.len() == 0 { return Err(InvalidPath.into()); }
-    // first we look up the cache
-    let dcache = DCACHE.shared_access();
+    if path.is_empty() { return Err(InvalidPath); }
+    // the path belongs to the deepest mount on the way
+    let chain = MNTPOINT_TABLE.shared_access().resolve(path);
+    let mnt = chain.last().cloned().ok_or(PathNotFound)?;
+    let check = !is_root();
 
-    let mut curr = path.clone();
-    let mut cached = dcache.get(curr[curr.len() - 1].as_str());
+    // without permissions to check, the walk starts from the nearest cached ancestor inside the mount
     let mut cached_dentry: Option<Arc<Dentry>> = None;
-    'outer: loop
+    let mut curr = path.clone();
+    while !check && curr.len() > mnt.point.len()
     {
-        if let Some(mnt) = MNTPOINT_TABLE.shared_access().get(&curr)
-        {
-            cached_dentry = Some(Arc::new(Dentry::new(curr,
-                                                      VInodeRef::new(mnt.fs.root_inode()))));
-            break 'outer;
-        } else if let Some(cached_vec) = cached
+        cached_dentry = find_dcache(&curr, &mnt);
+        if cached_dentry.is_some()
         {
-            for c in cached_vec.iter()
-            {
-                if c.path == curr
-                {
-                    cached_dentry = Some(c.clone());
-                    break 'outer;
-                }
-            }
-            cached = None;
-        } else if let Some(p) = curr.parent() {
-            curr = p;
-            cached = dcache.get(curr[curr.len() - 1].as_str());
-        } else { break; }
-    }
-    drop(dcache);
-    let mut search_parent: Option<Arc<Dentry>> = None;
-    if cached_dentry.is_some() { search_parent = cached_dentry; } else {
-        for mnt in MNTPOINT_TABLE.shared_access().iter()
-        {
-            if path.starts_with(mnt.0)
-            {
-                search_parent = Some(Arc::new(Dentry::new(mnt.0.clone(),
-                                                          VInodeRef::new(mnt.1.fs.root_inode()))));
-                insert_dcache(search_parent.clone().unwrap());
-                break;
-            }
+            // the rest of the way is counted by lookup_child
+            if curr.len() == path.len() { DCACHE_STATS.exclusive_access().hits += 1; }
+            break;
         }
+        curr = curr.parent().unwrap();
     }
 
-    if search_parent.is_none() { return Err(PathNotFound); }
-    let mut curr = search_parent.unwrap();
-    loop {
-        if *path == curr.path
-        {
-            return if *curr.exist.shared_access() { Ok(curr) } else { Err(PathNotFound) };
-        }
-        let name = path[curr.path.len()].to_string();
-        match curr.clone().inode().lookup(name.as_str())
+    // otherwise every directory is searched from the root, entering the mounts on the way
+    let mut curr = cached_dentry.unwrap_or_else(|| Dentry::root(if check { &chain[0] } else { &mnt }));
+    while curr.path.len() < path.len()
+    {
+        // nothing is left under a removed directory, even the named pipes made in it
+        if !*curr.exist.shared_access() { return Err(PathNotFound); }
+        if check { check_search(&curr.permissions()?)?; }
+        let len = curr.path.len() + 1;
+        if let Some(mounted) = chain.iter().find(|m| m.point.len() == len)
         {
-            Ok(inode) => {
-                curr = Arc::new(Dentry::new(path[..curr.path.len() + 1].into(),
-                                            VInodeRef::new(inode)));
-                insert_dcache(curr.clone());
-            }
-            Err(_) => { return Err(PathNotFound); }
+            curr = Dentry::root(mounted);
+            continue;
         }
+        let fifo = if len == path.len() { find_fifo(path, &mnt) } else { None };
+        curr = match fifo {
+            Some(fifo) => fifo,
+            None => curr.lookup_child(path[curr.path.len()].as_str()).map_err(|_| PathNotFound)?,
+        };
     }
+    if *curr.exist.shared_access() { Ok(curr) } else { Err(PathNotFound) }
+}
+
+/// Fails unless the current credentials can search the directory, leaving other inodes to
+/// fail with [NotADirectory](crate::CNFSError::NotADirectory) where they are used as one
+fn check_search(metadata: &Metadata) -> CNFSResult
+{
+    if metadata.inode_type == InodeType::Dir { check_permission(metadata, MAY_EXEC) } else { Ok(()) }
 }
 
 pub type DentryType = VInodeType;
@@ -139,21 +431,107 @@ pub type DentryType = VInodeType;
 /// Create a dentry
 pub(crate) fn create_dentry(path: &Path, inode_type: DentryType) -> CNFSResult<Arc<Dentry>>
 {
-    if path.len() == 0 { return Err(InvalidPath); }
-    let i = lookup_dentry(&path.parent().unwrap())?.inode()
-        .create(path[path.len() - 1].as_str(), inode_type)?;
-    let dentry = Arc::new(Dentry::new(path.clone(),
-                                      VInodeRef::new(i)));
-    insert_dcache(dentry.clone());
-    Ok(dentry)
+    if path.is_empty() { return Err(InvalidPath); }
+    let parent = lookup_dentry(&path.parent().ok_or(InvalidPath)?)?;
+    parent.check_writable()?;
+    let parent_metadata = parent.permissions()?;
+    check_search(&parent_metadata)?;
+    check_permission(&parent_metadata, MAY_WRITE)?;
+    let name = path[path.len() - 1].as_str();
+    // The inode would belong to the owner given by the mount options on a filesystem without
+    // owners, so only that user creates there
+    let credentials = credentials();
+    if inode_type != DentryType::Fifo && !credentials.is_root() && parent_metadata.uid != Some(credentials.uid)
+        && parent.inode().fs_metadata()?.uid.is_none()
+    {
+        return Err(PermissionDenied);
+    }
+    let child = if inode_type == DentryType::Fifo {
+        make_fifo(&parent, name)?
+    } else {
+        if find_fifo(path, &parent.mount).is_some() { return Err(AlreadyExisted); }
+        parent.create_child(name, inode_type)?
+    };
+    // The new inode belongs to its creator, where the filesystem keeps owners
+    let metadata = child.permissions()?;
+    if metadata.uid != Some(credentials.uid) || metadata.gid != Some(credentials.gid)
+    {
+        match child.inode().set_owner(credentials.uid, credentials.gid) {
+            Ok(()) | Err(NotImplemented) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    notify(&child, EventMask::CREATE);
+    Ok(child)
 }
 
 /// Remove a dentry
 pub(crate) fn remove_dentry(path: &Path) -> CNFSResult
 {
-    let dentry = lookup_dentry(&path)?;
+    let dentry = lookup_dentry(path)?;
+    dentry.check_writable()?;
+    let origin = dentry.origin();
+    if MNTPOINT_TABLE.shared_access().iter().iter().any(|mnt| mnt.point == *path || mnt.point == origin.path)
+    {
+        return Err(Busy);
+    }
+    let parent_dentry = lookup_dentry(&path.parent().ok_or(InvalidPath)?)?;
+    if !is_root() { check_removable(&parent_dentry.permissions()?, &dentry.permissions()?)?; }
+    if dentry.inode().pipe().is_some()
+    {
+        remove_fifo(&dentry);
+    } else {
+        // The named pipes are only kept by the VFS, so the filesystem can't see them
+        if !fifo_entries(&dentry).is_empty() { return Err(DirectoryNotEmpty); }
+        parent_dentry.inode().remove(path[path.len() - 1].as_str())?;
+        remove_dcache(&dentry.origin().path);
+    }
     *dentry.exist.exclusive_access() = false;
-    let parent_dentry = lookup_dentry(&path.parent().unwrap())?;
-    remove_dcache(&path);
-    parent_dentry.clone().inode().remove(path[path.len() - 1].as_str())
-}
\ No newline at end of file
+    origin.mount.xattrs.remove_all(&origin.path);
+    notify(&dentry, EventMask::REMOVE);
+    Ok(())
+}
+
+/// Rename a dentry inside its directory
+pub(crate) fn rename_dentry(from: &Path, to: &Path) -> CNFSResult
+{
+    let parent = from.parent().ok_or(InvalidPath)?;
+    if to.parent().as_ref() != Some(&parent) { return Err(InvalidArgument); }
+    let dentry = lookup_dentry(from)?;
+    dentry.check_writable()?;
+    let origin = dentry.origin();
+    let busy = MNTPOINT_TABLE.shared_access().iter().iter().any(|mnt| {
+        mnt.point.starts_with(from) || mnt.point.starts_with(&origin.path)
+            || mnt.bind.as_ref().is_some_and(|b| {
+                Arc::ptr_eq(&b.mount, &origin.mount) && b.path.starts_with(&origin.path)
+            })
+    });
+    if busy { return Err(Busy); }
+    if from == to { return Ok(()); }
+    match lookup_dentry(to) {
+        Ok(_) => return Err(AlreadyExisted),
+        Err(PathNotFound) => {}
+        Err(err) => return Err(err),
+    }
+    let parent_dentry = lookup_dentry(&parent)?;
+    if !is_root() { check_removable(&parent_dentry.permissions()?, &dentry.permissions()?)?; }
+
+    let new_origin = origin.path.rebase(from, to);
+    // The cached pages are written back by the path of their inode in some filesystems
+    for cached in purge_dcache_origin(&origin).iter().chain([&dentry])
+    {
+        cached.inode_mut().flush()?;
+    }
+    if dentry.inode().pipe().is_none()
+    {
+        let (old_name, new_name) = (&from[from.len() - 1], &to[to.len() - 1]);
+        parent_dentry.inode().rename(old_name, new_name)?;
+    }
+    notify(&dentry, EventMask::RENAME);
+    rename_fifos(&origin.mount, &origin.path, &new_origin);
+    origin.mount.xattrs.rename(&origin.path, &new_origin);
+    rename_watches(&origin.mount, &origin.path, &new_origin);
+    purge_dcache_origin(&origin);
+    notify(&lookup_dentry(to)?, EventMask::RENAME);
+    Ok(())
+}
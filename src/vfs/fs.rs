@@ -1,6 +1,8 @@
 use crate::error::CNFSResult;
 use crate::CNFSError::NotImplemented;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 /// The Inode type
@@ -9,6 +11,84 @@ pub enum InodeType {
     Dir,
     /// Regular file
     File,
+    /// Character device, whose data is a stream of bytes that is never cached
+    CharDevice,
+    /// Named pipe, kept by the VFS rather than the filesystem
+    Fifo,
+    /// Symbolic link, whose data is the path it points to, which the VFS does not follow
+    Symlink,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Metadata of an inode
+pub struct Metadata {
+    /// Type of the inode
+    pub inode_type: InodeType,
+    /// Size in bytes
+    pub size: u64,
+    /// Last access time, in seconds since the Unix epoch
+    pub accessed: Option<u64>,
+    /// Last modification time, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// Creation time, in seconds since the Unix epoch
+    pub created: Option<u64>,
+    /// Last status change time, of the data or the metadata, in seconds since the Unix epoch
+    pub changed: Option<u64>,
+    /// User ID of the owner
+    pub uid: Option<u32>,
+    /// Group ID of the owner
+    pub gid: Option<u32>,
+    /// Permission bits, like `0o644`
+    pub mode: Option<u32>,
+}
+
+impl Metadata {
+    /// New a metadata without timestamps and permissions
+    pub fn new(inode_type: InodeType, size: u64) -> Self
+    {
+        Self {
+            inode_type,
+            size,
+            accessed: None,
+            modified: None,
+            created: None,
+            changed: None,
+            uid: None,
+            gid: None,
+            mode: None,
+        }
+    }
+
+    /// Set the owner and permission bits
+    pub fn with_permissions(mut self, uid: u32, gid: u32, mode: u32) -> Self
+    {
+        self.uid = Some(uid);
+        self.gid = Some(gid);
+        self.mode = Some(mode);
+        self
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch, or 0 without the `std` feature
+pub(crate) fn now() -> u64
+{
+    #[cfg(feature = "std")]
+    {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// An entry of a directory
+pub struct DirEntry {
+    /// Name of the entry
+    pub name: String,
+    /// Type of the entry
+    pub inode_type: InodeType,
 }
 
 /// Trait for inode
@@ -48,6 +128,78 @@ pub trait Inode: Send + Sync {
     {
         Err(NotImplemented)
     }
+
+    /// Rename an entry of the directory, failing if the new name is already taken
+    fn rename(&self, _old_name: &str, _new_name: &str) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Returns the metadata of the inode
+    fn metadata(&self) -> CNFSResult<Metadata>
+    {
+        Err(NotImplemented)
+    }
+
+    /// Returns the entries of a directory, without `.` and `..`
+    fn read_dir(&self) -> CNFSResult<Vec<DirEntry>>
+    {
+        Err(NotImplemented)
+    }
+
+    /// Set the last modification time, in seconds since the Unix epoch
+    fn set_modified(&self, _modified: u64) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Set the permission bits, like `0o644`
+    fn set_mode(&self, _mode: u32) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Set the user and group IDs of the owner
+    fn set_owner(&self, _uid: u32, _gid: u32) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Returns the value of an extended attribute, like `user.sha256`
+    ///
+    /// Filesystems without native extended attributes leave these methods unimplemented, and
+    /// the VFS keeps the attributes of their inodes instead.
+    fn get_xattr(&self, _name: &str) -> CNFSResult<Vec<u8>>
+    {
+        Err(NotImplemented)
+    }
+
+    /// Set an extended attribute, replacing its value if it exists
+    fn set_xattr(&self, _name: &str, _value: &[u8]) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Returns the names of the extended attributes
+    fn list_xattr(&self) -> CNFSResult<Vec<String>>
+    {
+        Err(NotImplemented)
+    }
+
+    /// Remove an extended attribute
+    fn remove_xattr(&self, _name: &str) -> CNFSResult
+    {
+        Err(NotImplemented)
+    }
+
+    /// Returns whether the data of the inode can be kept in the page cache
+    ///
+    /// Inodes whose data is generated when it is read, like the files of a [ProcFs](crate::ProcFs),
+    /// return false so that every read and write reaches them.
+    fn cacheable(&self) -> bool
+    {
+        true
+    }
 }
 
 /// Inode reference
@@ -57,4 +209,22 @@ pub type InodeRef = Arc<dyn Inode>;
 pub trait FileSystem: Send + Sync {
     /// Returns the root directory of the filesystem
     fn root_inode(&self) -> InodeRef;
+
+    /// Synchronize the filesystem to its storage
+    fn sync(&self) -> CNFSResult
+    {
+        Ok(())
+    }
+
+    /// Returns the type name of the filesystem, like `vfat`
+    fn fs_type(&self) -> &str
+    {
+        "unknown"
+    }
+
+    /// Returns the name of the filesystem instance, like the device or image it lives on
+    fn name(&self) -> &str
+    {
+        self.fs_type()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use crate::error::CNFSError::WouldBlock;
+use crate::error::CNFSResult;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Type of an advisory lock
+pub enum LockType
+{
+    /// Shared lock, which other files can hold as well
+    Shared,
+    /// Exclusive lock, which no other file can hold at the same time
+    Exclusive,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// A byte-range lock held by an opened file
+pub struct FileLock
+{
+    /// The first locked byte
+    pub start: u64,
+    /// The byte after the last locked one, which is [u64::MAX] for locks through the end of file
+    pub end: u64,
+    /// Type of the lock
+    pub lock_type: LockType,
+}
+
+impl FileLock
+{
+    fn overlaps(&self, start: u64, end: u64) -> bool
+    {
+        self.start < end && start < self.end
+    }
+}
+
+/// The advisory locks of an inode, kept with the owners holding them
+#[derive(Default)]
+pub(crate) struct LockTable
+{
+    locks: Vec<(usize, FileLock)>,
+}
+
+impl LockTable
+{
+    /// Returns a lock of another owner that conflicts with the given one
+    pub fn conflict(&self, owner: usize, lock: &FileLock) -> Option<FileLock>
+    {
+        self.locks.iter()
+            .find(|(o, l)| *o != owner && l.overlaps(lock.start, lock.end)
+                && (l.lock_type == LockType::Exclusive || lock.lock_type == LockType::Exclusive))
+            .map(|(_, l)| *l)
+    }
+
+    /// Set a lock, replacing the locks the owner holds on its range
+    ///
+    /// It fails with [WouldBlock] if another owner holds a conflicting lock.
+    pub fn lock(&mut self, owner: usize, lock: FileLock) -> CNFSResult
+    {
+        if self.conflict(owner, &lock).is_some() { return Err(WouldBlock); }
+        self.unlock(owner, lock.start, lock.end);
+        self.locks.push((owner, lock));
+        Ok(())
+    }
+
+    /// Release the locks the owner holds on a range, splitting those crossing its bounds
+    pub fn unlock(&mut self, owner: usize, start: u64, end: u64)
+    {
+        let mut kept = Vec::new();
+        for (o, l) in self.locks.drain(..)
+        {
+            if o != owner || !l.overlaps(start, end)
+            {
+                kept.push((o, l));
+                continue;
+            }
+            if l.start < start { kept.push((o, FileLock { end: start, ..l })); }
+            if l.end > end { kept.push((o, FileLock { start: end, ..l })); }
+        }
+        self.locks = kept;
+    }
+
+    /// Release every lock the owner holds
+    pub fn unlock_all(&mut self, owner: usize)
+    {
+        self.locks.retain(|(o, _)| *o != owner);
+    }
+}
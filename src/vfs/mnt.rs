@@ -1,42 +1,519 @@
+use crate::config::{config, VfsConfig};
 use crate::error::CNFSResult;
 use crate::sync::UPCell;
-use crate::vfs::dentry::remove_dcache;
-use crate::vfs::fs::FileSystem;
+use crate::vfs::dentry::{purge_dcache, Dentry, DCACHE};
+use crate::vfs::fs::{FileSystem, InodeType, Metadata};
 use crate::vfs::lookup_dentry;
+use crate::vfs::notify::{notify_mount, EventMask};
+use crate::vfs::perm::check_root;
+use crate::vfs::pipe::purge_fifos;
 use crate::vfs::path::Path;
-use crate::CNFSError::{AlreadyMountedPath, NoMountedFilesystem};
+use crate::vfs::xattr::XattrStore;
+use crate::CNFSError::{AlreadyMountedPath, Busy, InvalidArgument, NoMountedFilesystem, NotADirectory};
 use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::Ref;
+use bitflags::bitflags;
+use core::fmt;
 use lazy_static::lazy_static;
 
+/// Permission bits cleared from the defaults of a mount without [MountOptions::umask]
+const DEFAULT_UMASK: u32 = 0o022;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+/// Options of a mounted filesystem
+///
+/// ```rust
+///  use cnfs::MountOptions;
+///  let options = MountOptions::new().read_only(true).label("usb");
+///  assert!(options.read_only && !options.no_cache);
+/// ```
+///
+pub struct MountOptions
+{
+    /// Every mutating call on the filesystem fails with [ReadOnly](crate::CNFSError::ReadOnly)
+    pub read_only: bool,
+    /// Writes bypass the file buffer and go straight through to the filesystem
+    pub synchronous: bool,
+    /// Reads and writes bypass the page cache
+    pub no_cache: bool,
+    /// Label of the mount
+    pub label: Option<String>,
+    /// Overrides the global configuration for this mount
+    ///
+    /// The page size and page cache limits of every inode in the filesystem,
+    /// as well as the default buffer size of files opened in it, come from it.
+    /// It can't be changed by [remount].
+    pub config: Option<VfsConfig>,
+    /// Owner user ID of the inodes whose filesystem doesn't keep one, which is 0 if not set
+    ///
+    /// Apart from the superuser, only this user creates inodes in such a filesystem.
+    pub uid: Option<u32>,
+    /// Owner group ID of the inodes whose filesystem doesn't keep one, which is 0 if not set
+    pub gid: Option<u32>,
+    /// Permission bits cleared from `0o777` for the inodes whose filesystem doesn't keep them,
+    /// which is `0o022` if not set. Files don't get the execute bits.
+    pub umask: Option<u32>,
+}
+
+impl MountOptions
+{
+    /// Returns the default options
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Set the read-only flag
+    pub fn read_only(mut self, read_only: bool) -> Self
+    {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Set the synchronous (write-through) flag
+    pub fn synchronous(mut self, synchronous: bool) -> Self
+    {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Set the no-cache flag
+    pub fn no_cache(mut self, no_cache: bool) -> Self
+    {
+        self.no_cache = no_cache;
+        self
+    }
+
+    /// Set the label
+    pub fn label(mut self, label: &str) -> Self
+    {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Set the configuration override
+    pub fn config(mut self, config: VfsConfig) -> Self
+    {
+        self.config = Some(config);
+        self
+    }
+
+    /// Set the default owner
+    pub fn owner(mut self, uid: u32, gid: u32) -> Self
+    {
+        self.uid = Some(uid);
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Set the default permission mask
+    pub fn umask(mut self, umask: u32) -> Self
+    {
+        self.umask = Some(umask);
+        self
+    }
+
+    /// Fill the permissions that the filesystem doesn't keep with the defaults of the mount
+    pub(crate) fn fill_permissions(&self, metadata: &mut Metadata)
+    {
+        let umask = self.umask.unwrap_or(DEFAULT_UMASK);
+        let mode = if metadata.inode_type == InodeType::Dir { 0o777 } else { 0o666 };
+        metadata.uid.get_or_insert(self.uid.unwrap_or(0));
+        metadata.gid.get_or_insert(self.gid.unwrap_or(0));
+        metadata.mode.get_or_insert(mode & !umask);
+    }
+}
+
+impl fmt::Display for MountOptions
+{
+    /// Formats the options like the fourth field of `/proc/mounts`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.read_only { "ro" } else { "rw" })?;
+        if self.synchronous { write!(f, ",sync")?; }
+        if self.no_cache { write!(f, ",nocache")?; }
+        if let Some(label) = &self.label { write!(f, ",label={}", escape(label))?; }
+        if let Some(uid) = self.uid { write!(f, ",uid={uid}")?; }
+        if let Some(gid) = self.gid { write!(f, ",gid={gid}")?; }
+        if let Some(umask) = self.umask { write!(f, ",umask={umask:03o}")?; }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Information about a mounted filesystem
+pub struct MountInfo
+{
+    /// The mount point
+    pub path: Path,
+    /// Type name of the filesystem
+    pub fs_type: String,
+    /// Name of the filesystem instance
+    pub name: String,
+    /// Options of the mount
+    pub options: MountOptions,
+    /// The bound directory in the filesystem if it is a bind mount
+    pub bind: Option<Path>,
+}
+
+impl fmt::Display for MountInfo
+{
+    /// Formats the mount like a line of `/proc/mounts`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {} 0 0", escape(&self.name), escape(&self.path.to_string()),
+               escape(&self.fs_type), self.options)
+    }
+}
+
+/// Escape the characters that separate fields in `/proc/mounts`
+pub(crate) fn escape(s: &str) -> String
+{
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars()
+    {
+        match c {
+            ' ' => ret.push_str("\\040"),
+            '\t' => ret.push_str("\\011"),
+            '\n' => ret.push_str("\\012"),
+            '\\' => ret.push_str("\\134"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+bitflags! {
+    /// Unmount Flags
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct UmountFlags: u32 {
+        /// Detach the filesystem now and release it when the last opened file is closed
+        const LAZY = 0b00000001;
+        /// Detach the filesystem even if it is busy, cutting off the opened files
+        const FORCE = 0b00000010;
+    }
+}
+
 pub struct Mount
 {
     pub(crate) fs: Arc<dyn FileSystem>,
+    pub(crate) point: Path,
+    /// The bound directory if it is a bind mount, resolved instead of the root of `fs`
+    pub(crate) bind: Option<Arc<Dentry>>,
+    pub(crate) config: VfsConfig,
+    pub(crate) options: UPCell<MountOptions>,
+    /// Stacks of the mounts attached to directories in this filesystem
+    pub(crate) children: UPCell<BTreeMap<Path, MountStack>>,
+    /// Number of opened files in the filesystem
+    pub(crate) open_files: UPCell<usize>,
+    /// Set once the filesystem is forcibly unmounted
+    pub(crate) aborted: UPCell<bool>,
+    /// Set once the filesystem is unmounted, even lazily while files are still opened in it
+    pub(crate) detached: UPCell<bool>,
+    /// Extended attributes of the inodes whose filesystem doesn't keep them
+    pub(crate) xattrs: XattrStore,
+}
+
+impl Mount
+{
+    pub(crate) fn new(fs: Arc<dyn FileSystem>, point: Path, bind: Option<Arc<Dentry>>, config: VfsConfig,
+                      options: MountOptions) -> Self
+    {
+        Self {
+            fs,
+            point,
+            bind,
+            config,
+            options: unsafe { UPCell::new(options) },
+            children: unsafe { UPCell::new(BTreeMap::new()) },
+            open_files: unsafe { UPCell::new(0) },
+            aborted: unsafe { UPCell::new(false) },
+            detached: unsafe { UPCell::new(false) },
+            xattrs: XattrStore::new(),
+        }
+    }
+
+    pub(crate) fn options(&self) -> Ref<'_, MountOptions>
+    {
+        self.options.shared_access()
+    }
+
+    fn info(&self) -> MountInfo
+    {
+        MountInfo {
+            path: self.point.clone(),
+            fs_type: self.fs.fs_type().into(),
+            name: self.fs.name().into(),
+            options: self.options().clone(),
+            bind: self.bind.as_ref().map(|d| d.path.clone()),
+        }
+    }
+
+    /// Returns every mount attached below this one
+    fn descendants(&self) -> Vec<Arc<Mount>>
+    {
+        let mut ret = Vec::new();
+        for stack in self.children.shared_access().values()
+        {
+            visit(stack, &mut ret);
+        }
+        ret
+    }
+}
+
+/// Mounts stacked on one mount point, the last one is the visible one
+pub(crate) type MountStack = Vec<Arc<Mount>>;
+
+/// The mount tree
+///
+/// The root stack holds the mounts at `/`, and every mount holds the stacks mounted on
+/// directories inside it. A path resolves to the deepest visible mount by walking down
+/// from the root, so a mount hides everything mounted below the mount it is stacked on.
+#[derive(Default)]
+pub struct MountTree
+{
+    root: MountStack,
+}
+
+impl MountTree
+{
+    /// Returns the visible mounts on the way from the root to the given path
+    pub fn resolve(&self, path: &Path) -> Vec<Arc<Mount>>
+    {
+        let mut chain: Vec<Arc<Mount>> = Vec::new();
+        let mut curr = match self.root.last() {
+            Some(mnt) => mnt.clone(),
+            None => return chain,
+        };
+        chain.push(curr.clone());
+        for i in 2..=path.len()
+        {
+            let next = curr.children.shared_access().get(&Path::from(&path[..i]))
+                .and_then(|stack| stack.last().cloned());
+            if let Some(mnt) = next
+            {
+                chain.push(mnt.clone());
+                curr = mnt;
+            }
+        }
+        chain
+    }
+
+    /// Returns the visible mount whose mount point is the given path
+    pub fn top(&self, path: &Path) -> Option<Arc<Mount>>
+    {
+        self.resolve(path).pop().filter(|mnt| mnt.point == *path)
+    }
+
+    /// Mount on top of the stack at the mount point
+    fn push(&mut self, mnt: Arc<Mount>)
+    {
+        if mnt.point.len() == 1
+        {
+            self.root.push(mnt);
+            return;
+        }
+        let parent = self.resolve(&mnt.point).into_iter().rev()
+            .find(|m| m.point.len() < mnt.point.len())
+            .expect("A mount point must be in a mounted filesystem");
+        parent.children.exclusive_access().entry(mnt.point.clone()).or_default().push(mnt);
+    }
+
+    /// Remove a visible mount, revealing the one stacked under it
+    fn pop(&mut self, mnt: &Arc<Mount>)
+    {
+        if mnt.point.len() == 1
+        {
+            self.root.retain(|m| !Arc::ptr_eq(m, mnt));
+            return;
+        }
+        let parent = self.resolve(&mnt.point).into_iter().rev()
+            .find(|m| m.point.len() < mnt.point.len())
+            .expect("A mount point must be in a mounted filesystem");
+        let mut children = parent.children.exclusive_access();
+        if let Some(stack) = children.get_mut(&mnt.point)
+        {
+            stack.retain(|m| !Arc::ptr_eq(m, mnt));
+            if stack.is_empty() { children.remove(&mnt.point); }
+        }
+    }
+
+    /// Returns every mount, parents before children and lower mounts before upper ones
+    pub fn iter(&self) -> Vec<Arc<Mount>>
+    {
+        let mut ret = Vec::new();
+        visit(&self.root, &mut ret);
+        ret
+    }
+}
+
+fn visit(stack: &MountStack, ret: &mut Vec<Arc<Mount>>)
+{
+    for mnt in stack
+    {
+        ret.push(mnt.clone());
+        for child in mnt.children.shared_access().values()
+        {
+            visit(child, ret);
+        }
+    }
 }
 
 lazy_static! {
-    pub static ref MNTPOINT_TABLE: UPCell<BTreeMap<Path, Mount>> = unsafe{UPCell::new(BTreeMap::<Path, Mount>::new())};
+    pub static ref MNTPOINT_TABLE: UPCell<MountTree> = unsafe{UPCell::new(MountTree::default())};
 }
 
 /// Mount a filesystem at the given path.
-pub fn mount(fs: Arc<dyn FileSystem>, mnt_point: Path) -> CNFSResult
+///
+/// If the path is a mount point already, the filesystem is stacked on top of the mounted one,
+/// hiding it until it is unmounted. Like every change to the mount table, it is only allowed
+/// to the superuser, failing with [PermissionDenied](crate::CNFSError::PermissionDenied).
+pub fn mount(fs: Arc<dyn FileSystem>, mnt_point: Path, options: MountOptions) -> CNFSResult
 {
+    check_root()?;
+    let config = options.config.unwrap_or_else(config);
+    config.validate()?;
     if mnt_point.to_string() != "/" {
-        let dentry = lookup_dentry(&mnt_point)?;
-        remove_dcache(&dentry.path);
+        lookup_dentry(&mnt_point)?;
     }
     let mut table = MNTPOINT_TABLE.exclusive_access();
-    let already_mounted = table.get(&mnt_point);
-    if already_mounted.is_some() { return Err(AlreadyMountedPath); }
-    table.insert(mnt_point.clone(), Mount { fs });
+    let already_mounted = table.top(&mnt_point);
+    if already_mounted.is_some_and(|mnt| Arc::ptr_eq(&mnt.fs, &fs)) { return Err(AlreadyMountedPath); }
+    table.push(Arc::new(Mount::new(fs, mnt_point.clone(), None, config, options)));
+    drop(table);
+    purge_dcache(&mnt_point);
+    notify_mount(&mnt_point, EventMask::MOUNT);
     Ok(())
 }
-/// Mount the filesystem at the given path.
+
+/// Make the directory at `src` visible at `dst` as well.
+///
+/// The directory may be inside any mounted filesystem, including another bind mount, and
+/// filesystems mounted under `src` are not carried over. Files opened through the new mount
+/// keep the filesystem of `src` busy as well, and unmounting it forcibly aborts the new mount.
+/// Both paths must be directories. The `options` apply to the new mount only, so a read-only
+/// view of a writable directory can be made like:
+///
+/// ```rust,no_run
+///  use cnfs::{bind_mount, MountOptions, Path};
+///  bind_mount(&Path::new("/data/shared"), &Path::new("/sandbox/shared"),
+///             MountOptions::new().read_only(true)).unwrap();
+/// ```
+///
+pub fn bind_mount(src: &Path, dst: &Path, options: MountOptions) -> CNFSResult
+{
+    check_root()?;
+    let origin = lookup_dentry(src)?.origin();
+    if origin.permissions()?.inode_type != InodeType::Dir { return Err(NotADirectory); }
+    if lookup_dentry(dst)?.permissions()?.inode_type != InodeType::Dir { return Err(NotADirectory); }
+    if options.config.is_some_and(|c| c != origin.mount.config) { return Err(InvalidArgument); }
+    let mnt = Mount::new(origin.mount.fs.clone(), dst.clone(), Some(origin.clone()),
+                         origin.mount.config, options);
+    MNTPOINT_TABLE.exclusive_access().push(Arc::new(mnt));
+    purge_dcache(dst);
+    notify_mount(dst, EventMask::MOUNT);
+    Ok(())
+}
+
+/// Change the options of the filesystem mounted at the given path.
+///
+/// Data cached for the filesystem is written back before the new options take effect.
+pub fn remount(mnt_point: Path, options: MountOptions) -> CNFSResult
+{
+    check_root()?;
+    let mnt = MNTPOINT_TABLE.shared_access().top(&mnt_point).ok_or(NoMountedFilesystem)?;
+    if options.config.is_some_and(|c| c != mnt.config) { return Err(InvalidArgument); }
+    let cached: Vec<_> = DCACHE.shared_access().values().flatten()
+        .filter(|d| Arc::ptr_eq(&d.mount, &mnt) && *d.exist.shared_access()).cloned().collect();
+    for dentry in cached
+    {
+        dentry.inode_mut().invalidate()?;
+    }
+    *mnt.options.exclusive_access() = options;
+    Ok(())
+}
+
+/// Unmount the filesystem at the given path.
+///
+/// This is a convenience function for using [umount_with_flags] without flags.
 pub fn umount(mnt_point: Path) -> CNFSResult
 {
-    let mut table = MNTPOINT_TABLE.exclusive_access();
-    let already_mounted = table.get(&mnt_point);
-    if already_mounted.is_none() { return Err(NoMountedFilesystem); }
-    table.remove(&mnt_point);
+    umount_with_flags(mnt_point, UmountFlags::empty())
+}
+
+/// Unmount the filesystem at the given path.
+///
+/// The cached data is written back and the cached dentries under the mount point are dropped.
+/// If other filesystems are stacked on the mount point, only the visible one is unmounted,
+/// revealing the one under it.
+///
+/// Without flags, it fails with [Busy](crate::CNFSError::Busy) while files are opened in the
+/// filesystem or other filesystems are mounted inside it. With [UmountFlags::LAZY] or
+/// [UmountFlags::FORCE] those mounts are detached as well.
+pub fn umount_with_flags(mnt_point: Path, flags: UmountFlags) -> CNFSResult
+{
+    check_root()?;
+    let mnt = MNTPOINT_TABLE.shared_access().top(&mnt_point).ok_or(NoMountedFilesystem)?;
+    let force = flags.contains(UmountFlags::FORCE);
+    let nested = mnt.descendants();
+    if !flags.intersects(UmountFlags::LAZY | UmountFlags::FORCE)
+        && (*mnt.open_files.shared_access() != 0 || !nested.is_empty())
+    {
+        return Err(Busy);
+    }
+
+    let detached: Vec<_> = nested.into_iter().chain([mnt.clone()]).collect();
+    let cached: Vec<_> = DCACHE.shared_access().values().flatten()
+        .filter(|d| detached.iter().any(|m| Arc::ptr_eq(&d.mount, m))).cloned().collect();
+    for dentry in cached
+    {
+        let mut inode = dentry.inode_mut();
+        let flushed = inode.flush().and_then(|_| inode.take_error());
+        if !force { flushed?; }
+    }
+    for m in detached.iter()
+    {
+        let synced = m.fs.sync();
+        if !force { synced?; }
+    }
+
+    MNTPOINT_TABLE.exclusive_access().pop(&mnt);
+    purge_dcache(&mnt_point);
+    purge_fifos(&detached);
+    for m in detached
+    {
+        *m.detached.exclusive_access() = true;
+        if force { *m.aborted.exclusive_access() = true; }
+    }
+    notify_mount(&mnt_point, EventMask::UMOUNT);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Returns an iterator over the mounted filesystems, in mount tree order.
+///
+/// Filesystems stacked on one mount point are listed from the bottom to the top.
+pub fn mounts() -> impl Iterator<Item=MountInfo>
+{
+    let mounts = MNTPOINT_TABLE.shared_access().iter();
+    mounts.into_iter().map(|mnt| mnt.info())
+}
+
+/// Returns the mount point of the filesystem that the given path belongs to.
+pub fn mount_point_of(path: &Path) -> CNFSResult<Path>
+{
+    let mnt = MNTPOINT_TABLE.shared_access().resolve(path).pop();
+    mnt.map(|m| m.point.clone()).ok_or(NoMountedFilesystem)
+}
+
+/// Render the mount table in the format of `/proc/mounts`.
+pub fn proc_mounts() -> String
+{
+    let mut ret = String::new();
+    for info in mounts()
+    {
+        ret += &info.to_string();
+        ret.push('\n');
+    }
+    ret
+}
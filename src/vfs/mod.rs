@@ -1,10 +1,29 @@
 mod dentry;
 mod fs;
+mod lock;
 mod mnt;
+mod notify;
 mod path;
+mod perm;
+mod pipe;
+mod registry;
 mod vinode;
+mod xattr;
 
 pub(crate) use dentry::*;
-pub use fs::{FileSystem, Inode, InodeRef, InodeType};
-pub use mnt::{mount, umount};
+pub use fs::{DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata};
+pub(crate) use fs::now;
+pub use lock::{FileLock, LockType};
+pub use mnt::{bind_mount, mount, mount_point_of, mounts, proc_mounts, remount, umount, umount_with_flags, MountInfo,
+              MountOptions, UmountFlags};
+pub(crate) use mnt::escape;
+pub(crate) use notify::notify;
+pub use notify::{watch, Event, EventMask, Watch};
 pub use path::*;
+pub(crate) use perm::{check_permission, check_root, MAY_READ, MAY_WRITE};
+pub use perm::{credentials, set_credentials, with_credentials, Credentials};
+pub(crate) use pipe::{anonymous_pipe, PipeEnd};
+pub(crate) use xattr::{xattr_namespace, XattrNamespace};
+pub use xattr::{XATTR_NAME_MAX, XATTR_SIZE_MAX};
+pub use registry::{filesystems, mount_by_type, mount_partition, probe_filesystem, register_filesystem,
+                   unregister_filesystem, FileSystemFactory, MountSource};
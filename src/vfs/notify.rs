@@ -0,0 +1,207 @@
+use crate::config::config;
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::dentry::{lookup_dentry, Dentry};
+use crate::vfs::mnt::Mount;
+use crate::vfs::path::Path;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use lazy_static::lazy_static;
+
+bitflags! {
+    /// Kinds of events a [Watch] reports
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct EventMask: u32 {
+        /// A file or directory was created
+        const CREATE = 0b00000001;
+        /// A file or directory was removed
+        const REMOVE = 0b00000010;
+        /// Data was written to a file
+        const MODIFY = 0b00000100;
+        /// A file opened for writing was closed
+        const CLOSE_WRITE = 0b00001000;
+        /// A filesystem was mounted
+        const MOUNT = 0b00010000;
+        /// A filesystem was unmounted
+        const UMOUNT = 0b00100000;
+        /// A file or directory was renamed, which is reported at both its old and new path
+        const RENAME = 0b01000000;
+        /// The queue of the watch was full, so the events after it were dropped
+        const OVERFLOW = 0b10000000;
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// An event reported by a [Watch]
+pub struct Event
+{
+    /// The path of the file or directory the event happened to, under the watched path
+    pub path: Path,
+    /// What happened, which is one of the kinds in [EventMask]
+    pub kind: EventMask,
+}
+
+struct WatchState
+{
+    /// The path given to [watch]
+    path: Path,
+    /// The path watched in the filesystem it belongs to, which follows renames
+    origin_path: UPCell<Path>,
+    /// The mount of the filesystem the watched path belongs to
+    origin_mount: Arc<Mount>,
+    mask: EventMask,
+    events: UPCell<VecDeque<Event>>,
+    /// Number of events queued before new ones are dropped
+    capacity: usize,
+}
+
+impl WatchState
+{
+    /// Queue an event, merging it into the newest one if they are the same
+    fn push(&self, event: Event)
+    {
+        let mut events = self.events.exclusive_access();
+        if events.back() == Some(&event) { return; }
+        if events.len() >= self.capacity
+        {
+            if events.back().map(|e| e.kind) != Some(EventMask::OVERFLOW)
+            {
+                events.push_back(Event { path: self.path.clone(), kind: EventMask::OVERFLOW });
+            }
+            return;
+        }
+        events.push_back(event);
+    }
+}
+
+lazy_static! {
+    /// Every watch not yet dropped
+    static ref WATCHES: UPCell<Vec<Arc<WatchState>>> = unsafe{UPCell::new(Vec::new())};
+}
+
+/// A watch on a path, which queues the events of the path and its children until they are taken
+///
+/// The watch stops when it is dropped.
+pub struct Watch
+{
+    state: Arc<WatchState>,
+}
+
+impl Watch
+{
+    /// Returns the watched path
+    pub fn path(&self) -> &Path
+    {
+        &self.state.path
+    }
+
+    /// Take the oldest event not yet taken
+    pub fn next_event(&self) -> Option<Event>
+    {
+        self.state.events.exclusive_access().pop_front()
+    }
+
+    /// Take every event not yet taken, from the oldest to the newest
+    pub fn events(&self) -> Vec<Event>
+    {
+        self.state.events.exclusive_access().drain(..).collect()
+    }
+}
+
+impl Iterator for Watch
+{
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.next_event()
+    }
+}
+
+impl Drop for Watch
+{
+    fn drop(&mut self) {
+        WATCHES.exclusive_access().retain(|w| !Arc::ptr_eq(w, &self.state));
+    }
+}
+
+/// Watch the file or directory at the given path for the events in the mask.
+///
+/// Events happening to the path itself and, for directories, to its children are reported, so
+/// a directory is watched without the files in its subdirectories. Files are watched by what
+/// they are rather than by the path they were changed through, so changes made through a bind
+/// mount are reported at the watched path too. Mount events are reported for mount points.
+///
+/// Up to [VfsConfig::watch_queue_size](crate::VfsConfig::watch_queue_size) events are queued,
+/// and an event repeating the newest one, like a file written again, is merged into it. Once
+/// the queue is full, the new events are dropped and [EventMask::OVERFLOW] is queued at the
+/// watched path whatever the mask.
+///
+/// A renamed file is reported with [EventMask::RENAME] at its old path and then at its new one,
+/// and a watch on a renamed file or directory follows it to its new path.
+///
+/// ```rust,no_run
+///  use cnfs::{watch, write_all, EventMask, Path};
+///  let watch = watch(&Path::new("/inbox"), EventMask::CREATE | EventMask::CLOSE_WRITE).unwrap();
+///  write_all(&Path::new("/inbox/mail"), b"cnss").unwrap();
+///  for event in watch.events() {
+///      println!("{:?} {}", event.kind, event.path);
+///  }
+/// ```
+pub fn watch(path: &Path, mask: EventMask) -> CNFSResult<Watch>
+{
+    let origin = lookup_dentry(path)?.origin();
+    let state = Arc::new(WatchState {
+        path: path.clone(),
+        origin_path: unsafe { UPCell::new(origin.path.clone()) },
+        origin_mount: origin.mount.clone(),
+        mask,
+        events: unsafe { UPCell::new(VecDeque::new()) },
+        capacity: config().watch_queue_size,
+    });
+    WATCHES.exclusive_access().push(state.clone());
+    Ok(Watch { state })
+}
+
+/// Report an event that happened to the file or directory of the given dentry
+pub(crate) fn notify(dentry: &Arc<Dentry>, kind: EventMask)
+{
+    let origin = dentry.origin();
+    for w in WATCHES.shared_access().iter()
+    {
+        if !w.mask.contains(kind) || !Arc::ptr_eq(&w.origin_mount, &origin.mount) { continue; }
+        let watched = w.origin_path.shared_access();
+        let path = if origin.path == *watched {
+            w.path.clone()
+        } else if origin.path.parent().as_ref() == Some(&*watched) {
+            w.path.join(&origin.path[origin.path.len() - 1])
+        } else {
+            continue;
+        };
+        w.push(Event { path, kind });
+    }
+}
+
+/// Keep watching the files and directories at or under a renamed path of the given mount
+pub(crate) fn rename_watches(mount: &Arc<Mount>, from: &Path, to: &Path)
+{
+    for w in WATCHES.shared_access().iter()
+    {
+        if !Arc::ptr_eq(&w.origin_mount, mount) { continue; }
+        let mut watched = w.origin_path.exclusive_access();
+        if watched.starts_with(from) { *watched = watched.rebase(from, to); }
+    }
+}
+
+/// Report a mount event at the given mount point
+pub(crate) fn notify_mount(mnt_point: &Path, kind: EventMask)
+{
+    for w in WATCHES.shared_access().iter()
+    {
+        if w.mask.contains(kind) && (&w.path == mnt_point || mnt_point.parent().as_ref() == Some(&w.path))
+        {
+            w.push(Event { path: mnt_point.clone(), kind });
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use core::cmp::Ordering;
+use core::fmt;
 use core::ops::{Index, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
 use core::slice::Iter;
 
-#[derive(Debug, Eq, Ord, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone)]
 /// Path struct
 pub struct Path
 {
@@ -62,33 +62,61 @@ impl Path
         Some(ret)
     }
 
+    /// Returns the path with a name appended
+    ///
+    /// ```rust
+    ///  use cnfs::Path;
+    ///  assert_eq!(Path::new("/home").join("cnss"), Path::new("/home/cnss"));
+    ///  assert_eq!(Path::new("/").join("home"), Path::new("/home"));
+    /// ```
+    ///
+    pub fn join(&self, name: &str) -> Path
+    {
+        Path::new(&(self.to_string() + "/" + name))
+    }
+
     /// Check if the path is starts with given path
     pub fn starts_with(&self, item: &Self) -> bool
     {
         self.names.starts_with(&item.names)
     }
 
+    /// Returns the path with its beginning `from` replaced by `to`, for a path under a renamed one
+    pub(crate) fn rebase(&self, from: &Self, to: &Self) -> Self
+    {
+        let mut names = to.names.clone();
+        names.extend_from_slice(&self.names[from.len()..]);
+        Self { names }
+    }
+
     /// Returns the length of the path.
     pub fn len(&self) -> usize
     {
         self.names.len()
     }
 
+    /// Returns true if the path has no component.
+    pub fn is_empty(&self) -> bool
+    {
+        self.names.is_empty()
+    }
+
     /// Iterator
     pub fn iter(&self) -> Iter<'_, String>
     {
         self.names.iter()
     }
+}
 
-    /// Convert the path to string.
-    pub fn to_string(&self) -> String
-    {
+impl fmt::Display for Path
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut ret = self.names.join("/");
         if ret.len() != 1
         {
             assert_eq!(ret.remove(0), '/');
         }
-        ret
+        write!(f, "{}", ret)
     }
 }
 
@@ -156,16 +184,3 @@ impl From<&[String]> for Path
         }
     }
 }
-
-impl PartialEq for Path {
-    fn eq(&self, other: &Self) -> bool {
-        self.names.eq(&other.names)
-    }
-}
-
-impl PartialOrd for Path
-{
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.names.partial_cmp(&other.names)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,156 @@
+use crate::error::CNFSError::PermissionDenied;
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::fs::{InodeType, Metadata};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+/// Permission to read a file or list a directory
+pub(crate) const MAY_READ: u32 = 0o4;
+/// Permission to write a file or change the entries of a directory
+pub(crate) const MAY_WRITE: u32 = 0o2;
+/// Permission to search a directory
+pub(crate) const MAY_EXEC: u32 = 0o1;
+/// Only the owners of an entry or of the directory can remove it from a directory with this bit
+const STICKY: u32 = 0o1000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Credentials that the VFS checks the permission bits against
+///
+/// ```rust
+///  use cnfs::Credentials;
+///  let tenant = Credentials::new(1000, 1000).groups(&[100]);
+///  assert!(tenant.in_group(100) && !tenant.is_root());
+/// ```
+///
+pub struct Credentials
+{
+    /// User ID
+    pub uid: u32,
+    /// Primary group ID
+    pub gid: u32,
+    /// Supplementary group IDs
+    pub groups: Vec<u32>,
+}
+
+impl Credentials
+{
+    /// New the credentials of a user without supplementary groups
+    pub fn new(uid: u32, gid: u32) -> Self
+    {
+        Self { uid, gid, groups: Vec::new() }
+    }
+
+    /// Returns the credentials of the superuser, which pass every permission check
+    pub fn root() -> Self
+    {
+        Self::new(0, 0)
+    }
+
+    /// Set the supplementary groups
+    pub fn groups(mut self, groups: &[u32]) -> Self
+    {
+        self.groups = groups.into();
+        self
+    }
+
+    /// Returns whether they are the credentials of the superuser
+    pub fn is_root(&self) -> bool
+    {
+        self.uid == 0
+    }
+
+    /// Returns whether the group is the primary or a supplementary one
+    pub fn in_group(&self, gid: u32) -> bool
+    {
+        self.gid == gid || self.groups.contains(&gid)
+    }
+
+    /// Returns whether the access asked for, made of `MAY_*` bits, is allowed on the inode
+    fn permits(&self, metadata: &Metadata, access: u32) -> bool
+    {
+        let mode = metadata.mode.unwrap_or(0);
+        if self.is_root()
+        {
+            // The superuser executes files only if someone can
+            return access & MAY_EXEC == 0 || metadata.inode_type == InodeType::Dir || mode & 0o111 != 0;
+        }
+        let granted = if metadata.uid == Some(self.uid) {
+            mode >> 6
+        } else if metadata.gid.is_some_and(|gid| self.in_group(gid)) {
+            mode >> 3
+        } else {
+            mode
+        };
+        granted & access == access
+    }
+}
+
+lazy_static! {
+    static ref CREDENTIALS: UPCell<Credentials> = unsafe{UPCell::new(Credentials::root())};
+}
+
+/// Set the credentials of the calling context, which every following call is checked against.
+///
+/// The credentials are the superuser's until they are set.
+pub fn set_credentials(credentials: Credentials)
+{
+    *CREDENTIALS.exclusive_access() = credentials;
+}
+
+/// Returns the credentials of the calling context.
+pub fn credentials() -> Credentials
+{
+    CREDENTIALS.shared_access().clone()
+}
+
+/// Run a function with the given credentials, restoring the current ones afterwards.
+///
+/// ```rust
+///  use cnfs::{create_directory, mount, with_credentials, CNFSError, Credentials, MountOptions, Path, RamFs};
+///  use std::sync::Arc;
+///  mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new()).unwrap();
+///  let created = with_credentials(Credentials::new(1000, 1000), || create_directory(&Path::new("/home")));
+///  assert_eq!(created, Err(CNFSError::PermissionDenied));
+/// ```
+pub fn with_credentials<T>(credentials: Credentials, f: impl FnOnce() -> T) -> T
+{
+    let saved = core::mem::replace(&mut *CREDENTIALS.exclusive_access(), credentials);
+    let ret = f();
+    *CREDENTIALS.exclusive_access() = saved;
+    ret
+}
+
+/// Fails with [PermissionDenied] unless the current credentials allow the access on the inode
+pub(crate) fn check_permission(metadata: &Metadata, access: u32) -> CNFSResult
+{
+    if CREDENTIALS.shared_access().permits(metadata, access) { Ok(()) } else { Err(PermissionDenied) }
+}
+
+/// Fails with [PermissionDenied] unless the current credentials may remove an entry from a
+/// directory, given the metadata of both
+pub(crate) fn check_removable(dir: &Metadata, entry: &Metadata) -> CNFSResult
+{
+    check_permission(dir, MAY_WRITE | MAY_EXEC)?;
+    let credentials = CREDENTIALS.shared_access();
+    if dir.mode.unwrap_or(0) & STICKY == 0 || credentials.is_root()
+        || dir.uid == Some(credentials.uid) || entry.uid == Some(credentials.uid)
+    {
+        Ok(())
+    } else {
+        Err(PermissionDenied)
+    }
+}
+
+/// Fails with [PermissionDenied] unless the current credentials are the superuser's, which
+/// changing the mount table or the registered devices needs
+pub(crate) fn check_root() -> CNFSResult
+{
+    if is_root() { Ok(()) } else { Err(PermissionDenied) }
+}
+
+/// Returns whether the current credentials are the superuser's, which skips every check
+pub(crate) fn is_root() -> bool
+{
+    CREDENTIALS.shared_access().is_root()
+}
@@ -0,0 +1,306 @@
+use crate::config::VfsConfig;
+use crate::error::CNFSError::{AlreadyExisted, BrokenPipe, InvalidPath, PathNotFound, WouldBlock};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::dentry::Dentry;
+use crate::vfs::fs::{DirEntry, FileSystem, Inode, InodeRef, InodeType, Metadata};
+use crate::vfs::mnt::{Mount, MountOptions};
+use crate::vfs::path::Path;
+use crate::vfs::perm::credentials;
+use crate::vfs::vinode::VInodeRef;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+use lazy_static::lazy_static;
+
+struct PipeState
+{
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    readers: usize,
+    writers: usize,
+    /// Whether a reader has been opened since the pipe was last closed on both ends
+    had_reader: bool,
+    /// Whether a writer has been opened since the pipe was last closed on both ends
+    had_writer: bool,
+}
+
+/// A bounded byte buffer between the files opened for reading and for writing on a pipe
+///
+/// Reads and writes never block. Reading an empty pipe fails with [WouldBlock] until the last
+/// writer is closed, after which it reads the end of file. Writing to a full pipe fails with
+/// [WouldBlock], and writing after the last reader is closed fails with [BrokenPipe]. Data
+/// written before any reader is opened waits in the buffer.
+pub(crate) struct Pipe
+{
+    state: UPCell<PipeState>,
+}
+
+impl Pipe
+{
+    pub fn new(capacity: usize) -> Self
+    {
+        let state = PipeState {
+            buffer: VecDeque::new(),
+            capacity,
+            readers: 0,
+            writers: 0,
+            had_reader: false,
+            had_writer: false,
+        };
+        Self { state: unsafe { UPCell::new(state) } }
+    }
+
+    /// Open an end of the pipe, which is closed when the returned handle is dropped
+    pub fn open(self: &Arc<Self>, read: bool, write: bool) -> PipeEnd
+    {
+        let mut state = self.state.exclusive_access();
+        if read
+        {
+            state.readers += 1;
+            state.had_reader = true;
+        }
+        if write
+        {
+            state.writers += 1;
+            state.had_writer = true;
+        }
+        PipeEnd { pipe: self.clone(), read, write }
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        let mut state = self.state.exclusive_access();
+        if state.buffer.is_empty()
+        {
+            return if state.writers == 0 && state.had_writer { Ok(0) } else { Err(WouldBlock) };
+        }
+        let len = min(buffer.len(), state.buffer.len());
+        for (dst, src) in buffer.iter_mut().zip(state.buffer.drain(..len))
+        {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    fn write(&self, buffer: &[u8]) -> CNFSResult<usize>
+    {
+        let mut state = self.state.exclusive_access();
+        if state.readers == 0 && state.had_reader { return Err(BrokenPipe); }
+        let len = min(buffer.len(), state.capacity - state.buffer.len());
+        if len == 0 && !buffer.is_empty() { return Err(WouldBlock); }
+        state.buffer.extend(&buffer[..len]);
+        Ok(len)
+    }
+
+    fn len(&self) -> usize
+    {
+        self.state.shared_access().buffer.len()
+    }
+}
+
+/// An opened end of a pipe
+pub(crate) struct PipeEnd
+{
+    pipe: Arc<Pipe>,
+    read: bool,
+    write: bool,
+}
+
+impl Drop for PipeEnd
+{
+    fn drop(&mut self) {
+        let mut state = self.pipe.state.exclusive_access();
+        if self.read { state.readers -= 1; }
+        if self.write { state.writers -= 1; }
+        if state.readers == 0 && state.writers == 0
+        {
+            // Like a FIFO reopened later, the pipe starts over
+            state.buffer.clear();
+            state.had_reader = false;
+            state.had_writer = false;
+        }
+    }
+}
+
+/// Inode of a pipe, whose data never goes through the page cache
+struct PipeInode
+{
+    pipe: Arc<Pipe>,
+    /// Permission bits
+    mode: UPCell<u32>,
+    /// User and group IDs of the owner
+    owner: UPCell<(u32, u32)>,
+}
+
+impl Inode for PipeInode
+{
+    fn read(&self, _offset: u64, buffer: &mut [u8]) -> CNFSResult<usize> {
+        self.pipe.read(buffer)
+    }
+
+    fn write(&self, _offset: u64, buffer: &[u8]) -> CNFSResult<usize> {
+        self.pipe.write(buffer)
+    }
+
+    fn sync(&self) -> CNFSResult {
+        Ok(())
+    }
+
+    fn metadata(&self) -> CNFSResult<Metadata> {
+        let (uid, gid) = *self.owner.shared_access();
+        let metadata = Metadata::new(InodeType::Fifo, self.pipe.len() as u64);
+        Ok(metadata.with_permissions(uid, gid, *self.mode.shared_access()))
+    }
+
+    fn set_mode(&self, mode: u32) -> CNFSResult {
+        *self.mode.exclusive_access() = mode & 0o7777;
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult {
+        *self.owner.exclusive_access() = (uid, gid);
+        Ok(())
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Filesystem of the anonymous pipes, which is never in the mount tree
+struct PipeFs;
+
+/// Root of the [PipeFs], which has nothing in it
+struct PipeFsRoot;
+
+impl Inode for PipeFsRoot {}
+
+impl FileSystem for PipeFs
+{
+    fn root_inode(&self) -> InodeRef {
+        Arc::new(PipeFsRoot)
+    }
+
+    fn fs_type(&self) -> &str {
+        "pipefs"
+    }
+}
+
+lazy_static! {
+    static ref PIPE_MOUNT: Arc<Mount> = Arc::new(Mount::new(Arc::new(PipeFs), Path::new("/"), None,
+                                                            VfsConfig::default(), MountOptions::new()));
+    /// Number of anonymous pipes made, which names the next one
+    static ref PIPE_COUNT: UPCell<u64> = unsafe{UPCell::new(0)};
+    /// The dentries of the named pipes, in the filesystems they were made in
+    static ref FIFOS: UPCell<Vec<Arc<Dentry>>> = unsafe{UPCell::new(Vec::new())};
+}
+
+fn pipe_dentry(path: Path, mount: Arc<Mount>, capacity: usize, mode: u32) -> Arc<Dentry>
+{
+    let pipe = Arc::new(Pipe::new(capacity));
+    let credentials = credentials();
+    let fs_inode = PipeInode {
+        pipe: pipe.clone(),
+        mode: unsafe { UPCell::new(mode) },
+        owner: unsafe { UPCell::new((credentials.uid, credentials.gid)) },
+    };
+    let inode = VInodeRef::pipe(Arc::new(fs_inode), pipe, &mount.config);
+    Arc::new(Dentry { path, inode, exist: Arc::new(unsafe { UPCell::new(true) }), mount, alias_of: None })
+}
+
+/// Make the dentry of an anonymous pipe
+pub(crate) fn anonymous_pipe(capacity: usize) -> Arc<Dentry>
+{
+    let mut count = PIPE_COUNT.exclusive_access();
+    *count += 1;
+    pipe_dentry(Path::new(&format!("/pipe:[{count}]")), PIPE_MOUNT.clone(), capacity, 0o600)
+}
+
+/// Returns the path and mount that a path in the given mount has in the filesystem it belongs to,
+/// which differ from the given ones in bind mounts
+fn origin_of(path: &Path, mount: &Arc<Mount>) -> (Path, Arc<Mount>)
+{
+    match &mount.bind {
+        Some(origin) => {
+            let mut ret = origin.path.clone();
+            for name in &path[mount.point.len()..]
+            {
+                ret = ret.join(name);
+            }
+            (ret, origin.mount.clone())
+        }
+        None => (path.clone(), mount.clone()),
+    }
+}
+
+/// Find the named pipe at a path in the given mount, which is the one the path resolves to
+pub(crate) fn find_fifo(path: &Path, mount: &Arc<Mount>) -> Option<Arc<Dentry>>
+{
+    let (origin_path, origin_mount) = origin_of(path, mount);
+    let fifo = FIFOS.shared_access().iter()
+        .find(|d| d.path == origin_path && Arc::ptr_eq(&d.mount, &origin_mount)).cloned()?;
+    if Arc::ptr_eq(&origin_mount, mount) { Some(fifo) } else { Some(Dentry::alias(&fifo, path.clone(), mount.clone())) }
+}
+
+/// Make a named pipe in the directory of the given dentry
+pub(crate) fn make_fifo(parent: &Arc<Dentry>, name: &str) -> CNFSResult<Arc<Dentry>>
+{
+    let path = parent.path.join(name);
+    if path.len() != parent.path.len() + 1 { return Err(InvalidPath); }
+    if find_fifo(&path, &parent.mount).is_some() { return Err(AlreadyExisted); }
+    match parent.lookup_child(name) {
+        Ok(_) => return Err(AlreadyExisted),
+        Err(PathNotFound) => {}
+        Err(err) => return Err(err),
+    }
+    let (origin_path, origin_mount) = origin_of(&path, &parent.mount);
+    let capacity = origin_mount.config.pipe_buffer_size;
+    let fifo = pipe_dentry(origin_path, origin_mount, capacity, 0o644);
+    FIFOS.exclusive_access().push(fifo.clone());
+    if parent.alias_of.is_some() { Ok(Dentry::alias(&fifo, path, parent.mount.clone())) } else { Ok(fifo) }
+}
+
+/// Remove a named pipe, which stays usable by the files opened on it
+pub(crate) fn remove_fifo(fifo: &Arc<Dentry>)
+{
+    let origin = fifo.origin();
+    let removed: Vec<_> = FIFOS.exclusive_access().extract_if(.., |d| Arc::ptr_eq(d, &origin)).collect();
+    drop(removed);
+}
+
+/// Move the named pipes at or under a renamed path of the given mount to the new path
+pub(crate) fn rename_fifos(mount: &Arc<Mount>, from: &Path, to: &Path)
+{
+    for fifo in FIFOS.exclusive_access().iter_mut()
+    {
+        if !Arc::ptr_eq(&fifo.mount, mount) || !fifo.path.starts_with(from) { continue; }
+        *fifo = Arc::new(Dentry {
+            path: fifo.path.rebase(from, to),
+            inode: VInodeRef(fifo.inode.0.clone()),
+            exist: fifo.exist.clone(),
+            mount: mount.clone(),
+            alias_of: None,
+        });
+    }
+}
+
+/// Returns the entries of the named pipes in the directory of the given dentry
+pub(crate) fn fifo_entries(dir: &Dentry) -> Vec<DirEntry>
+{
+    let origin = dir.alias_of.as_deref().unwrap_or(dir);
+    FIFOS.shared_access().iter()
+        .filter(|d| d.path.parent().as_ref() == Some(&origin.path) && Arc::ptr_eq(&d.mount, &origin.mount))
+        .map(|d| DirEntry { name: d.path[d.path.len() - 1].clone(), inode_type: InodeType::Fifo })
+        .collect()
+}
+
+/// Forget the named pipes in the given mounts, when they are unmounted
+pub(crate) fn purge_fifos(mounts: &[Arc<Mount>])
+{
+    let removed: Vec<_> = FIFOS.exclusive_access()
+        .extract_if(.., |d| mounts.iter().any(|m| Arc::ptr_eq(&d.mount, m))).collect();
+    drop(removed);
+}
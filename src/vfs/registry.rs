@@ -0,0 +1,183 @@
+use crate::block::{BlockDeviceRef, PartitionDevice};
+use crate::error::CNFSError::{AlreadyExisted, UnknownFileSystem};
+use crate::error::CNFSResult;
+#[cfg(feature = "fatfs")]
+use crate::fs::FatFactory;
+use crate::fs::{Ext2Factory, TarFactory, ZipFactory};
+use crate::sync::UPCell;
+use crate::vfs::dentry::lookup_dentry;
+use crate::vfs::fs::FileSystem;
+use crate::vfs::mnt::{mount, MountOptions};
+use crate::vfs::path::Path;
+use crate::vfs::perm::check_root;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::fmt;
+use lazy_static::lazy_static;
+
+/// Number of bytes read from the start of a source for probing
+const PROBE_SIZE: usize = 4096;
+
+#[derive(Clone)]
+/// Where a filesystem is built from
+pub enum MountSource
+{
+    /// An image file inside the VFS
+    File(Path),
+    /// A block device
+    Device(BlockDeviceRef),
+    /// An option string, like the directory of a host filesystem
+    Options(String),
+}
+
+impl fmt::Debug for MountSource
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MountSource::File(path) => f.debug_tuple("File").field(path).finish(),
+            MountSource::Device(device) => {
+                write!(f, "Device({} x {} bytes)", device.block_count(), device.block_size())
+            }
+            MountSource::Options(options) => f.debug_tuple("Options").field(options).finish(),
+        }
+    }
+}
+
+/// Trait for building filesystems of a registered type
+pub trait FileSystemFactory: Send + Sync
+{
+    /// Build a filesystem from the source
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>>;
+
+    /// Check if the superblock read from the start of a source belongs to this type
+    ///
+    /// `superblock` holds the first 4096 bytes of the source, or less if it is shorter.
+    fn probe(&self, _superblock: &[u8]) -> bool
+    {
+        false
+    }
+}
+
+impl<F> FileSystemFactory for F
+where
+    F: Fn(&MountSource) -> CNFSResult<Arc<dyn FileSystem>> + Send + Sync,
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        self(source)
+    }
+}
+
+lazy_static! {
+    static ref FS_TYPES: UPCell<Vec<(String, Arc<dyn FileSystemFactory>)>> = unsafe{UPCell::new(builtin_filesystems())};
+}
+
+/// The types registered on first use of the registry: `ext2`, `vfat` with the `fatfs`
+/// feature, `tar` and `zip`, probed in this order.
+fn builtin_filesystems() -> Vec<(String, Arc<dyn FileSystemFactory>)>
+{
+    let mut types: Vec<(String, Arc<dyn FileSystemFactory>)> = vec![("ext2".into(), Arc::new(Ext2Factory))];
+    #[cfg(feature = "fatfs")]
+    types.push(("vfat".into(), Arc::new(FatFactory)));
+    types.push(("tar".into(), Arc::new(TarFactory)));
+    types.push(("zip".into(), Arc::new(ZipFactory)));
+    types
+}
+
+/// Register a filesystem type.
+///
+/// A closure building the filesystem from a [MountSource] can be registered directly,
+/// while types that can be detected by [probe_filesystem] implement [FileSystemFactory].
+/// The names of the built-in types, like `ext2`, are already taken.
+pub fn register_filesystem(name: &str, factory: Arc<dyn FileSystemFactory>) -> CNFSResult
+{
+    let mut types = FS_TYPES.exclusive_access();
+    if types.iter().any(|(n, _)| n == name) { return Err(AlreadyExisted); }
+    types.push((name.into(), factory));
+    Ok(())
+}
+
+/// Unregister a filesystem type.
+pub fn unregister_filesystem(name: &str) -> CNFSResult
+{
+    let mut types = FS_TYPES.exclusive_access();
+    let len = types.len();
+    types.retain(|(n, _)| n != name);
+    if types.len() == len { Err(UnknownFileSystem) } else { Ok(()) }
+}
+
+/// Returns the names of the registered filesystem types, in registration order.
+pub fn filesystems() -> Vec<String>
+{
+    FS_TYPES.shared_access().iter().map(|(n, _)| n.clone()).collect()
+}
+
+fn factory(name: &str) -> CNFSResult<Arc<dyn FileSystemFactory>>
+{
+    FS_TYPES.shared_access().iter().find(|(n, _)| n == name)
+        .map(|(_, f)| f.clone()).ok_or(UnknownFileSystem)
+}
+
+fn read_superblock(source: &MountSource) -> CNFSResult<Vec<u8>>
+{
+    let path = match source {
+        MountSource::File(path) => path,
+        MountSource::Device(device) => {
+            let size = device.block_count() * device.block_size() as u64;
+            let mut superblock = vec![0_u8; min(size, PROBE_SIZE as u64) as usize];
+            device.read_at(0, &mut superblock)?;
+            return Ok(superblock);
+        }
+        MountSource::Options(_) => return Err(UnknownFileSystem),
+    };
+    let dentry = lookup_dentry(path)?;
+    let mut superblock = vec![0_u8; PROBE_SIZE];
+    let mut nread = 0;
+    while nread < superblock.len()
+    {
+        let bytes = dentry.read(nread as u64, &mut superblock[nread..])?;
+        if bytes == 0 { break; }
+        nread += bytes;
+    }
+    superblock.truncate(nread);
+    Ok(superblock)
+}
+
+/// Detect the type of the filesystem in the source.
+///
+/// Every registered type is asked to probe the superblock, in registration order.
+pub fn probe_filesystem(source: &MountSource) -> CNFSResult<String>
+{
+    let superblock = read_superblock(source)?;
+    let types = FS_TYPES.shared_access().clone();
+    types.into_iter().find(|(_, f)| f.probe(&superblock))
+        .map(|(n, _)| n).ok_or(UnknownFileSystem)
+}
+
+/// Build a filesystem of a registered type from the source and mount it at the given path.
+///
+/// With the type `auto`, the type is detected by [probe_filesystem].
+pub fn mount_by_type(fs_type: &str, source: MountSource, mnt_point: Path, options: MountOptions)
+                     -> CNFSResult
+{
+    check_root()?;
+    let factory = if fs_type == "auto" {
+        factory(&probe_filesystem(&source)?)?
+    } else {
+        factory(fs_type)?
+    };
+    mount(factory.create(&source)?, mnt_point, options)
+}
+
+/// Mount the partition numbered `number` of a disk, like partition 2 of `disk.img`.
+///
+/// The partition is given as a [MountSource::Device] to [mount_by_type], so `fs_type` can
+/// also be `auto`.
+pub fn mount_partition(fs_type: &str, disk: BlockDeviceRef, number: usize, mnt_point: Path,
+                       options: MountOptions) -> CNFSResult
+{
+    let partition = Arc::new(PartitionDevice::open(disk, number)?);
+    mount_by_type(fs_type, MountSource::Device(partition), mnt_point, options)
+}
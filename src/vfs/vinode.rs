@@ -1,12 +1,15 @@
-use crate::config::{OSINODE_PAGE_ENTRY_SIZE, OSINODE_PAGE_SIZE};
+use crate::config::VfsConfig;
 use crate::sync::UPCell;
-use crate::vfs::fs::InodeRef;
+use crate::vfs::fs::{DirEntry, InodeRef, Metadata};
+use crate::vfs::lock::LockTable;
+use crate::vfs::pipe::Pipe;
+use crate::CNFSError::{self, NoSpace};
 use crate::{CNFSResult, InodeType};
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::min;
-use core::option::Option;
 
 struct Page
 {
@@ -15,11 +18,11 @@ struct Page
 }
 
 impl Page {
-    fn new() -> Self
+    fn new(page_size: usize) -> Self
     {
         Self {
             dirty: false,
-            data: Vec::with_capacity(OSINODE_PAGE_SIZE),
+            data: Vec::with_capacity(page_size),
         }
     }
 }
@@ -32,14 +35,14 @@ struct PageNumber(u64);
 
 impl Offset
 {
-    fn floor(&self) -> PageNumber
+    fn floor(&self, page_size: usize) -> PageNumber
     {
-        PageNumber(self.0 / OSINODE_PAGE_SIZE as u64)
+        PageNumber(self.0 / page_size as u64)
     }
 
-    fn page_offset(&self) -> usize
+    fn page_offset(&self, page_size: usize) -> usize
     {
-        self.0 as usize % OSINODE_PAGE_SIZE
+        (self.0 % page_size as u64) as usize
     }
 }
 
@@ -50,9 +53,9 @@ impl PageNumber
         self.0 += 1;
     }
 
-    fn offset(&self) -> u64
+    fn offset(&self, page_size: usize) -> u64
     {
-        self.0 * OSINODE_PAGE_SIZE as u64
+        self.0 * page_size as u64
     }
 }
 
@@ -60,33 +63,81 @@ pub(crate) struct VInode
 {
     fs_inode: InodeRef,
     cache: BTreeMap<PageNumber, Page>,
+    page_size: usize,
+    page_entry_size: usize,
+    cacheable: bool,
+    /// The pipe that files opened on the inode join, if it is a pipe
+    pipe: Option<Arc<Pipe>>,
+    /// The advisory locks held by the files opened on the inode
+    locks: LockTable,
+    /// A failed write back that no caller could be told about yet
+    error: Option<CNFSError>,
 }
 
 pub type VInodeType = InodeType;
 pub(crate) struct VInodeRef(pub(crate) Arc<UPCell<VInode>>);
 impl VInodeRef {
-    pub(crate) fn new(fs_inode: InodeRef) -> Self
+    pub(crate) fn new(fs_inode: InodeRef, config: &VfsConfig) -> Self
     {
-        Self(Arc::new(unsafe { UPCell::new(VInode::new(fs_inode)) }))
+        Self(Arc::new(unsafe { UPCell::new(VInode::new(fs_inode, config)) }))
+    }
+
+    /// New the inode of a pipe
+    pub(crate) fn pipe(fs_inode: InodeRef, pipe: Arc<Pipe>, config: &VfsConfig) -> Self
+    {
+        let mut inode = VInode::new(fs_inode, config);
+        inode.pipe = Some(pipe);
+        Self(Arc::new(unsafe { UPCell::new(inode) }))
     }
 }
 
 #[allow(unused)]
 impl VInode
 {
-    pub fn new(fs_inode: InodeRef) -> Self
+    pub fn new(fs_inode: InodeRef, config: &VfsConfig) -> Self
     {
         Self {
+            cacheable: fs_inode.cacheable(),
             fs_inode,
             cache: BTreeMap::new(),
+            page_size: config.page_size,
+            page_entry_size: config.page_entry_size,
+            pipe: None,
+            locks: LockTable::default(),
+            error: None,
         }
     }
 
+    /// Returns the pipe if the inode is one
+    pub fn pipe(&self) -> Option<Arc<Pipe>>
+    {
+        self.pipe.clone()
+    }
+
+    /// Returns the advisory locks of the inode
+    pub fn locks(&mut self) -> &mut LockTable
+    {
+        &mut self.locks
+    }
+
+    /// Returns the filesystem inode if its data must not go through the page cache
+    pub fn uncached(&self) -> Option<InodeRef>
+    {
+        if self.cacheable { None } else { Some(self.fs_inode.clone()) }
+    }
+
+    /// Returns the number of cached pages, how many of them are dirty, and the bytes they hold
+    pub fn cache_usage(&self) -> (usize, usize, usize)
+    {
+        let dirty = self.cache.values().filter(|p| p.dirty).count();
+        (self.cache.len(), dirty, self.cache.values().map(|p| p.data.len()).sum())
+    }
+
     pub fn read(&mut self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize>
     {
         let off = Offset(offset);
-        let mut curr_page = off.floor();
-        let mut curr_page_offset = off.page_offset();
+        let mut curr_page = off.floor(self.page_size);
+        let mut curr_page_offset = off.page_offset(self.page_size);
         let mut nread: usize = 0;
 
         loop
@@ -95,9 +146,9 @@ impl VInode
             {
                 Ok(p) =>
                     {
-                        if p.data.len() == 0
+                        if p.data.len() <= curr_page_offset
                         {
-                            self.cache.remove(&curr_page);
+                            if p.data.is_empty() { self.cache.remove(&curr_page); }
                             return Ok(nread);
                         } else {
                             p
@@ -130,9 +181,10 @@ impl VInode
 
     pub fn write(&mut self, offset: u64, buffer: &[u8]) -> CNFSResult<usize>
     {
+        let page_size = self.page_size;
         let off = Offset(offset);
-        let mut curr_page = off.floor();
-        let mut curr_page_offset = off.page_offset();
+        let mut curr_page = off.floor(page_size);
+        let mut curr_page_offset = off.page_offset(page_size);
         let mut nwritten: usize = 0;
         loop
         {
@@ -140,7 +192,7 @@ impl VInode
             {
                 Ok(p) =>
                     {
-                        let min_page_size = min(OSINODE_PAGE_SIZE, buffer.len() - nwritten);
+                        let min_page_size = min(page_size, curr_page_offset + buffer.len() - nwritten);
                         if p.data.len() < min_page_size
                         {
                             p.data.resize(min_page_size, 0);
@@ -174,16 +226,58 @@ impl VInode
     }
 
     pub fn sync(&mut self) -> CNFSResult
+    {
+        self.flush()?;
+        self.fs_inode.sync()?;
+        self.take_error()
+    }
+
+    /// Keep the error of a write back that failed when the file was dropped
+    pub fn record_error(&mut self, err: CNFSError)
+    {
+        self.error.get_or_insert(err);
+    }
+
+    /// Returns the error of a failed write back kept by [record_error](Self::record_error), once
+    pub fn take_error(&mut self) -> CNFSResult
+    {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Write the dirty pages back to the filesystem inode
+    pub fn flush(&mut self) -> CNFSResult
     {
         for page in self.cache.iter_mut()
         {
             if page.1.dirty
             {
-                self.fs_inode.write(page.0.offset(), page.1.data.as_slice())?;
+                write_page(&self.fs_inode, page.0.offset(self.page_size), page.1.data.as_slice())?;
                 page.1.dirty = false;
             }
         }
-        self.fs_inode.sync()
+        Ok(())
+    }
+
+    /// Write the dirty pages back and drop the page cache
+    pub fn invalidate(&mut self) -> CNFSResult
+    {
+        self.flush()?;
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Read from the filesystem inode, bypassing the page cache
+    pub fn read_direct(&mut self, offset: u64, buffer: &mut [u8]) -> CNFSResult<usize>
+    {
+        self.invalidate()?;
+        self.fs_inode.read(offset, buffer)
+    }
+
+    /// Write to the filesystem inode, bypassing the page cache
+    pub fn write_direct(&mut self, offset: u64, buffer: &[u8]) -> CNFSResult<usize>
+    {
+        self.invalidate()?;
+        self.fs_inode.write(offset, buffer)
     }
 
     pub fn lookup(&self, name: &str) -> CNFSResult<InodeRef>
@@ -201,48 +295,108 @@ impl VInode
         self.fs_inode.remove(name)
     }
 
+    pub fn rename(&self, old_name: &str, new_name: &str) -> CNFSResult
+    {
+        self.fs_inode.rename(old_name, new_name)
+    }
+
+    pub fn read_dir(&self) -> CNFSResult<Vec<DirEntry>>
+    {
+        self.fs_inode.read_dir()
+    }
+
+    /// Write the dirty pages back first, so writing them doesn't change the time again
+    pub fn set_modified(&mut self, modified: u64) -> CNFSResult
+    {
+        self.flush()?;
+        self.fs_inode.set_modified(modified)
+    }
+
+    pub fn set_mode(&self, mode: u32) -> CNFSResult
+    {
+        self.fs_inode.set_mode(mode)
+    }
+
+    pub fn set_owner(&self, uid: u32, gid: u32) -> CNFSResult
+    {
+        self.fs_inode.set_owner(uid, gid)
+    }
+
+    pub fn get_xattr(&self, name: &str) -> CNFSResult<Vec<u8>>
+    {
+        self.fs_inode.get_xattr(name)
+    }
+
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> CNFSResult
+    {
+        self.fs_inode.set_xattr(name, value)
+    }
+
+    pub fn list_xattr(&self) -> CNFSResult<Vec<String>>
+    {
+        self.fs_inode.list_xattr()
+    }
+
+    pub fn remove_xattr(&self, name: &str) -> CNFSResult
+    {
+        self.fs_inode.remove_xattr(name)
+    }
+
+    /// Returns the metadata of the filesystem inode, without the data still in the page cache
+    pub fn fs_metadata(&self) -> CNFSResult<Metadata>
+    {
+        self.fs_inode.metadata()
+    }
+
+    /// Write the dirty pages back and return the metadata of the filesystem inode
+    pub fn metadata(&mut self) -> CNFSResult<Metadata>
+    {
+        self.flush()?;
+        self.fs_inode.metadata()
+    }
+
     fn load_page(&mut self, page_number: PageNumber) -> CNFSResult<&mut Page> {
-        if self.cache.len() >= OSINODE_PAGE_ENTRY_SIZE
+        if !self.cache.contains_key(&page_number) && self.cache.len() >= self.page_entry_size
         {
-            let mut target: Option<PageNumber> = None;
-            for i in self.cache.iter()
-            {
-                if *i.0 != page_number
-                {
-                    if target.is_none()
-                    {
-                        target = Some(*i.0);
-                    }
-
-                    if !i.1.dirty
-                    {
-                        target = Some(*i.0);
-                        break;
-                    }
-                }
-            }
-            let removed = self.cache.remove(&target.unwrap()).unwrap();
-            if removed.dirty
+            // evict a clean page if there is one
+            let target = self.cache.iter().find(|p| !p.1.dirty)
+                .or(self.cache.iter().next()).map(|p| *p.0).unwrap();
+            let page = &self.cache[&target];
+            if page.dirty
             {
-                self.fs_inode.write(target.unwrap().offset(), removed.data.as_slice())
-                    .expect("Failed to write to file.");
+                write_page(&self.fs_inode, target.offset(self.page_size), page.data.as_slice())?;
             }
+            self.cache.remove(&target);
         }
 
-        let page = self.cache.entry(page_number).or_insert(Page::new());
+        let page = self.cache.entry(page_number).or_insert(Page::new(self.page_size));
         if page.data.is_empty()
         {
-            page.data.resize(OSINODE_PAGE_SIZE, 0);
-            let bytes = self.fs_inode.read(page_number.offset(), page.data.as_mut_slice())?;
+            page.data.resize(self.page_size, 0);
+            let bytes = self.fs_inode.read(page_number.offset(self.page_size), page.data.as_mut_slice())?;
             page.data.truncate(bytes);
         }
         Ok(page)
     }
 }
 
+/// Write a whole page to the filesystem inode
+fn write_page(fs_inode: &InodeRef, offset: u64, data: &[u8]) -> CNFSResult
+{
+    let mut written = 0;
+    while written < data.len()
+    {
+        match fs_inode.write(offset + written as u64, &data[written..])? {
+            0 => return Err(NoSpace),
+            bytes => written += bytes,
+        }
+    }
+    Ok(())
+}
+
 impl Drop for VInode
 {
     fn drop(&mut self) {
-        let _ = self.sync();
+        let _ = self.flush();
     }
 }
\ No newline at end of file
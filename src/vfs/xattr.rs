@@ -0,0 +1,90 @@
+use crate::error::CNFSError::{InvalidArgument, NoAttribute};
+use crate::error::CNFSResult;
+use crate::sync::UPCell;
+use crate::vfs::path::Path;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Longest name of an extended attribute, with its namespace prefix
+pub const XATTR_NAME_MAX: usize = 255;
+/// Largest value of an extended attribute
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Namespace of an extended attribute, given by the prefix of its name
+pub(crate) enum XattrNamespace
+{
+    /// `user.*`, guarded by the permission bits of the inode
+    User,
+    /// `trusted.*`, only seen and changed by the superuser
+    Trusted,
+}
+
+/// Returns the namespace of an attribute name, failing with [InvalidArgument] if it has no
+/// known prefix or is too long
+pub(crate) fn xattr_namespace(name: &str) -> CNFSResult<XattrNamespace>
+{
+    if name.len() > XATTR_NAME_MAX { return Err(InvalidArgument); }
+    match name.split_once('.') {
+        Some(("user", rest)) if !rest.is_empty() => Ok(XattrNamespace::User),
+        Some(("trusted", rest)) if !rest.is_empty() => Ok(XattrNamespace::Trusted),
+        _ => Err(InvalidArgument),
+    }
+}
+
+/// Extended attributes kept by the VFS for the inodes of a filesystem that has no native ones
+///
+/// They are kept by the path of the inode in the mount, in memory only, so they are lost when
+/// the filesystem is unmounted.
+pub(crate) struct XattrStore
+{
+    attrs: UPCell<BTreeMap<Path, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl XattrStore
+{
+    pub fn new() -> Self
+    {
+        Self { attrs: unsafe { UPCell::new(BTreeMap::new()) } }
+    }
+
+    pub fn get(&self, path: &Path, name: &str) -> CNFSResult<Vec<u8>>
+    {
+        let attrs = self.attrs.shared_access();
+        attrs.get(path).and_then(|a| a.get(name)).cloned().ok_or(NoAttribute)
+    }
+
+    pub fn set(&self, path: &Path, name: &str, value: &[u8])
+    {
+        self.attrs.exclusive_access().entry(path.clone()).or_default().insert(name.into(), value.into());
+    }
+
+    pub fn list(&self, path: &Path) -> Vec<String>
+    {
+        self.attrs.shared_access().get(path).map_or_else(Vec::new, |a| a.keys().cloned().collect())
+    }
+
+    pub fn remove(&self, path: &Path, name: &str) -> CNFSResult
+    {
+        let mut attrs = self.attrs.exclusive_access();
+        let inode_attrs = attrs.get_mut(path).ok_or(NoAttribute)?;
+        inode_attrs.remove(name).ok_or(NoAttribute)?;
+        if inode_attrs.is_empty() { attrs.remove(path); }
+        Ok(())
+    }
+
+    /// Forget the attributes of a removed inode
+    pub fn remove_all(&self, path: &Path)
+    {
+        self.attrs.exclusive_access().remove(path);
+    }
+
+    /// Move the attributes of the inodes at or under a renamed path to the new path
+    pub fn rename(&self, from: &Path, to: &Path)
+    {
+        let mut attrs = self.attrs.exclusive_access();
+        let moved: Vec<_> = attrs.extract_if(.., |path, _| path.starts_with(from)).collect();
+        attrs.extend(moved.into_iter().map(|(path, a)| (path.rebase(from, to), a)));
+    }
+}
@@ -1,2 +0,0 @@
-pub mod stdfs;
-pub mod fatfs;
\ No newline at end of file
@@ -0,0 +1,123 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::archive::{export_tar, export_tar_with_flags, import_tar, ArchiveFlags};
+use cnfs::{create_directory, exists, metadata, mount, read_dir, read_to_end, umount, write_all, BlockStorage,
+           CNFSError, CNFSResult, FatFileSystem, HostFs, InodeType, MemBlockDevice, MountOptions, Path, RamFs,
+           TarFs};
+use std::sync::Arc;
+
+const LONG_NAME: &str = "a_file_name_long_enough_to_need_a_pax_header_even_after_splitting_the_path_into_the_\
+                         ustar_prefix_and_name.txt";
+
+fn pattern(len: usize) -> Vec<u8>
+{
+    b"cnss{th1s_i5_my_vfs_t3st}\n".iter().cycle().take(len).copied().collect()
+}
+
+fn names(path: &str) -> CNFSResult<Vec<String>>
+{
+    Ok(read_dir(&Path::new(path))?.into_iter().map(|e| e.name).collect())
+}
+
+/// Check the files written under `/host/data`, copied to another directory
+fn check_copy(dir: &str, hello: &[u8]) -> CNFSResult
+{
+    assert_eq!(read_to_end(&Path::new(&format!("{dir}/hello.txt")))?, hello);
+    assert_eq!(read_to_end(&Path::new(&format!("{dir}/deep/er/big.bin")))?, pattern(300 * 1024));
+    assert_eq!(read_to_end(&Path::new(&format!("{dir}/deep/{LONG_NAME}")))?, b"cnss{long}");
+    assert!(read_dir(&Path::new(&format!("{dir}/empty")))?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn archive_test() -> CNFSResult
+{
+    let host_dir = std::env::temp_dir().join("cnfs_archive_test");
+    let _ = std::fs::remove_dir_all(&host_dir);
+    std::fs::create_dir(&host_dir).unwrap();
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/host"))?;
+    create_directory(&Path::new("/fat"))?;
+    mount(Arc::new(HostFs::new(&host_dir)?), Path::new("/host"), MountOptions::new())?;
+    let image = std::fs::read("tests/resources/fat_1.img").unwrap();
+    let device = Arc::new(MemBlockDevice::from_vec(512, image)?);
+    mount(Arc::new(FatFileSystem::open(BlockStorage::new(device))?), Path::new("/fat"), MountOptions::new())?;
+
+    // Some data on the host, with a filesystem mounted inside it
+    for dir in ["/host/data", "/host/data/deep", "/host/data/deep/er", "/host/data/empty", "/host/data/mnt"]
+    {
+        create_directory(&Path::new(dir))?;
+    }
+    write_all(&Path::new("/host/data/hello.txt"), b"cnss")?;
+    write_all(&Path::new("/host/data/deep/er/big.bin"), &pattern(300 * 1024))?;
+    write_all(&Path::new(&format!("/host/data/deep/{LONG_NAME}")), b"cnss{long}")?;
+    mount(Arc::new(RamFs::new()), Path::new("/host/data/mnt"), MountOptions::new())?;
+    write_all(&Path::new("/host/data/mnt/inner"), b"cnss{inner}")?;
+
+    // From the host to FAT
+    let mut tarball = Vec::new();
+    export_tar(&Path::new("/host/data"), &mut tarball)?;
+    create_directory(&Path::new("/fat/backup"))?;
+    import_tar(tarball.as_slice(), &Path::new("/fat/backup"))?;
+    check_copy("/fat/backup", b"cnss")?;
+    assert_eq!(read_to_end(&Path::new("/fat/backup/mnt/inner"))?, b"cnss{inner}");
+
+    // The archive can be read by others
+    create_directory(&Path::new("/check"))?;
+    mount(Arc::new(TarFs::new(tarball)?), Path::new("/check"), MountOptions::new().read_only(true))?;
+    check_copy("/check", b"cnss")?;
+    let modified = metadata(&Path::new("/host/data/hello.txt"))?.modified;
+    assert_eq!(metadata(&Path::new("/check/hello.txt"))?.modified, modified);
+    umount(Path::new("/check"))?;
+
+    // Stopping at mount points
+    let mut tarball = Vec::new();
+    export_tar_with_flags(&Path::new("/host/data"), &mut tarball, ArchiveFlags::ONE_FILE_SYSTEM)?;
+    mount(Arc::new(TarFs::new(tarball)?), Path::new("/check"), MountOptions::new().read_only(true))?;
+    check_copy("/check", b"cnss")?;
+    assert_eq!(metadata(&Path::new("/check/mnt"))?.inode_type, InodeType::Dir);
+    assert!(!exists(&Path::new("/check/mnt/inner"))?);
+    umount(Path::new("/check"))?;
+
+    // From FAT back to the host, replacing what is there
+    write_all(&Path::new("/fat/backup/hello.txt"), b"CNSS")?;
+    let mut tarball = Vec::new();
+    export_tar(&Path::new("/fat/backup"), &mut tarball)?;
+    import_tar(tarball.as_slice(), &Path::new("/host/data"))?;
+    check_copy("/host/data", b"CNSS")?;
+    umount(Path::new("/host/data/mnt"))?;
+
+    // A single file
+    let mut tarball = Vec::new();
+    export_tar(&Path::new("/host/data/hello.txt"), &mut tarball)?;
+    assert_eq!(tarball.len(), 512 * 4);
+    import_tar(tarball.as_slice(), &Path::new("/fat"))?;
+    assert_eq!(read_to_end(&Path::new("/fat/hello.txt"))?, b"CNSS");
+
+    // Links and times are restored where the filesystem supports them
+    create_directory(&Path::new("/host/bundle"))?;
+    import_tar(std::fs::File::open("tests/resources/bundle.tar").unwrap(), &Path::new("/host/bundle"))?;
+    assert_eq!(names("/host/bundle")?, ["empty", "firmware", "version"]);
+    assert_eq!(names("/host/bundle/firmware/bin")?, ["version.hard", "version.link"]);
+    assert_eq!(read_to_end(&Path::new("/host/bundle/version"))?, b"cnss");
+    assert_eq!(read_to_end(&Path::new("/host/bundle/firmware/bin/version.link"))?, b"../../version");
+    assert_eq!(read_to_end(&Path::new("/host/bundle/firmware/image.bin"))?, pattern(100000));
+    for path in ["/host/bundle/version", "/host/bundle/firmware", "/host/bundle/firmware/image.bin"]
+    {
+        assert_eq!(metadata(&Path::new(path))?.modified, Some(1700000000));
+    }
+
+    // Errors
+    assert_eq!(import_tar(&[0_u8; 512][..], &Path::new("/fat/hello.txt")), Err(CNFSError::NotADirectory));
+    assert!(import_tar(&[0x55_u8; 512][..], &Path::new("/fat")).is_err());
+    let truncated = std::fs::read("tests/resources/bundle.tar").unwrap()[..60 * 1024].to_vec();
+    assert!(import_tar(truncated.as_slice(), &Path::new("/fat")).is_err());
+    let mut tarball = Vec::new();
+    assert_eq!(export_tar(&Path::new("/missing"), &mut tarball), Err(CNFSError::PathNotFound));
+
+    umount(Path::new("/fat"))?;
+    umount(Path::new("/host"))?;
+    umount(Path::new("/"))?;
+    std::fs::remove_dir_all(&host_dir).unwrap();
+    Ok(())
+}
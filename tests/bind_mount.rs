@@ -0,0 +1,74 @@
+use cnfs::{bind_mount, create_directory, exists, mount, mounts, open, read_to_end, remove, umount,
+           umount_with_flags, write_all, CNFSError, CNFSResult, FileMode, HostFs, MountOptions, Path,
+           RamFs, UmountFlags};
+use std::env::current_dir;
+use std::sync::Arc;
+
+#[test]
+fn bind_mount_test() -> CNFSResult
+{
+    let base = current_dir().unwrap().join("bind_mount_test");
+    for dir in ["data/shared", "sandbox1/shared", "sandbox2/shared", "sandbox3"]
+    {
+        std::fs::create_dir_all(base.join(dir)).unwrap();
+    }
+    mount(Arc::new(HostFs::new(base.clone())?), Path::new("/"), MountOptions::new())?;
+
+    let shared = Path::new("/data/shared");
+    let rw = Path::new("/sandbox1/shared");
+    let ro = Path::new("/sandbox2/shared");
+    bind_mount(&shared, &rw, MountOptions::new())?;
+    bind_mount(&shared, &ro, MountOptions::new().read_only(true))?;
+    bind_mount(&rw, &Path::new("/sandbox3"), MountOptions::new())?;
+    assert_eq!(bind_mount(&Path::new("/nowhere"), &rw, MountOptions::new()), Err(CNFSError::PathNotFound));
+
+    let binds: Vec<_> = mounts().filter_map(|m| m.bind).collect();
+    assert_eq!(binds, [shared.clone(), shared.clone(), shared.clone()]);
+
+    // Every view shares the cached data
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+    let mut file = open(&rw.join("f"), FileMode::write)?;
+    file.write_all(data.as_bytes())?;
+    file.sync()?;
+    assert_eq!(read_to_end(&shared.join("f"))?, data.as_bytes());
+    assert_eq!(read_to_end(&ro.join("f"))?, data.as_bytes());
+    assert_eq!(read_to_end(&Path::new("/sandbox3/f"))?, data.as_bytes());
+    drop(file);
+    assert_eq!(std::fs::read(base.join("data/shared/f")).unwrap(), data.as_bytes());
+    assert!(!base.join("sandbox1/shared/f").exists());
+
+    // Read-only view
+    assert_eq!(write_all(&ro.join("f"), b"cnss"), Err(CNFSError::ReadOnly));
+    assert_eq!(remove(&ro.join("f")), Err(CNFSError::ReadOnly));
+
+    // Removing and creating through one view is seen by the others
+    remove(&rw.join("f"))?;
+    assert!(!exists(&shared.join("f"))? && !exists(&ro.join("f"))?);
+    write_all(&shared.join("f"), b"cnss")?;
+    assert_eq!(read_to_end(&ro.join("f"))?, b"cnss");
+
+    assert_eq!(bind_mount(&shared.join("f"), &rw, MountOptions::new()), Err(CNFSError::NotADirectory));
+    assert_eq!(bind_mount(&shared, &shared.join("f"), MountOptions::new()), Err(CNFSError::NotADirectory));
+    umount(Path::new("/sandbox3"))?;
+
+    // Files opened through a view keep the bound filesystem busy
+    let ram = Path::new("/sandbox3");
+    mount(Arc::new(RamFs::new()), ram.clone(), MountOptions::new())?;
+    create_directory(&ram.join("d"))?;
+    bind_mount(&ram.join("d"), &shared, MountOptions::new())?;
+    let mut file = open(&shared.join("g"), FileMode::write)?;
+    assert_eq!(umount(ram.clone()), Err(CNFSError::Busy));
+    umount_with_flags(ram.clone(), UmountFlags::FORCE)?;
+    assert_eq!(file.write_all(b"cnss").and_then(|_| file.sync()), Err(CNFSError::NoMountedFilesystem));
+    assert_eq!(read_to_end(&shared.join("g")), Err(CNFSError::NoMountedFilesystem));
+    drop(file);
+    umount(shared.clone())?;
+    assert_eq!(read_to_end(&shared.join("f"))?, b"cnss");
+
+    umount(ro.clone())?;
+    umount(rw.clone())?;
+    assert!(!exists(&rw.join("f"))?);
+    umount(Path::new("/"))?;
+    std::fs::remove_dir_all(&base).unwrap();
+    Ok(())
+}
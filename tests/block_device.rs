@@ -0,0 +1,92 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::{exists, mount_by_type, read_to_end, umount, write_all, BlockDevice, BlockDeviceRef, BufferCache,
+           CNFSError, CNFSResult, FileBlockDevice, MemBlockDevice, MountOptions, MountSource, Path};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A device counting the blocks read from and written to it
+struct CountingDevice
+{
+    inner: MemBlockDevice,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+}
+
+impl BlockDevice for CountingDevice
+{
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.inner.block_count()
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> CNFSResult {
+        self.reads.fetch_add(buffer.len() / self.block_size(), Ordering::Relaxed);
+        self.inner.read_blocks(block, buffer)
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> CNFSResult {
+        self.writes.fetch_add(buffer.len() / self.block_size(), Ordering::Relaxed);
+        self.inner.write_blocks(block, buffer)
+    }
+}
+
+#[test]
+fn block_device_test() -> CNFSResult
+{
+    // Memory device
+    let mem = MemBlockDevice::new(512, 8)?;
+    let mut block = vec![0_u8; 512];
+    assert_eq!(mem.read_blocks(8, &mut block), Err(CNFSError::InvalidArgument));
+    assert_eq!(mem.read_blocks(0, &mut block[..100]), Err(CNFSError::InvalidArgument));
+    assert_eq!(mem.write_at(4090, b"cnss{overflow}"), Err(CNFSError::InvalidArgument));
+    assert_eq!(MemBlockDevice::from_vec(512, vec![0; 100]).err(), Some(CNFSError::InvalidArgument));
+
+    // Buffer cache
+    let device = Arc::new(CountingDevice {
+        inner: MemBlockDevice::new(512, 8)?,
+        reads: AtomicUsize::new(0),
+        writes: AtomicUsize::new(0),
+    });
+    let cache = BufferCache::new(device.clone(), 2)?;
+    let data = b"cnss{th1s_i5_my_vfs_t3st}";
+    cache.write_at(500, data)?;
+    assert_eq!((cache.cached_blocks(), cache.dirty_blocks()), (2, 2));
+    let mut dest = vec![0_u8; data.len()];
+    cache.read_at(500, &mut dest)?;
+    assert_eq!(dest, data);
+    assert_eq!(device.reads.load(Ordering::Relaxed), 2);
+    assert_eq!(device.writes.load(Ordering::Relaxed), 0);
+    // Full blocks are not read before being overwritten
+    cache.write_blocks(4, &[7; 512])?;
+    assert_eq!(device.reads.load(Ordering::Relaxed), 2);
+    assert_eq!(device.writes.load(Ordering::Relaxed), 1);
+    cache.flush()?;
+    assert_eq!(cache.dirty_blocks(), 0);
+    assert_eq!(&device.inner.to_vec()[500..500 + data.len()], data);
+    assert_eq!(device.inner.to_vec()[2048], 7);
+    // The closures may use the cache again
+    cache.with_block(4, |block| cache.write_at(0, &block[..4]))??;
+    cache.with_block_mut(1, |block| cache.read_at(0, &mut block[..4]))??;
+    assert_eq!(cache.with_block(1, |block| block[..4].to_vec())?, [7; 4]);
+    drop(cache);
+
+    // FAT on a cached file-backed device
+    let image = std::fs::read("tests/resources/fat_1.img").unwrap();
+    let file_device: BlockDeviceRef = Arc::new(FileBlockDevice::new(Cursor::new(image), 512)?);
+    let cached: BlockDeviceRef = Arc::new(BufferCache::new(file_device.clone(), 64)?);
+    mount_by_type("vfat", MountSource::Device(cached.clone()), Path::new("/"), MountOptions::new())?;
+    write_all(&Path::new("/cnss"), data)?;
+    assert_eq!(read_to_end(&Path::new("/cnss"))?, data);
+    umount(Path::new("/"))?;
+
+    mount_by_type("vfat", MountSource::Device(file_device), Path::new("/"), MountOptions::new())?;
+    assert!(exists(&Path::new("/cnss"))?);
+    assert_eq!(read_to_end(&Path::new("/cnss"))?, data);
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use cnfs::{init, mount, open, read_to_end, remove, umount, CNFSError, CNFSResult,
+           FileMode, HostFs, MountOptions, Path, VfsConfig};
+use std::env::current_dir;
+use std::sync::Arc;
+
+#[test]
+fn config_test() -> CNFSResult
+{
+    assert_eq!(init(VfsConfig::default().page_size(0)), Err(CNFSError::InvalidArgument));
+    init(VfsConfig::default().dcache_size(8))?;
+
+    let fs = Arc::new(HostFs::new(current_dir().unwrap())?);
+    let tiny = VfsConfig::default().file_buffer_size(5).page_size(7).page_entry_size(3);
+    mount(fs, Path::new("/"), MountOptions::new().config(tiny))?;
+
+    let test_file = Path::new("/config_test_file");
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+
+    let mut file = open(&test_file, FileMode::write)?;
+    assert_eq!(file.max_buffer_size(), 5);
+    for _ in 0..100
+    {
+        file.write_all(data.as_bytes())?;
+    }
+    file.set_max_buffer_size(64)?;
+    assert_eq!(file.max_buffer_size(), 64);
+    assert_eq!(file.set_max_buffer_size(0), Err(CNFSError::InvalidArgument));
+    for _ in 0..100
+    {
+        file.write_all(data.as_bytes())?;
+    }
+    file.sync()?;
+    file.seek(3)?;
+    let mut dest = vec![0_u8; data.len()];
+    assert_eq!(file.read(dest.as_mut_slice())?, dest.len());
+    assert_eq!(&dest[..data.len() - 3], &data.as_bytes()[3..]);
+    drop(file);
+
+    let content = read_to_end(&test_file)?;
+    assert_eq!(content, data.repeat(200).as_bytes());
+
+    remove(&test_file)?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use cnfs::{create_directory, exists, metadata, mount, open, read_dir, register_device, umount, unregister_device,
+           write_all, CNFSError, CNFSResult, DevFs, Device, FileMode, InodeType, MountOptions, Path, RamFs};
+use std::sync::{Arc, Mutex};
+
+/// A serial port that logs what is written and reads back a queued reply
+#[derive(Default)]
+struct Uart
+{
+    log: Mutex<Vec<u8>>,
+    reply: Mutex<Vec<u8>>,
+}
+
+impl Device for Uart
+{
+    fn read(&self, buffer: &mut [u8]) -> CNFSResult<usize> {
+        let mut reply = self.reply.lock().unwrap();
+        let len = buffer.len().min(reply.len());
+        buffer[..len].copy_from_slice(&reply[..len]);
+        reply.drain(..len);
+        Ok(len)
+    }
+
+    fn write(&self, buffer: &[u8]) -> CNFSResult<usize> {
+        self.log.lock().unwrap().extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+}
+
+fn read_device(path: &str, len: usize) -> CNFSResult<Vec<u8>>
+{
+    let mut buffer = vec![0x55; len];
+    let bytes = open(&Path::new(path), FileMode::read)?.read(&mut buffer)?;
+    buffer.truncate(bytes);
+    Ok(buffer)
+}
+
+#[test]
+fn devfs_test() -> CNFSResult
+{
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    for dir in ["/dev", "/dev2", "/dev3"]
+    {
+        create_directory(&Path::new(dir))?;
+    }
+    mount(Arc::new(DevFs::with_seed(42)), Path::new("/dev"), MountOptions::new())?;
+    mount(Arc::new(DevFs::with_seed(42)), Path::new("/dev2"), MountOptions::new())?;
+    mount(Arc::new(DevFs::with_seed(7)), Path::new("/dev3"), MountOptions::new())?;
+    let names: Vec<_> = read_dir(&Path::new("/dev"))?.into_iter().map(|e| (e.name, e.inode_type)).collect();
+    assert_eq!(names, ["full", "null", "random", "zero"].map(|n| (n.to_string(), InodeType::CharDevice)));
+    assert_eq!(metadata(&Path::new("/dev/null"))?.inode_type, InodeType::CharDevice);
+
+    // Built-in devices
+    assert!(read_device("/dev/null", 16)?.is_empty());
+    write_all(&Path::new("/dev/null"), b"cnss")?;
+    assert_eq!(read_device("/dev/zero", 4096)?, vec![0; 4096]);
+    assert_eq!(read_device("/dev/full", 16)?, vec![0; 16]);
+    let mut full = open(&Path::new("/dev/full"), FileMode::write)?;
+    assert_eq!(full.write(b"cnss"), Err(CNFSError::NoSpace));
+    drop(full);
+
+    // The same seed gives the same stream, read after read
+    let mut random = open(&Path::new("/dev/random"), FileMode::read)?;
+    let (mut first, mut second) = ([0_u8; 13], [0_u8; 13]);
+    random.read(&mut first)?;
+    random.read(&mut second)?;
+    assert_ne!(first, second);
+    assert_ne!(first, [0; 13]);
+    let stream = read_device("/dev2/random", 32)?;
+    assert_eq!(&stream[..13], first);
+    assert_ne!(read_device("/dev3/random", 32)?, read_device("/dev2/random", 32)?);
+    drop(random);
+
+    // Devices of the application, which every read and write reaches at once
+    let uart = Arc::new(Uart::default());
+    register_device("ttyS0", uart.clone())?;
+    assert_eq!(register_device("ttyS0", uart.clone()), Err(CNFSError::AlreadyExisted));
+    assert_eq!(register_device("zero", uart.clone()), Err(CNFSError::AlreadyExisted));
+    assert_eq!(register_device("tty/0", uart.clone()), Err(CNFSError::InvalidArgument));
+    assert!(exists(&Path::new("/dev/ttyS0"))? && exists(&Path::new("/dev3/ttyS0"))?);
+    let mut tty = open(&Path::new("/dev/ttyS0"), FileMode::read | FileMode::write)?;
+    tty.write_all(b"AT\r\n")?;
+    assert_eq!(*uart.log.lock().unwrap(), b"AT\r\n");
+    uart.reply.lock().unwrap().extend_from_slice(b"OK\r\n");
+    let mut buffer = [0_u8; 16];
+    assert_eq!(tty.read(&mut buffer)?, 4);
+    assert_eq!(&buffer[..4], b"OK\r\n");
+    assert_eq!(tty.read(&mut buffer)?, 0);
+    uart.reply.lock().unwrap().extend_from_slice(b"ERROR\r\n");
+    assert_eq!(tty.read(&mut buffer)?, 7);
+
+    // Unregistered devices disappear, but opened files keep working
+    unregister_device("ttyS0")?;
+    assert_eq!(unregister_device("ttyS0"), Err(CNFSError::PathNotFound));
+    assert!(!exists(&Path::new("/dev/ttyS0"))?);
+    tty.write_all(b"ATZ\r\n")?;
+    assert_eq!(*uart.log.lock().unwrap(), b"AT\r\nATZ\r\n");
+    drop(tty);
+
+    // Nothing can be created
+    assert_eq!(create_directory(&Path::new("/dev/new")), Err(CNFSError::ReadOnly));
+    umount(Path::new("/dev"))?;
+    umount(Path::new("/dev2"))?;
+    umount(Path::new("/dev3"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use cnfs::{close, create_directory, exists, metadata, mount, open, read_dir, read_to_end, remove, rename,
+           umount, write_all, BlockDeviceRef, CNFSError, CNFSResult, Ext2FileSystem, FileMode, InodeType,
+           MemBlockDevice, MountOptions, Path};
+use std::sync::Arc;
+
+/// Content of `big.bin` in the image, long enough to need double indirect blocks
+fn pattern(len: usize) -> Vec<u8>
+{
+    b"cnss{th1s_i5_my_vfs_t3st}\n".iter().cycle().take(len).copied().collect()
+}
+
+#[test]
+fn ext2_test() -> CNFSResult
+{
+    let image = std::fs::read("tests/resources/ext2_1.img").unwrap();
+    assert!(Ext2FileSystem::probe(&image[..4096]));
+    let mut corrupted = image.clone();
+    corrupted[1024 + 4..1024 + 8].fill(0);
+    let corrupted = Arc::new(MemBlockDevice::from_vec(512, corrupted)?);
+    assert!(matches!(Ext2FileSystem::open(corrupted), Err(CNFSError::FSInternal(_))));
+    assert!(!Ext2FileSystem::probe(&std::fs::read("tests/resources/fat_1.img").unwrap()[..4096]));
+    let device = Arc::new(MemBlockDevice::from_vec(512, image)?);
+    let fs = Ext2FileSystem::open(device.clone() as BlockDeviceRef)?;
+    assert!(!fs.read_only());
+    mount(Arc::new(fs), Path::new("/"), MountOptions::new())?;
+
+    // Read
+    assert_eq!(read_to_end(&Path::new("/hello.txt"))?, b"cnss");
+    assert_eq!(read_to_end(&Path::new("/dir/nested.txt"))?, b"cnss{nested}");
+    assert_eq!(read_to_end(&Path::new("/big.bin"))?, pattern(307200));
+    assert_eq!(read_to_end(&Path::new("/link"))?, b"hello.txt");
+    assert_eq!(metadata(&Path::new("/big.bin"))?.size, 307200);
+    assert_eq!(metadata(&Path::new("/dir"))?.inode_type, InodeType::Dir);
+    assert!(!exists(&Path::new("/missing"))?);
+    let root: Vec<_> = read_dir(&Path::new("/"))?.into_iter().map(|e| (e.name, e.inode_type)).collect();
+    assert_eq!(root, [("big.bin".into(), InodeType::File), ("dir".into(), InodeType::Dir),
+                      ("hello.txt".into(), InodeType::File), ("link".into(), InodeType::Symlink),
+                      ("lost+found".into(), InodeType::Dir)]);
+    assert_eq!(read_to_end(&Path::new("/hello.txt/x")), Err(CNFSError::PathNotFound));
+    assert_eq!(metadata(&Path::new("/link"))?.inode_type, InodeType::Symlink);
+    assert_eq!(write_all(&Path::new("/link"), b"big.bin"), Err(CNFSError::InvalidArgument));
+
+    // Write
+    let data = pattern(400 * 1024);
+    create_directory(&Path::new("/new"))?;
+    write_all(&Path::new("/new/large"), &data)?;
+    let mut file = open(&Path::new("/new/large"), FileMode::write)?;
+    file.seek(300 * 1024)?;
+    file.write_all(b"CNSS")?;
+    close(file);
+    for i in 0..64
+    {
+        write_all(&Path::new(&format!("/new/file_with_a_long_name_{i}")), i.to_string().as_bytes())?;
+    }
+    for i in (0..64).step_by(2)
+    {
+        remove(&Path::new(&format!("/new/file_with_a_long_name_{i}")))?;
+    }
+    assert_eq!(remove(&Path::new("/new")), Err(CNFSError::DirectoryNotEmpty));
+    assert_eq!(create_directory(&Path::new("/new")), Err(CNFSError::AlreadyExisted));
+    remove(&Path::new("/big.bin"))?;
+    rename(&Path::new("/link"), &Path::new("/shortcut"))?;
+    assert_eq!(metadata(&Path::new("/shortcut"))?.inode_type, InodeType::Symlink);
+    remove(&Path::new("/shortcut"))?;
+    rename(&Path::new("/dir"), &Path::new("/folder"))?;
+    rename(&Path::new("/hello.txt"), &Path::new("/greeting_with_a_long_name.txt"))?;
+    umount(Path::new("/"))?;
+
+    // Everything is on the device
+    let fs = Ext2FileSystem::open(device.clone() as BlockDeviceRef)?;
+    mount(Arc::new(fs), Path::new("/"), MountOptions::new())?;
+    let mut expected = data.clone();
+    expected[300 * 1024..300 * 1024 + 4].copy_from_slice(b"CNSS");
+    assert_eq!(read_to_end(&Path::new("/new/large"))?, expected);
+    for i in 0..64
+    {
+        let path = Path::new(&format!("/new/file_with_a_long_name_{i}"));
+        assert_eq!(exists(&path)?, i % 2 == 1);
+        if i % 2 == 1 { assert_eq!(read_to_end(&path)?, i.to_string().as_bytes()); }
+    }
+    assert!(!exists(&Path::new("/big.bin"))?);
+    assert!(!exists(&Path::new("/dir"))?);
+    assert_eq!(read_to_end(&Path::new("/folder/nested.txt"))?, b"cnss{nested}");
+    assert_eq!(read_to_end(&Path::new("/greeting_with_a_long_name.txt"))?, b"cnss");
+    umount(Path::new("/"))?;
+    Ok(())
+}
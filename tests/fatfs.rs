@@ -1,21 +1,17 @@
-use cnfs::{create_directory, exists, mount, open, remove, umount,
-           CNFSResult, FileMode, Path};
-use lazy_static::lazy_static;
-use std::sync::Arc;
-
-mod adapter;
-use adapter::fatfs::*;
+#![cfg(feature = "fatfs")]
 
-lazy_static! {
-    pub static ref fs: Arc<FSWrapper> = Arc::new(FSWrapper::new("tests/resources/fat_1.img".into()));
-}
+use cnfs::{close, create_directory, exists, mount, open, read_to_end, remove, rename, umount, write_all,
+           BlockStorage, CNFSError, CNFSResult, FatFileSystem, FileMode, MemBlockDevice, MountOptions, Path, RamFs};
+use std::sync::Arc;
 
 #[test]
 fn fatfs_test() -> CNFSResult
 {
-    fs.init();
+    let image = std::fs::read("tests/resources/fat_1.img").unwrap();
+    let img = BlockStorage::new(Arc::new(MemBlockDevice::from_vec(512, image)?));
+    let fs = Arc::new(FatFileSystem::open(img)?);
     // mount
-    mount(fs.clone(), Path::new("/"))?;
+    mount(fs.clone(), Path::new("/"), MountOptions::new())?;
     let test_dir = Path::new("/test_directory");
     let test_file = Path::new("/test_file");
 
@@ -27,6 +23,12 @@ fn fatfs_test() -> CNFSResult
     assert!(!exists(&test_dir)?);
     create_directory(&test_dir)?;
     assert!(exists(&test_dir)?);
+    assert_eq!(create_directory(&test_dir), Err(CNFSError::AlreadyExisted));
+    create_directory(&test_dir.join("sub"))?;
+    assert_eq!(remove(&test_dir), Err(CNFSError::DirectoryNotEmpty));
+    rename(&test_dir.join("sub"), &test_dir.join("moved"))?;
+    assert!(!exists(&test_dir.join("sub"))? && exists(&test_dir.join("moved"))?);
+    remove(&test_dir.join("moved"))?;
     remove(&test_dir)?;
     assert!(!exists(&test_dir)?);
 
@@ -61,11 +63,41 @@ fn fatfs_test() -> CNFSResult
         assert_eq!(file.read(dest.as_mut_slice())?, dest.len());
         assert_eq!(dest, data.as_bytes());
     }
+    close(file);
+    assert_eq!(create_directory(&test_file.join("dir")), Err(CNFSError::NotADirectory));
 
     assert!(exists(&test_file)?);
     remove(&test_file)?;
     assert!(!exists(&test_file)?);
 
+    // A full volume
+    let mut file = open(&test_file, FileMode::write)?;
+    file.write_all(&vec![0; 1 << 20])?;
+    file.sync()?;
+    assert_eq!(file.metadata(), Err(CNFSError::NoSpace));
+    close(file);
+    remove(&test_file)?;
+
+    umount(Path::new("/"))?;
+    drop(fs);
+
+    // An image inside the VFS
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    let image = Path::new("/fat.img");
+    let mnt = Path::new("/mnt");
+    write_all(&image, &std::fs::read("tests/resources/fat_2.img").unwrap())?;
+    create_directory(&mnt)?;
+    for _ in 0..2
+    {
+        let storage = open(&image, FileMode::read | FileMode::write)?;
+        mount(Arc::new(FatFileSystem::open(storage)?), mnt.clone(), MountOptions::new())?;
+        if !exists(&mnt.join("cnss"))?
+        {
+            write_all(&mnt.join("cnss"), data.as_bytes())?;
+        }
+        assert_eq!(read_to_end(&mnt.join("cnss"))?, data.as_bytes());
+        umount(mnt.clone())?;
+    }
     umount(Path::new("/"))?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,142 @@
+use cnfs::{close, create_directory, exists, metadata, mount, open, read, read_dir, read_to_end, remove, rename, umount, write_all, CNFSError, CNFSResult, FileMode, HostFs, InodeType, MountOptions, Path};
+use std::env::current_dir;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+#[test]
+fn hostfs_test() -> CNFSResult
+{
+    let fs = Arc::new(HostFs::new(current_dir().unwrap())?);
+
+    let real_curr = current_dir().unwrap();
+    let real_curr_str = real_curr.to_str().unwrap().to_owned();
+
+    // mount
+    mount(fs, Path::new("/"), MountOptions::new())?;
+    let test_dir = Path::new("/test_directory");
+    let test_file = Path::new("/test_file");
+    let real_dir_str: String = real_curr_str.clone() + &test_dir.to_string();
+    let real_file_str: String = real_curr_str.clone() + &test_file.to_string();
+    let std_dir_path = std::path::Path::new(&real_dir_str);
+    let std_file_path = std::path::Path::new(&real_file_str);
+
+    // Directory Test
+    if exists(&test_dir)?
+    {
+        remove(&test_dir)?;
+    }
+    assert!(!exists(&test_dir)? && !std_dir_path.exists());
+    create_directory(&test_dir)?;
+    assert!(exists(&test_dir)? && std_dir_path.exists());
+    rename(&test_dir, &Path::new("/test_renamed"))?;
+    assert!(!std_dir_path.exists() && real_curr.join("test_renamed").exists());
+    rename(&Path::new("/test_renamed"), &test_dir)?;
+    remove(&test_dir)?;
+    assert!(!exists(&test_dir)? && !std_dir_path.exists());
+
+    // File Test
+    if exists(&test_file)?
+    {
+        remove(&test_file)?;
+    }
+    assert!(!exists(&test_file)? && !std_file_path.exists());
+
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+    let mut dest = vec![0_u8; data.len()];
+
+    write_all(&test_file, data.as_bytes())?;
+    read(&test_file, dest.as_mut_slice())?;
+    assert_eq!(dest, data.as_bytes());
+    dest = read_to_end(&test_file)?;
+    assert_eq!(dest.len(), data.len());
+    assert_eq!(dest, data.as_bytes());
+
+    dest.fill(0);
+    let ret = File::open(std_file_path).unwrap().read(dest.as_mut_slice());
+    assert_eq!(ret.unwrap(), dest.len());
+    assert_eq!(dest, data.as_bytes());
+
+    let mut file = open(&test_file, FileMode::write)?;
+    for _ in 0..10000
+    {
+        file.write_all(data.as_bytes())?;
+    }
+
+    file.sync()?;
+    file.seek(0)?;
+    for _ in 0..10000
+    {
+        dest.fill(0);
+        assert_eq!(file.read(dest.as_mut_slice())?, dest.len());
+        assert_eq!(dest, data.as_bytes());
+    }
+
+    assert_eq!(file.metadata()?.size, (data.len() * 10000) as u64);
+    assert_eq!(Seek::seek(&mut file, SeekFrom::End(-1))?, (data.len() * 10000 - 1) as u64);
+    close(file);
+    let meta = metadata(&test_file)?;
+    assert_eq!((meta.inode_type, meta.size), (InodeType::File, (data.len() * 10000) as u64));
+    assert!(meta.modified.is_some());
+    assert_eq!(metadata(&Path::new("/"))?.inode_type, InodeType::Dir);
+
+    let mut stdfile = File::open(std_file_path).unwrap();
+    for _ in 0..10000
+    {
+        dest.fill(0);
+        assert_eq!(stdfile.read(dest.as_mut_slice()).unwrap(), dest.len());
+        assert_eq!(dest, data.as_bytes());
+    }
+
+    assert!(exists(&test_file)? && std_file_path.exists());
+    remove(&test_file)?;
+    assert!(!exists(&test_file)? && !std_file_path.exists());
+
+    // Symlinks can't escape the root
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let base = real_curr.join("hostfs_test");
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::write(base.join("inside"), "cnss").unwrap();
+        std::fs::write(base.join(std::ffi::OsStr::from_bytes(b"\xff")), "").unwrap();
+        std::os::unix::fs::symlink("inside", base.join("link")).unwrap();
+        std::os::unix::fs::symlink("/", base.join("escape")).unwrap();
+        std::os::unix::fs::symlink("../..", base.join("parent")).unwrap();
+        assert_eq!(read_to_end(&Path::new("/hostfs_test/link"))?, b"cnss");
+        assert!(!exists(&Path::new("/hostfs_test/escape"))?);
+        assert!(!exists(&Path::new("/hostfs_test/parent"))?);
+        assert!(!exists(&Path::new("/hostfs_test/missing"))?);
+        // Escaping symlinks and names that are not UTF-8 are not listed
+        let mut names: Vec<_> = read_dir(&Path::new("/hostfs_test"))?.into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["inside", "link"]);
+        assert_eq!(std::fs::read_dir(&base).unwrap().count(), 5);
+
+        // Symlinks are checked again when the file is opened on the host
+        let outside = std::env::temp_dir().join("cnfs_hostfs_outside");
+        std::fs::write(&outside, "secret").unwrap();
+        std::os::unix::fs::symlink("inside", base.join("swapped")).unwrap();
+        let mut file = open(&Path::new("/hostfs_test/swapped"), FileMode::read)?;
+        std::fs::remove_file(base.join("swapped")).unwrap();
+        std::os::unix::fs::symlink(&outside, base.join("swapped")).unwrap();
+        assert_eq!(file.read(&mut dest), Err(CNFSError::PathNotFound));
+        close(file);
+        std::fs::remove_file(base.join("swapped")).unwrap();
+        std::fs::remove_file(&outside).unwrap();
+
+        remove(&Path::new("/hostfs_test/link"))?;
+        assert!(base.join("inside").exists());
+        for name in ["inside", "escape", "parent"]
+        {
+            std::fs::remove_file(base.join(name)).unwrap();
+        }
+        // The file whose name is not UTF-8 is still there
+        assert!(read_dir(&Path::new("/hostfs_test"))?.is_empty());
+        assert_eq!(remove(&Path::new("/hostfs_test")), Err(CNFSError::DirectoryNotEmpty));
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    umount(Path::new("/"))?;
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use cnfs::{bind_mount, create_directory, init, mount, open, umount, write_all, CNFSError, CNFSResult, FileLock,
+           FileMode, LockType, MountOptions, Path, RamFs, VfsConfig};
+use std::sync::Arc;
+
+#[test]
+fn lock_test() -> CNFSResult
+{
+    init(VfsConfig::default().dcache_size(4))?;
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/state"))?;
+    create_directory(&Path::new("/view"))?;
+    bind_mount(&Path::new("/state"), &Path::new("/view"), MountOptions::new())?;
+    write_all(&Path::new("/state/db"), b"0123456789")?;
+    let rw = FileMode::read | FileMode::write;
+
+    // Whole-file locks apply across every file opened on the inode, even through bind mounts
+    let first = open(&Path::new("/state/db"), rw)?;
+    let second = open(&Path::new("/view/db"), rw)?;
+    first.try_lock_shared()?;
+    second.try_lock_shared()?;
+    assert_eq!(second.try_lock_exclusive(), Err(CNFSError::WouldBlock));
+    first.unlock();
+    second.try_lock_exclusive()?;
+    assert_eq!(first.try_lock_shared(), Err(CNFSError::WouldBlock));
+    // Waiting can't let the holder run, so the blocking calls fail the same way
+    assert_eq!(first.lock_shared(), Err(CNFSError::WouldBlock));
+    assert_eq!(first.lock_range(0, 1, LockType::Shared), Err(CNFSError::WouldBlock));
+    let holder = Some(FileLock { start: 0, end: u64::MAX, lock_type: LockType::Exclusive });
+    assert_eq!(first.conflicting_lock(0, 1, LockType::Shared)?, holder);
+    assert_eq!(second.conflicting_lock(0, 1, LockType::Exclusive)?, None);
+
+    // Locks outlive the dentry cache while the file is open
+    for i in 0..8
+    {
+        write_all(&Path::new(&format!("/state/evict{i}")), b"")?;
+    }
+    let third = open(&Path::new("/state/db"), rw)?;
+    assert_eq!(third.try_lock_shared(), Err(CNFSError::WouldBlock));
+    drop(third);
+
+    // Locks are released when the file is dropped
+    drop(second);
+    first.lock_exclusive()?;
+    first.unlock();
+
+    // Byte-range locks only conflict where they overlap
+    let second = open(&Path::new("/state/db"), rw)?;
+    first.try_lock_range(0, 4, LockType::Exclusive)?;
+    second.try_lock_range(4, 4, LockType::Exclusive)?;
+    second.try_lock_range(8, 0, LockType::Shared)?;
+    assert_eq!(second.try_lock_range(3, 2, LockType::Shared), Err(CNFSError::WouldBlock));
+    assert_eq!(first.try_lock_range(100, 1, LockType::Exclusive), Err(CNFSError::WouldBlock));
+    first.try_lock_range(100, 1, LockType::Shared)?;
+    assert_eq!(second.try_lock_exclusive(), Err(CNFSError::WouldBlock));
+
+    // Unlocking the middle of a range splits it
+    first.unlock_range(1, 2)?;
+    second.try_lock_range(1, 2, LockType::Exclusive)?;
+    assert_eq!(second.try_lock_range(0, 1, LockType::Shared), Err(CNFSError::WouldBlock));
+    assert_eq!(second.try_lock_range(3, 1, LockType::Shared), Err(CNFSError::WouldBlock));
+    let split = Some(FileLock { start: 3, end: 4, lock_type: LockType::Exclusive });
+    assert_eq!(second.conflicting_lock(3, 10, LockType::Shared)?, split);
+
+    // Setting a lock replaces the ones the file holds on the range
+    second.try_lock_range(4, 0, LockType::Shared)?;
+    first.try_lock_range(20, 0, LockType::Shared)?;
+    assert_eq!(first.try_lock_range(6, 1, LockType::Exclusive), Err(CNFSError::WouldBlock));
+    second.unlock_range(0, 0)?;
+    first.try_lock_range(6, 1, LockType::Exclusive)?;
+    drop(second);
+
+    // Range locks need the matching file mode, like fcntl
+    let reader = open(&Path::new("/state/db"), FileMode::read)?;
+    assert_eq!(reader.try_lock_range(0, 1, LockType::Exclusive), Err(CNFSError::InvalidArgument));
+    assert_eq!(reader.try_lock_range(u64::MAX, 2, LockType::Shared), Err(CNFSError::InvalidArgument));
+    reader.try_lock_range(10, 0, LockType::Shared)?;
+    drop(reader);
+    drop(first);
+
+    umount(Path::new("/view"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::{create_directory, exists, init, mount, mount_loop, open, read_to_end, remount, umount,
+           umount_with_flags, write_all, BlockDevice, CNFSError, CNFSResult, FatFileSystem, FileMode,
+           LoopDevice, MountOptions, Path, UmountFlags, VfsConfig};
+use std::io::Cursor;
+use std::sync::Arc;
+
+#[test]
+fn loop_mount_test() -> CNFSResult
+{
+    // A tiny dentry cache, so dentries of loop mounts are dropped in the middle of nested calls
+    init(VfsConfig::default().dcache_size(4))?;
+
+    // A 4 MiB FAT volume in memory, like a USB stick
+    let mut usb = Cursor::new(vec![0_u8; 4 << 20]);
+    fatfs::format_volume(&mut usb, fatfs::FormatVolumeOptions::new()).unwrap();
+    mount(Arc::new(cnfs::RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/mnt"))?;
+    create_directory(&Path::new("/mnt/usb"))?;
+    create_directory(&Path::new("/images"))?;
+    create_directory(&Path::new("/images/disk"))?;
+    create_directory(&Path::new("/images/fat"))?;
+    mount(Arc::new(FatFileSystem::open(usb)?), Path::new("/mnt/usb"), MountOptions::new())?;
+
+    // ext2 image on the FAT mount, and a FAT image inside the ext2 one
+    write_all(&Path::new("/mnt/usb/disk.img"), &std::fs::read("tests/resources/ext2_1.img").unwrap())?;
+    mount_loop("auto", &Path::new("/mnt/usb/disk.img"), Path::new("/images/disk"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/images/disk/hello.txt"))?, b"cnss");
+    write_all(&Path::new("/images/disk/fat.img"), &std::fs::read("tests/resources/fat_1.img").unwrap())?;
+    mount_loop("auto", &Path::new("/images/disk/fat.img"), Path::new("/images/fat"), MountOptions::new())?;
+
+    let data: Vec<u8> = b"cnss{th1s_i5_my_vfs_t3st}".iter().cycle().take(100 * 1024).copied().collect();
+    for i in 0..8
+    {
+        create_directory(&Path::new(&format!("/images/fat/dir{i}")))?;
+        write_all(&Path::new(&format!("/images/fat/dir{i}/file")), &data[..i * 1024])?;
+    }
+    write_all(&Path::new("/images/fat/large"), &data)?;
+    write_all(&Path::new("/images/disk/dir/ext2"), b"cnss{ext2}")?;
+    assert_eq!(read_to_end(&Path::new("/images/fat/large"))?, data);
+    remount(Path::new("/images/fat"), MountOptions::new().synchronous(true))?;
+    write_all(&Path::new("/images/fat/sync"), b"cnss{sync}")?;
+
+    // The image holding a mounted filesystem is busy
+    assert_eq!(umount(Path::new("/mnt/usb")), Err(CNFSError::Busy));
+    assert_eq!(umount(Path::new("/images/disk")), Err(CNFSError::Busy));
+    umount(Path::new("/images/fat"))?;
+    umount(Path::new("/images/disk"))?;
+
+    // The other view of the image sees the loop writes
+    let mut image = open(&Path::new("/mnt/usb/disk.img"), FileMode::read)?;
+    let device = LoopDevice::new(open(&Path::new("/mnt/usb/disk.img"), FileMode::read)?, 1024)?;
+    assert_eq!(device.block_count(), 2048);
+    assert_eq!(device.write_at(0, b"cnss"), Err(CNFSError::InvalidArgument));
+    let mut magic = [0_u8; 2];
+    image.read_at(1024 + 56, &mut magic)?;
+    assert_eq!(magic, [0x53, 0xEF]);
+    drop(image);
+    drop(device);
+
+    // Everything is in the FAT volume, read-only this time
+    mount_loop("ext2", &Path::new("/mnt/usb/disk.img"), Path::new("/images/disk"),
+               MountOptions::new().read_only(true))?;
+    mount_loop("vfat", &Path::new("/images/disk/fat.img"), Path::new("/images/fat"),
+               MountOptions::new().read_only(true))?;
+    for i in 0..8
+    {
+        assert_eq!(read_to_end(&Path::new(&format!("/images/fat/dir{i}/file")))?, &data[..i * 1024]);
+    }
+    assert_eq!(read_to_end(&Path::new("/images/fat/large"))?, data);
+    assert_eq!(read_to_end(&Path::new("/images/fat/sync"))?, b"cnss{sync}");
+    assert_eq!(read_to_end(&Path::new("/images/disk/dir/ext2"))?, b"cnss{ext2}");
+    assert_eq!(write_all(&Path::new("/images/fat/new"), b"cnss"), Err(CNFSError::ReadOnly));
+    assert!(!exists(&Path::new("/images/fat/new"))?);
+    umount(Path::new("/images/fat"))?;
+    umount(Path::new("/images/disk"))?;
+
+    assert_eq!(mount_loop("auto", &Path::new("/mnt/usb/missing.img"), Path::new("/images/disk"),
+                          MountOptions::new().read_only(true)), Err(CNFSError::PathNotFound));
+
+    // The loop mount keeps a lazily unmounted filesystem alive
+    mount_loop("ext2", &Path::new("/mnt/usb/disk.img"), Path::new("/images/disk"), MountOptions::new())?;
+    umount_with_flags(Path::new("/mnt/usb"), UmountFlags::LAZY)?;
+    assert!(!exists(&Path::new("/mnt/usb/disk.img"))?);
+    write_all(&Path::new("/images/disk/lazy"), b"cnss{lazy}")?;
+    assert_eq!(read_to_end(&Path::new("/images/disk/lazy"))?, b"cnss{lazy}");
+    umount(Path::new("/images/disk"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -1,34 +1,34 @@
-use lazy_static::lazy_static;
+#![cfg(feature = "fatfs")]
+
 use std::env::current_dir;
 use std::sync::Arc;
 
-use cnfs::{create_directory, exists, mount, open, remove, CNFSResult, FileMode, Path};
-
-mod adapter;
-use adapter::*;
+use cnfs::{create_directory, exists, mount, mount_point_of, mounts, open, proc_mounts, remove, umount,
+           BlockStorage, CNFSError, CNFSResult, FatFileSystem, FileMode, HostFs, MemBlockDevice, MountOptions,
+           Path};
 
 fn test_dir(dir: &Path) -> CNFSResult
 {
-    if exists(&dir)?
+    if exists(dir)?
     {
-        remove(&dir)?;
+        remove(dir)?;
     }
-    assert!(!exists(&dir)?);
-    create_directory(&dir)?;
-    assert!(exists(&dir)?);
-    remove(&dir)?;
-    assert!(!exists(&dir)?);
+    assert!(!exists(dir)?);
+    create_directory(dir)?;
+    assert!(exists(dir)?);
+    remove(dir)?;
+    assert!(!exists(dir)?);
     Ok(())
 }
 
 fn test_file(path: &Path) -> CNFSResult
 {
-    if exists(&path)?
+    if exists(path)?
     {
-        remove(&path)?;
+        remove(path)?;
     }
-    assert!(!exists(&path)?);
-    let mut file = open(&path, FileMode::write)?;
+    assert!(!exists(path)?);
+    let mut file = open(path, FileMode::write)?;
 
     let data = "cnss{th1s_i5_my_vfs_t3st}";
     let mut dest = vec![0_u8; data.len()];
@@ -54,30 +54,38 @@ fn test_file(path: &Path) -> CNFSResult
         assert_eq!(dest, data.as_bytes());
     }
 
-    assert!(exists(&path)?);
-    remove(&path)?;
-    assert!(!exists(&path)?);
+    assert!(exists(path)?);
+    remove(path)?;
+    assert!(!exists(path)?);
     Ok(())
 }
 
-lazy_static! {
-    pub static ref fat_fs: Arc<fatfs::FSWrapper>
-    = Arc::new(fatfs::FSWrapper::new("tests/resources/fat_2.img".into()));
-}
-
 #[test]
 fn mixed_test() -> CNFSResult
 {
-    fat_fs.init();
-    let std_fs = Arc::new(stdfs::FSWrapper::new(current_dir().unwrap()));
+    let image = std::fs::read("tests/resources/fat_2.img").unwrap();
+    let img = BlockStorage::new(Arc::new(MemBlockDevice::from_vec(512, image)?));
+    let fat_fs = Arc::new(FatFileSystem::open(img)?);
+    let std_fs = Arc::new(HostFs::new(current_dir().unwrap())?);
 
-    mount(std_fs.clone(), Path::new("/"))?;
+    mount(std_fs.clone(), Path::new("/"), MountOptions::new())?;
     let fat_mnt = Path::new("/mnt");
     if !exists(&fat_mnt)?
     {
         create_directory(&fat_mnt)?;
     }
-    mount(fat_fs.clone(), Path::new("/mnt"))?;
+    mount(fat_fs.clone(), Path::new("/mnt"), MountOptions::new().label("fat 2"))?;
+
+    // Mount table
+    let table: Vec<_> = mounts().collect();
+    assert_eq!(table.len(), 2);
+    assert_eq!((table[0].path.clone(), table[0].fs_type.as_str()), (Path::new("/"), "hostfs"));
+    assert_eq!((table[1].path.clone(), table[1].fs_type.as_str()), (Path::new("/mnt"), "vfat"));
+    assert_eq!(table[1].options.label.as_deref(), Some("fat 2"));
+    assert_eq!(mount_point_of(&Path::new("/mnt/a/b"))?, Path::new("/mnt"));
+    assert_eq!(mount_point_of(&Path::new("/mn"))?, Path::new("/"));
+    assert_eq!(proc_mounts(), format!("{} / hostfs rw 0 0\nvfat /mnt vfat rw,label=fat\\0402 0 0\n",
+                                      current_dir().unwrap().to_str().unwrap()));
 
     let dir = Path::new("/test_directory");
     let file = Path::new("/test_file");
@@ -93,6 +101,8 @@ fn mixed_test() -> CNFSResult
     test_file(&file)?;
     test_file(&file1)?;
 
+    assert_eq!(remove(&fat_mnt), Err(CNFSError::Busy));
+    umount(fat_mnt.clone())?;
     remove(&fat_mnt)?;
 
     Ok(())
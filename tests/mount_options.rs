@@ -0,0 +1,43 @@
+use cnfs::{create_directory, mount, open, read_to_end, remount, remove, umount, write_all,
+           CNFSError, CNFSResult, FileMode, HostFs, MountOptions, Path};
+use std::env::current_dir;
+use std::sync::Arc;
+
+#[test]
+fn mount_options_test() -> CNFSResult
+{
+    let fs = Arc::new(HostFs::new(current_dir().unwrap())?);
+    mount(fs, Path::new("/"), MountOptions::new().label("host"))?;
+
+    let test_file = Path::new("/mount_options_test_file");
+    let real_file = current_dir().unwrap().join("mount_options_test_file");
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+    write_all(&test_file, data.as_bytes())?;
+    assert_eq!(read_to_end(&test_file)?, data.as_bytes());
+
+    // Read-only
+    remount(Path::new("/"), MountOptions::new().read_only(true))?;
+    assert_eq!(open(&test_file, FileMode::write).err(), Some(CNFSError::ReadOnly));
+    assert_eq!(write_all(&test_file, data.as_bytes()), Err(CNFSError::ReadOnly));
+    assert_eq!(create_directory(&Path::new("/mount_options_test_dir")), Err(CNFSError::ReadOnly));
+    assert_eq!(remove(&test_file), Err(CNFSError::ReadOnly));
+    assert_eq!(read_to_end(&test_file)?, data.as_bytes());
+
+    // Write-through
+    remount(Path::new("/"), MountOptions::new().synchronous(true))?;
+    let mut file = open(&test_file, FileMode::write)?;
+    file.write_all(b"CNSS")?;
+    assert_eq!(&std::fs::read(&real_file).unwrap()[..4], b"CNSS");
+    drop(file);
+
+    // No-cache
+    remount(Path::new("/"), MountOptions::new().no_cache(true))?;
+    std::fs::write(&real_file, "changed underneath").unwrap();
+    assert_eq!(read_to_end(&test_file)?, b"changed underneath");
+
+    assert_eq!(remount(Path::new("/nowhere"), MountOptions::new()), Err(CNFSError::NoMountedFilesystem));
+
+    remove(&test_file)?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use cnfs::{exists, mount, mount_point_of, mounts, read_to_end, remove, umount, CNFSError, CNFSResult,
+           HostFs, MountOptions, Path};
+use std::env::current_dir;
+use std::sync::Arc;
+
+#[test]
+fn mount_tree_test() -> CNFSResult
+{
+    let base = current_dir().unwrap().join("mount_tree_test");
+    for dir in ["root/mnt", "a/sub", "b", "c"]
+    {
+        std::fs::create_dir_all(base.join(dir)).unwrap();
+    }
+    std::fs::write(base.join("root/mnt/x"), "root").unwrap();
+    std::fs::write(base.join("a/x"), "a").unwrap();
+    std::fs::write(base.join("b/x"), "b").unwrap();
+    std::fs::write(base.join("c/y"), "c").unwrap();
+
+    let root = Arc::new(HostFs::new(base.join("root"))?);
+    let a = Arc::new(HostFs::new(base.join("a"))?);
+    let b = Arc::new(HostFs::new(base.join("b"))?);
+    let c = Arc::new(HostFs::new(base.join("c"))?);
+    let mnt = Path::new("/mnt");
+
+    mount(root, Path::new("/"), MountOptions::new())?;
+    mount(a.clone(), mnt.clone(), MountOptions::new())?;
+    assert_eq!(mount(a, mnt.clone(), MountOptions::new()), Err(CNFSError::AlreadyMountedPath));
+    mount(c, Path::new("/mnt/sub"), MountOptions::new())?;
+
+    // The deepest mount wins on a cold lookup
+    assert_eq!(read_to_end(&Path::new("/mnt/x"))?, b"a");
+    assert_eq!(read_to_end(&Path::new("/mnt/sub/y"))?, b"c");
+    assert_eq!(mount_point_of(&Path::new("/mnt/sub/y"))?, Path::new("/mnt/sub"));
+
+    // Stacking hides the mount under it together with everything mounted inside it
+    mount(b, mnt.clone(), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/mnt/x"))?, b"b");
+    assert!(!exists(&Path::new("/mnt/sub/y"))?);
+    assert_eq!(mount_point_of(&Path::new("/mnt/sub/y"))?, mnt);
+    let table: Vec<_> = mounts().map(|m| m.path).collect();
+    assert_eq!(table, [Path::new("/"), mnt.clone(), Path::new("/mnt/sub"), mnt.clone()]);
+
+    // Unmounting reveals the one underneath
+    umount(mnt.clone())?;
+    assert_eq!(read_to_end(&Path::new("/mnt/x"))?, b"a");
+    assert_eq!(read_to_end(&Path::new("/mnt/sub/y"))?, b"c");
+    assert_eq!(umount(mnt.clone()), Err(CNFSError::Busy));
+    assert_eq!(remove(&Path::new("/mnt/sub")), Err(CNFSError::Busy));
+    assert_eq!(remove(&mnt), Err(CNFSError::Busy));
+    umount(Path::new("/mnt/sub"))?;
+    umount(mnt.clone())?;
+    assert_eq!(read_to_end(&Path::new("/mnt/x"))?, b"root");
+
+    umount(Path::new("/"))?;
+    assert_eq!(mount_point_of(&mnt), Err(CNFSError::NoMountedFilesystem));
+    std::fs::remove_dir_all(&base).unwrap();
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use cnfs::{bind_mount, create_directory, exists, init, mkfifo, mount, open, read_to_end, remove, rename, umount, watch,
+           write_all, CNFSError, CNFSResult, Event, EventMask, FileMode, MountOptions, Path, RamFs, VfsConfig};
+use std::sync::Arc;
+
+fn event(path: &str, kind: EventMask) -> Event
+{
+    Event { path: Path::new(path), kind }
+}
+
+#[test]
+fn notify_test() -> CNFSResult
+{
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/inbox"))?;
+    create_directory(&Path::new("/mnt"))?;
+    assert_eq!(watch(&Path::new("/missing"), EventMask::all()).err(), Some(CNFSError::PathNotFound));
+
+    // A directory reports its children, but not the files in its subdirectories
+    let inbox = watch(&Path::new("/inbox"), EventMask::all())?;
+    write_all(&Path::new("/inbox/mail"), b"cnss")?;
+    create_directory(&Path::new("/inbox/old"))?;
+    write_all(&Path::new("/inbox/old/mail"), b"cnss")?;
+    mkfifo(&Path::new("/inbox/fifo"))?;
+    remove(&Path::new("/inbox/fifo"))?;
+    assert_eq!(inbox.events(), [
+        event("/inbox/mail", EventMask::CREATE),
+        event("/inbox/mail", EventMask::MODIFY),
+        event("/inbox/mail", EventMask::CLOSE_WRITE),
+        event("/inbox/old", EventMask::CREATE),
+        event("/inbox/fifo", EventMask::CREATE),
+        event("/inbox/fifo", EventMask::REMOVE),
+    ]);
+    assert_eq!(inbox.next_event(), None);
+
+    // Buffered data is reported when it reaches the filesystem
+    let mail = watch(&Path::new("/inbox/mail"), EventMask::MODIFY | EventMask::CLOSE_WRITE | EventMask::REMOVE)?;
+    let mut file = open(&Path::new("/inbox/mail"), FileMode::write)?;
+    file.write_all(b"hello")?;
+    assert_eq!(mail.next_event(), None);
+    file.sync()?;
+    assert_eq!(mail.next_event(), Some(event("/inbox/mail", EventMask::MODIFY)));
+    drop(file);
+    assert_eq!(mail.next_event(), Some(event("/inbox/mail", EventMask::CLOSE_WRITE)));
+    let mut file = open(&Path::new("/inbox/mail"), FileMode::read)?;
+    file.read(&mut [0; 4])?;
+    drop(file);
+    remove(&Path::new("/inbox/mail"))?;
+    assert_eq!(mail.collect::<Vec<_>>(), [event("/inbox/mail", EventMask::REMOVE)]);
+    inbox.events();
+
+    // Changes through a bind mount are reported at the watched path
+    create_directory(&Path::new("/view"))?;
+    let root = watch(&Path::new("/"), EventMask::MOUNT | EventMask::UMOUNT)?;
+    bind_mount(&Path::new("/inbox"), &Path::new("/view"), MountOptions::new())?;
+    write_all(&Path::new("/view/letter"), b"cnss")?;
+    assert_eq!(inbox.next_event(), Some(event("/inbox/letter", EventMask::CREATE)));
+    umount(Path::new("/view"))?;
+
+    // Mount points report mounts on them
+    let mnt = watch(&Path::new("/mnt"), EventMask::MOUNT | EventMask::UMOUNT)?;
+    mount(Arc::new(RamFs::new()), Path::new("/mnt"), MountOptions::new())?;
+    umount(Path::new("/mnt"))?;
+    assert_eq!(mnt.events(), [event("/mnt", EventMask::MOUNT), event("/mnt", EventMask::UMOUNT)]);
+    assert_eq!(root.events(), [
+        event("/view", EventMask::MOUNT),
+        event("/view", EventMask::UMOUNT),
+        event("/mnt", EventMask::MOUNT),
+        event("/mnt", EventMask::UMOUNT),
+    ]);
+
+    // Repeated events are merged, and a full queue drops the new ones after an overflow event
+    init(VfsConfig::default().watch_queue_size(2))?;
+    let small = watch(&Path::new("/inbox"), EventMask::MODIFY | EventMask::CREATE)?;
+    let mut file = open(&Path::new("/inbox/letter"), FileMode::write)?;
+    for _ in 0..3
+    {
+        file.write_all(b"cnss")?;
+        file.sync()?;
+    }
+    drop(file);
+    write_all(&Path::new("/inbox/note"), b"cnss")?;
+    write_all(&Path::new("/inbox/memo"), b"cnss")?;
+    assert_eq!(small.events(), [
+        event("/inbox/letter", EventMask::MODIFY),
+        event("/inbox/note", EventMask::CREATE),
+        event("/inbox", EventMask::OVERFLOW),
+    ]);
+    write_all(&Path::new("/inbox/draft"), b"cnss")?;
+    assert_eq!(small.next_event(), Some(event("/inbox/draft", EventMask::CREATE)));
+    drop(small);
+
+    // Renames are reported at the old and new paths, and watches follow the renamed files
+    inbox.events();
+    let old = watch(&Path::new("/inbox/old"), EventMask::CREATE)?;
+    rename(&Path::new("/inbox/old"), &Path::new("/inbox/archive"))?;
+    rename(&Path::new("/inbox/draft"), &Path::new("/inbox/sent"))?;
+    assert_eq!(inbox.events(), [
+        event("/inbox/old", EventMask::RENAME),
+        event("/inbox/archive", EventMask::RENAME),
+        event("/inbox/draft", EventMask::RENAME),
+        event("/inbox/sent", EventMask::RENAME),
+    ]);
+    assert_eq!(read_to_end(&Path::new("/inbox/archive/mail"))?, b"cnss");
+    assert!(!exists(&Path::new("/inbox/draft"))?);
+    write_all(&Path::new("/inbox/archive/reply"), b"cnss")?;
+    assert_eq!(old.events(), [event("/inbox/old/reply", EventMask::CREATE)]);
+    assert_eq!(rename(&Path::new("/inbox/sent"), &Path::new("/inbox/note")), Err(CNFSError::AlreadyExisted));
+    assert_eq!(rename(&Path::new("/inbox/sent"), &Path::new("/sent")), Err(CNFSError::InvalidArgument));
+    let file = open(&Path::new("/inbox/archive/mail"), FileMode::read)?;
+    assert_eq!(rename(&Path::new("/inbox/archive"), &Path::new("/inbox/old")), Err(CNFSError::Busy));
+    drop(file);
+    mount(Arc::new(RamFs::new()), Path::new("/mnt"), MountOptions::new())?;
+    assert_eq!(rename(&Path::new("/mnt"), &Path::new("/media")), Err(CNFSError::Busy));
+    umount(Path::new("/mnt"))?;
+    drop(old);
+
+    // Dropped watches stop
+    drop(inbox);
+    drop(root);
+    drop(mnt);
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::{create_directory, exists, mount, open, read_dir, read_to_end, remove, umount, write_all,
+           BlockStorage, CNFSError, CNFSResult, DirEntry, FatFileSystem, FileMode, InodeType,
+           MemBlockDevice, MountOptions, OverlayFs, Path, RamFs};
+use std::sync::Arc;
+
+fn names(path: &str) -> CNFSResult<Vec<String>>
+{
+    Ok(read_dir(&Path::new(path))?.into_iter().map(|e| e.name).collect())
+}
+
+#[test]
+fn overlay_test() -> CNFSResult
+{
+    // A FAT image with some content
+    let image = std::fs::read("tests/resources/fat_2.img").unwrap();
+    let device = Arc::new(MemBlockDevice::from_vec(512, image)?);
+    mount(Arc::new(FatFileSystem::open(BlockStorage::new(device.clone()))?), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/etc"))?;
+    create_directory(&Path::new("/etc/conf.d"))?;
+    create_directory(&Path::new("/var"))?;
+    write_all(&Path::new("/etc/hosts"), b"cnss{hosts}")?;
+    write_all(&Path::new("/etc/conf.d/net"), b"cnss{net}")?;
+    write_all(&Path::new("/var/log"), b"cnss{log}")?;
+    write_all(&Path::new("/readme"), b"cnss{readme}")?;
+    umount(Path::new("/"))?;
+    let pristine = device.to_vec();
+
+    let lower = Arc::new(FatFileSystem::open(BlockStorage::new(device.clone()))?);
+    let upper = Arc::new(RamFs::new());
+    mount(Arc::new(OverlayFs::new(lower, upper.clone())), Path::new("/"), MountOptions::new())?;
+
+    // Lookups fall through
+    assert_eq!(read_to_end(&Path::new("/etc/conf.d/net"))?, b"cnss{net}");
+    assert_eq!(read_dir(&Path::new("/"))?, [
+        DirEntry { name: "etc".into(), inode_type: InodeType::Dir },
+        DirEntry { name: "readme".into(), inode_type: InodeType::File },
+        DirEntry { name: "var".into(), inode_type: InodeType::Dir },
+    ]);
+
+    // Copy up on the first write
+    let mut hosts = open(&Path::new("/etc/hosts"), FileMode::write)?;
+    hosts.seek(11)?;
+    hosts.write_all(b"+cnss{upper}")?;
+    drop(hosts);
+    assert_eq!(read_to_end(&Path::new("/etc/hosts"))?, b"cnss{hosts}+cnss{upper}");
+    assert_eq!(cnfs::metadata(&Path::new("/etc/hosts"))?.size, 23);
+    write_all(&Path::new("/etc/new"), b"cnss{new}")?;
+    assert_eq!(names("/etc")?, ["conf.d", "hosts", "new"]);
+
+    // Whiteouts
+    remove(&Path::new("/readme"))?;
+    remove(&Path::new("/etc/conf.d/net"))?;
+    assert!(!exists(&Path::new("/readme"))?);
+    assert_eq!(remove(&Path::new("/var")), Err(CNFSError::DirectoryNotEmpty));
+    remove(&Path::new("/var/log"))?;
+    remove(&Path::new("/var"))?;
+    remove(&Path::new("/etc/conf.d"))?;
+    assert_eq!(names("/")?, ["etc"]);
+    assert_eq!(names("/etc")?, ["hosts", "new"]);
+    assert_eq!(create_directory(&Path::new("/.wh.etc")), Err(CNFSError::InvalidPath));
+    assert!(!exists(&Path::new("/.wh.readme"))?);
+
+    // A new directory in place of a removed one is empty
+    create_directory(&Path::new("/var"))?;
+    assert!(names("/var")?.is_empty());
+    assert!(!exists(&Path::new("/var/log"))?);
+    write_all(&Path::new("/readme"), b"cnss{again}")?;
+    assert_eq!(read_to_end(&Path::new("/readme"))?, b"cnss{again}");
+    assert_eq!(create_directory(&Path::new("/etc")), Err(CNFSError::AlreadyExisted));
+    assert_eq!(names("/")?, ["etc", "readme", "var"]);
+    umount(Path::new("/"))?;
+
+    // The lower image is untouched, and the upper layer holds the changes
+    assert!(device.to_vec() == pristine);
+    mount(upper.clone(), Path::new("/"), MountOptions::new())?;
+    assert_eq!(names("/")?, ["etc", "readme", "var"]);
+    assert_eq!(names("/etc")?, [".wh.conf.d", "hosts", "new"]);
+    assert_eq!(names("/var")?, [".wh..wh..opq"]);
+    umount(Path::new("/"))?;
+
+    // And the changes are seen again by a new overlay
+    let lower = Arc::new(FatFileSystem::open(BlockStorage::new(device.clone()))?);
+    mount(Arc::new(OverlayFs::new(lower, upper)), Path::new("/"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/etc/hosts"))?, b"cnss{hosts}+cnss{upper}");
+    assert!(!exists(&Path::new("/etc/conf.d"))?);
+    umount(Path::new("/"))?;
+    assert!(device.to_vec() == pristine);
+    Ok(())
+}
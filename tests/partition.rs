@@ -0,0 +1,74 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::{exists, mount_by_type, mount_partition, read_partitions, read_to_end, umount, write_all,
+           BlockDevice, BlockDeviceRef, CNFSError, CNFSResult, FileBlockDevice, MemBlockDevice, MountOptions,
+           MountSource, PartitionDevice, PartitionType, Path};
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// Load a disk image in memory, so the resource is not modified
+fn disk(name: &str) -> BlockDeviceRef
+{
+    let image = std::fs::read(format!("tests/resources/{name}")).unwrap();
+    Arc::new(FileBlockDevice::new(Cursor::new(image), 512).unwrap())
+}
+
+#[test]
+fn partition_test() -> CNFSResult
+{
+    // MBR with a logical partition
+    let mbr = disk("disk_mbr.img");
+    let partitions = read_partitions(mbr.as_ref())?;
+    let layout: Vec<_> = partitions.iter().map(|p| (p.number, p.offset / 512, p.size / 512, p.partition_type))
+        .collect();
+    assert_eq!(layout, [(1, 2048, 1024, PartitionType::Mbr(0x0C)), (2, 4096, 4096, PartitionType::Mbr(0x83)),
+                        (5, 8193, 1024, PartitionType::Mbr(0x0C))]);
+
+    mount_partition("auto", mbr.clone(), 2, Path::new("/"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/hello.txt"))?, b"cnss");
+    mount_partition("vfat", mbr.clone(), 1, Path::new("/dir"), MountOptions::new())?;
+    mount_partition("vfat", mbr.clone(), 5, Path::new("/hello.txt"), MountOptions::new())?;
+    write_all(&Path::new("/dir/first"), b"cnss{first}")?;
+    write_all(&Path::new("/hello.txt/logical"), b"cnss{logical}")?;
+    assert_eq!(mount_partition("vfat", mbr.clone(), 3, Path::new("/link"), MountOptions::new()),
+               Err(CNFSError::PathNotFound));
+    umount(Path::new("/hello.txt"))?;
+    umount(Path::new("/dir"))?;
+    umount(Path::new("/"))?;
+
+    // Writes stay inside their partitions
+    let fat = PartitionDevice::open(mbr.clone(), 1)?;
+    assert_eq!(fat.offset(), 2048 * 512);
+    mount_by_type("vfat", MountSource::Device(Arc::new(fat)), Path::new("/"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/first"))?, b"cnss{first}");
+    assert!(!exists(&Path::new("/logical"))?);
+    umount(Path::new("/"))?;
+    mount_partition("vfat", mbr.clone(), 5, Path::new("/"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/logical"))?, b"cnss{logical}");
+    umount(Path::new("/"))?;
+    mount_partition("ext2", mbr.clone(), 2, Path::new("/"), MountOptions::new())?;
+    assert!(!exists(&Path::new("/first"))?);
+    umount(Path::new("/"))?;
+
+    // GPT, also with a corrupted primary header
+    let gpt = disk("disk_gpt.img");
+    let partitions = read_partitions(gpt.as_ref())?;
+    let names: Vec<_> = partitions.iter().map(|p| (p.number, p.name.as_str(), p.offset / 512)).collect();
+    assert_eq!(names, [(1, "fat", 2048), (2, "ext2", 4096)]);
+    gpt.write_at(512 + 8, &[0xFF; 4])?;
+    assert_eq!(read_partitions(gpt.as_ref())?, partitions);
+    mount_partition("auto", gpt.clone(), 2, Path::new("/"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/dir/nested.txt"))?, b"cnss{nested}");
+    umount(Path::new("/"))?;
+
+    // Bare volume and bounds
+    assert!(read_partitions(disk("ext2_1.img").as_ref())?.is_empty());
+    let small: BlockDeviceRef = Arc::new(MemBlockDevice::new(512, 8)?);
+    assert_eq!(PartitionDevice::new(small.clone(), 512, 4096).err(), Some(CNFSError::InvalidArgument));
+    assert_eq!(PartitionDevice::new(small.clone(), 100, 512).err(), Some(CNFSError::InvalidArgument));
+    assert_eq!(PartitionDevice::new(small.clone(), u64::MAX - 511, 1024).err(), Some(CNFSError::InvalidArgument));
+    let part = PartitionDevice::new(small.clone(), 1024, 1024)?;
+    assert_eq!(part.write_at(1020, b"cnss{overflow}"), Err(CNFSError::InvalidArgument));
+    assert_eq!(part.write_blocks(2, &[0; 512]), Err(CNFSError::InvalidArgument));
+    Ok(())
+}
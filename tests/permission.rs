@@ -0,0 +1,132 @@
+use cnfs::{bind_mount, chmod, chown, create_directory, credentials, metadata, mkfifo, mount, open, proc_mounts,
+           read_dir, read_to_end, remount, remove, set_credentials, set_modified, umount, with_credentials,
+           write_all, CNFSError, CNFSResult, Credentials, FileMode, MountOptions, Path, RamFs, TarFs};
+use std::sync::Arc;
+
+const BUNDLE: &[u8] = include_bytes!("resources/bundle.tar");
+
+fn owner_and_mode(path: &str) -> CNFSResult<(u32, u32, u32)>
+{
+    let metadata = metadata(&Path::new(path))?;
+    Ok((metadata.uid.unwrap(), metadata.gid.unwrap(), metadata.mode.unwrap()))
+}
+
+#[test]
+fn permission_test() -> CNFSResult
+{
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    let alice = Credentials::new(1000, 1000);
+    let bob = Credentials::new(1001, 1001).groups(&[1000]);
+    let eve = Credentials::new(1002, 1002);
+
+    // The superuser makes a home for each tenant
+    assert_eq!(credentials(), Credentials::root());
+    for (home, tenant) in [("/alice", &alice), ("/bob", &bob)]
+    {
+        create_directory(&Path::new(home))?;
+        chown(&Path::new(home), Some(tenant.uid), Some(tenant.gid))?;
+        chmod(&Path::new(home), 0o750)?;
+    }
+    assert_eq!(owner_and_mode("/alice")?, (1000, 1000, 0o750));
+    assert_eq!(chmod(&Path::new("/alice"), 0o10000), Err(CNFSError::InvalidArgument));
+
+    // New files belong to their creator
+    set_credentials(alice.clone());
+    write_all(&Path::new("/alice/notes"), b"cnss")?;
+    assert_eq!(owner_and_mode("/alice/notes")?, (1000, 1000, 0o644));
+    mkfifo(&Path::new("/alice/fifo"))?;
+    assert_eq!(owner_and_mode("/alice/fifo")?, (1000, 1000, 0o644));
+    assert_eq!(create_directory(&Path::new("/bob/x")), Err(CNFSError::PermissionDenied));
+    assert_eq!(create_directory(&Path::new("/shared")), Err(CNFSError::PermissionDenied));
+
+    // Group members can read, others can't even search the directory
+    with_credentials(bob.clone(), || -> CNFSResult {
+        assert_eq!(read_to_end(&Path::new("/alice/notes"))?, b"cnss");
+        assert_eq!(read_dir(&Path::new("/alice"))?.len(), 2);
+        assert_eq!(open(&Path::new("/alice/notes"), FileMode::write).err(), Some(CNFSError::PermissionDenied));
+        assert_eq!(write_all(&Path::new("/alice/new"), b"x"), Err(CNFSError::PermissionDenied));
+        assert_eq!(remove(&Path::new("/alice/notes")), Err(CNFSError::PermissionDenied));
+        assert_eq!(set_modified(&Path::new("/alice/notes"), 0), Err(CNFSError::PermissionDenied));
+        Ok(())
+    })?;
+    set_modified(&Path::new("/alice/notes"), 0)?;
+    with_credentials(eve.clone(), || -> CNFSResult {
+        assert_eq!(read_to_end(&Path::new("/alice/notes")), Err(CNFSError::PermissionDenied));
+        assert_eq!(metadata(&Path::new("/alice/notes")), Err(CNFSError::PermissionDenied));
+        assert_eq!(read_dir(&Path::new("/alice")), Err(CNFSError::PermissionDenied));
+        Ok(())
+    })?;
+    assert_eq!(credentials(), alice);
+
+    // Only the owner changes the mode, and only to groups they are in
+    chmod(&Path::new("/alice/notes"), 0o600)?;
+    with_credentials(bob.clone(), || {
+        assert_eq!(read_to_end(&Path::new("/alice/notes")), Err(CNFSError::PermissionDenied));
+        assert_eq!(chmod(&Path::new("/alice/notes"), 0o644), Err(CNFSError::PermissionDenied));
+    });
+    assert_eq!(chown(&Path::new("/alice/notes"), Some(1001), None), Err(CNFSError::PermissionDenied));
+    assert_eq!(chown(&Path::new("/alice/notes"), None, Some(1001)), Err(CNFSError::PermissionDenied));
+    set_credentials(alice.clone().groups(&[100]));
+    chown(&Path::new("/alice/notes"), None, Some(100))?;
+    assert_eq!(owner_and_mode("/alice/notes")?, (1000, 100, 0o600));
+
+    // The owner's bits apply even when the group's allow more
+    chmod(&Path::new("/alice/notes"), 0o066)?;
+    assert_eq!(read_to_end(&Path::new("/alice/notes")), Err(CNFSError::PermissionDenied));
+    chmod(&Path::new("/alice/notes"), 0o644)?;
+
+    // Sticky directories only let owners remove entries
+    set_credentials(Credentials::root());
+    create_directory(&Path::new("/tmp"))?;
+    chmod(&Path::new("/tmp"), 0o1777)?;
+    with_credentials(alice.clone(), || write_all(&Path::new("/tmp/alice"), b"cnss"))?;
+    assert_eq!(with_credentials(bob.clone(), || remove(&Path::new("/tmp/alice"))), Err(CNFSError::PermissionDenied));
+    with_credentials(alice.clone(), || remove(&Path::new("/tmp/alice")))?;
+
+    // Filesystems without owners take them from the mount options
+    create_directory(&Path::new("/bundle"))?;
+    let options = MountOptions::new().read_only(true).owner(1000, 1000).umask(0o027);
+    mount(Arc::new(TarFs::new(BUNDLE.to_vec())?), Path::new("/bundle"), options)?;
+    assert_eq!(owner_and_mode("/bundle/firmware")?, (1000, 1000, 0o750));
+    assert_eq!(owner_and_mode("/bundle/version")?, (1000, 1000, 0o640));
+    assert!(proc_mounts().contains(" /bundle tar ro,uid=1000,gid=1000,umask=027 0 0"));
+    assert_eq!(chmod(&Path::new("/bundle/version"), 0o600), Err(CNFSError::ReadOnly));
+    with_credentials(eve.clone(), || {
+        assert_eq!(read_to_end(&Path::new("/bundle/version")), Err(CNFSError::PermissionDenied));
+    });
+    with_credentials(alice.clone(), || -> CNFSResult {
+        assert_eq!(read_to_end(&Path::new("/bundle/version"))?, b"cnss");
+        Ok(())
+    })?;
+
+    // Only the user the mount options give the files to creates them without owners
+    #[cfg(feature = "fatfs")]
+    {
+        let image = std::io::Cursor::new(std::fs::read("tests/resources/fat_1.img").unwrap());
+        create_directory(&Path::new("/fat"))?;
+        let options = MountOptions::new().owner(1000, 1000).umask(0);
+        mount(Arc::new(cnfs::FatFileSystem::open(image)?), Path::new("/fat"), options)?;
+        with_credentials(alice.clone(), || write_all(&Path::new("/fat/alice"), b"cnss"))?;
+        assert_eq!(owner_and_mode("/fat/alice")?, (1000, 1000, 0o666));
+        with_credentials(bob.clone(), || {
+            assert_eq!(write_all(&Path::new("/fat/bob"), b"cnss"), Err(CNFSError::PermissionDenied));
+            assert_eq!(create_directory(&Path::new("/fat/bob")), Err(CNFSError::PermissionDenied));
+        });
+        assert!(!cnfs::exists(&Path::new("/fat/bob"))?);
+        umount(Path::new("/fat"))?;
+    }
+
+    // Only the superuser changes the mount table
+    with_credentials(alice.clone(), || {
+        let bundle = Path::new("/bundle");
+        let mounted = mount(Arc::new(RamFs::new()), Path::new("/alice"), MountOptions::new());
+        assert_eq!(mounted, Err(CNFSError::PermissionDenied));
+        assert_eq!(bind_mount(&bundle, &Path::new("/alice"), MountOptions::new()), Err(CNFSError::PermissionDenied));
+        assert_eq!(remount(bundle.clone(), MountOptions::new()), Err(CNFSError::PermissionDenied));
+        assert_eq!(umount(bundle), Err(CNFSError::PermissionDenied));
+    });
+    umount(Path::new("/bundle"))?;
+
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use cnfs::{bind_mount, config, create_directory, exists, init, metadata, mkfifo, mount, open, pipe, read_dir,
+           remove, umount, CNFSError, CNFSResult, FileMode, InodeType, MountOptions, Path, RamFs, VfsConfig};
+use std::sync::Arc;
+
+#[test]
+fn pipe_test() -> CNFSResult
+{
+    init(config().pipe_buffer_size(8))?;
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+
+    // Anonymous pipes are bounded and read the end of file once the writer is closed
+    let (mut reader, mut writer) = pipe()?;
+    let mut buffer = [0_u8; 16];
+    assert_eq!(reader.read(&mut buffer), Err(CNFSError::WouldBlock));
+    assert_eq!(writer.write(b"hello, cnss"), Ok(8));
+    assert_eq!(writer.write(b"!"), Err(CNFSError::WouldBlock));
+    assert_eq!(reader.read(&mut buffer[..5])?, 5);
+    assert_eq!(&buffer[..5], b"hello");
+    writer.write_all(b" cn")?;
+    assert_eq!(reader.read(&mut buffer)?, 6);
+    assert_eq!(&buffer[..6], b", c cn");
+    assert_eq!(writer.read(&mut buffer), Err(CNFSError::InvalidArgument));
+    assert_eq!(reader.write(b"x"), Err(CNFSError::InvalidArgument));
+    writer.write_all(b"ss")?;
+    drop(writer);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest)?;
+    assert_eq!(rest, b"ss");
+    assert_eq!(reader.read(&mut buffer)?, 0);
+
+    // Writing after the reader is closed breaks the pipe
+    let (reader, mut writer) = pipe()?;
+    drop(reader);
+    assert_eq!(writer.write(b"x"), Err(CNFSError::BrokenPipe));
+    drop(writer);
+
+    // Named pipes are found by path and never go through the page cache
+    create_directory(&Path::new("/run"))?;
+    mkfifo(&Path::new("/run/stage"))?;
+    assert_eq!(mkfifo(&Path::new("/run/stage")), Err(CNFSError::AlreadyExisted));
+    assert_eq!(create_directory(&Path::new("/run/stage")), Err(CNFSError::AlreadyExisted));
+    create_directory(&Path::new("/run/dir"))?;
+    assert_eq!(mkfifo(&Path::new("/run/dir")), Err(CNFSError::AlreadyExisted));
+    assert_eq!(metadata(&Path::new("/run/stage"))?.inode_type, InodeType::Fifo);
+    let names: Vec<_> = read_dir(&Path::new("/run"))?.into_iter().map(|e| (e.name, e.inode_type)).collect();
+    assert_eq!(names, [("dir".to_string(), InodeType::Dir), ("stage".to_string(), InodeType::Fifo)]);
+
+    let mut producer = open(&Path::new("/run/stage"), FileMode::write)?;
+    producer.write_all(b"step 1")?;
+    let mut consumer = open(&Path::new("/run/stage"), FileMode::read)?;
+    assert_eq!(metadata(&Path::new("/run/stage"))?.size, 6);
+    assert_eq!(consumer.read(&mut buffer)?, 6);
+    assert_eq!(&buffer[..6], b"step 1");
+    assert_eq!(consumer.read(&mut buffer), Err(CNFSError::WouldBlock));
+    producer.write_all(b"step 2")?;
+    drop(producer);
+    let mut rest = Vec::new();
+    consumer.read_to_end(&mut rest)?;
+    assert_eq!(rest, b"step 2");
+    drop(consumer);
+
+    // The pipe starts over once both ends are closed
+    let mut consumer = open(&Path::new("/run/stage"), FileMode::read)?;
+    assert_eq!(consumer.read(&mut buffer), Err(CNFSError::WouldBlock));
+    drop(consumer);
+
+    // Bind mounts see the same pipe
+    create_directory(&Path::new("/bind"))?;
+    bind_mount(&Path::new("/run"), &Path::new("/bind"), MountOptions::new())?;
+    let mut producer = open(&Path::new("/bind/stage"), FileMode::write)?;
+    producer.write_all(b"bound")?;
+    let mut consumer = open(&Path::new("/run/stage"), FileMode::read)?;
+    assert_eq!(consumer.read(&mut buffer)?, 5);
+    assert_eq!(&buffer[..5], b"bound");
+    mkfifo(&Path::new("/bind/other"))?;
+    assert_eq!(metadata(&Path::new("/run/other"))?.inode_type, InodeType::Fifo);
+    drop(producer);
+    drop(consumer);
+    umount(Path::new("/bind"))?;
+
+    // Removed pipes disappear, but opened files keep working
+    let mut consumer = open(&Path::new("/run/stage"), FileMode::read)?;
+    let mut producer = open(&Path::new("/run/stage"), FileMode::write)?;
+    remove(&Path::new("/run/stage"))?;
+    assert!(!exists(&Path::new("/run/stage"))?);
+    producer.write_all(b"late")?;
+    assert_eq!(consumer.read(&mut buffer)?, 4);
+    drop(producer);
+    drop(consumer);
+    remove(&Path::new("/run/other"))?;
+
+    // Directories holding named pipes are not empty, and a failed removal leaves them around
+    mkfifo(&Path::new("/run/dir/fifo"))?;
+    assert_eq!(remove(&Path::new("/run/dir")), Err(CNFSError::DirectoryNotEmpty));
+    assert_eq!(remove(&Path::new("/run")), Err(CNFSError::DirectoryNotEmpty));
+    assert!(exists(&Path::new("/run/dir/fifo"))?);
+    remove(&Path::new("/run/dir/fifo"))?;
+    remove(&Path::new("/run/dir"))?;
+    assert!(!exists(&Path::new("/run/dir/fifo"))?);
+
+    umount(Path::new("/"))?;
+    init(VfsConfig::default())?;
+    Ok(())
+}
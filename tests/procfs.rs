@@ -0,0 +1,75 @@
+use cnfs::{create_directory, metadata, mount, open, proc_mounts, read_dir, read_to_end, umount, write_all, CNFSError,
+           CNFSResult, FileMode, MountOptions, Path, ProcFs, RamFs};
+use std::sync::Arc;
+
+fn cat(path: &str) -> CNFSResult<String>
+{
+    Ok(String::from_utf8(read_to_end(&Path::new(path))?).unwrap())
+}
+
+/// Returns a value of `/proc/dcache`
+fn dcache(name: &str) -> CNFSResult<u64>
+{
+    let stats = cat("/proc/dcache")?;
+    let line = stats.lines().find(|l| l.starts_with(name)).unwrap();
+    Ok(line[name.len() + 1..].parse().unwrap())
+}
+
+#[test]
+fn procfs_test() -> CNFSResult
+{
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/proc"))?;
+    create_directory(&Path::new("/my data"))?;
+    mount(Arc::new(ProcFs::new()), Path::new("/proc"), MountOptions::new())?;
+    let names: Vec<_> = read_dir(&Path::new("/proc"))?.into_iter().map(|e| e.name).collect();
+    assert_eq!(names, ["dcache", "mounts", "open_files", "pagecache"]);
+
+    // Generated on every read
+    assert_eq!(cat("/proc/mounts")?, proc_mounts());
+    mount(Arc::new(RamFs::new()), Path::new("/my data"), MountOptions::new().label("tmp"))?;
+    assert_eq!(cat("/proc/mounts")?, proc_mounts());
+    assert!(cat("/proc/mounts")?.contains("ramfs /my\\040data ramfs rw,label=tmp 0 0\n"));
+    assert_eq!(metadata(&Path::new("/proc/mounts"))?.size, proc_mounts().len() as u64);
+
+    // Opened files and their offsets
+    assert_eq!(cat("/proc/open_files")?, "/proc/open_files 0 r\n");
+    write_all(&Path::new("/my data/log"), b"cnss{log}")?;
+    let mut writer = open(&Path::new("/my data/log"), FileMode::read | FileMode::write)?;
+    writer.seek(9)?;
+    writer.write_all(b"cnss")?;
+    writer.sync()?;
+    let mut reader = open(&Path::new("/my data/log"), FileMode::read)?;
+    reader.seek(5)?;
+    assert_eq!(cat("/proc/open_files")?, "/my\\040data/log 13 rw\n/my\\040data/log 5 r\n/proc/open_files 0 r\n");
+    drop(reader);
+    assert_eq!(cat("/proc/open_files")?, "/my\\040data/log 13 rw\n/proc/open_files 0 r\n");
+
+    // Cached pages, with the dirty ones not written back yet
+    assert_eq!(cat("/proc/pagecache")?, "/my\\040data/log 1 1 13\n");
+    writer.write_at(2048, b"cnss")?;
+    assert_eq!(cat("/proc/pagecache")?, "/my\\040data/log 2 2 17\n");
+    drop(writer);
+    assert_eq!(cat("/proc/pagecache")?, "/my\\040data/log 2 0 17\n");
+
+    // Lookups hitting and missing the dentry cache, where opening /proc/dcache is a hit itself
+    let (hits, misses) = (dcache("hits")?, dcache("misses")?);
+    metadata(&Path::new("/my data/log"))?;
+    assert_eq!((dcache("hits")?, dcache("misses")?), (hits + 3, misses));
+    assert_eq!(metadata(&Path::new("/my data/none")), Err(CNFSError::PathNotFound));
+    assert_eq!((dcache("hits")?, dcache("misses")?), (hits + 5, misses + 1));
+    assert!(dcache("size")? >= 5);
+    assert_eq!(dcache("limit")?, 4096);
+
+    // Read-only
+    let mut file = open(&Path::new("/proc/mounts"), FileMode::read | FileMode::write)?;
+    assert_eq!(file.write_at(0, b"cnss"), Err(CNFSError::ReadOnly));
+    drop(file);
+    assert_eq!(create_directory(&Path::new("/proc/new")), Err(CNFSError::ReadOnly));
+    assert_eq!(read_to_end(&Path::new("/proc/none")), Err(CNFSError::PathNotFound));
+
+    umount(Path::new("/my data"))?;
+    umount(Path::new("/proc"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use cnfs::{chmod, close, create_directory, exists, metadata, mount, open, remove, set_modified, umount,
+           umount_with_flags, write_all, CNFSError, CNFSResult, FileMode, MountOptions, Path, RamFs, UmountFlags};
+use std::sync::Arc;
+
+#[test]
+fn ramfs_test() -> CNFSResult
+{
+    let fs = Arc::new(RamFs::new());
+    mount(fs.clone(), Path::new("/"), MountOptions::new())?;
+    let test_dir = Path::new("/test_directory");
+    let test_file = Path::new("/test_directory/test_file");
+
+    // Directory Test
+    assert!(!exists(&test_dir)?);
+    create_directory(&test_dir)?;
+    assert!(exists(&test_dir)?);
+    assert_eq!(create_directory(&test_dir), Err(CNFSError::AlreadyExisted));
+    create_directory(&test_dir.join("sub"))?;
+    assert_eq!(remove(&test_dir), Err(CNFSError::DirectoryNotEmpty));
+    remove(&test_dir.join("sub"))?;
+
+    // File Test
+    let mut file = open(&test_file, FileMode::write)?;
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+    let mut dest = vec![0_u8; data.len()];
+    for _ in 0..10000
+    {
+        file.write_all(data.as_bytes())?;
+    }
+    file.sync()?;
+    file.seek(0)?;
+    for _ in 0..10000
+    {
+        dest.fill(0);
+        assert_eq!(file.read(dest.as_mut_slice())?, dest.len());
+        assert_eq!(dest, data.as_bytes());
+    }
+    close(file);
+    assert_eq!(fs.used_bytes(), data.len() * 10000);
+    assert_eq!(fs.used_inodes(), 3);
+    assert_eq!(create_directory(&test_file.join("dir")), Err(CNFSError::NotADirectory));
+
+    // Timestamps
+    let meta = metadata(&test_file)?;
+    assert!(meta.accessed.is_some() && meta.created.is_some() && meta.changed.is_some());
+    set_modified(&test_file, 1700000000)?;
+    assert_eq!(metadata(&test_file)?.modified, Some(1700000000));
+    chmod(&test_file, 0o600)?;
+    assert_eq!(metadata(&test_file)?.modified, Some(1700000000));
+    assert!(metadata(&test_file)?.changed >= meta.changed);
+
+    remove(&test_file)?;
+    assert!(!exists(&test_file)?);
+    remove(&test_dir)?;
+    assert_eq!(fs.used_bytes(), 0);
+    assert_eq!(fs.used_inodes(), 1);
+    umount(Path::new("/"))?;
+
+    // Limits
+    let limited = Arc::new(RamFs::with_limits(Some(8), Some(3)));
+    mount(limited.clone(), Path::new("/"), MountOptions::new().synchronous(true))?;
+    write_all(&Path::new("/a"), b"cnss")?;
+    let mut file = open(&Path::new("/b"), FileMode::write)?;
+    assert_eq!(file.write_all(b"cnss{full}"), Err(CNFSError::NoSpace));
+    drop(file);
+    assert_eq!(limited.used_bytes(), 8);
+    assert_eq!(create_directory(&Path::new("/c")), Err(CNFSError::NoSpace));
+    remove(&Path::new("/a"))?;
+    create_directory(&Path::new("/c"))?;
+    assert_eq!(limited.used_bytes(), 4);
+    // The rest of /b can't be written back
+    assert_eq!(umount(Path::new("/")), Err(CNFSError::NoSpace));
+    umount_with_flags(Path::new("/"), UmountFlags::FORCE)?;
+
+    // A write back that failed when the file was closed is reported later
+    let limited = Arc::new(RamFs::with_limits(Some(8), None));
+    mount(limited.clone(), Path::new("/"), MountOptions::new())?;
+    write_all(&Path::new("/a"), b"cnss{8b}")?;
+    write_all(&Path::new("/b"), b"cnss")?;
+    assert_eq!(limited.used_bytes(), 8);
+    remove(&Path::new("/a"))?;
+    assert_eq!(umount(Path::new("/")), Err(CNFSError::NoSpace));
+    assert_eq!(limited.used_bytes(), 4);
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use cnfs::{exists, filesystems, mount_by_type, mounts, probe_filesystem, read_to_end,
+           register_filesystem, umount, unregister_filesystem, write_all, BlockDeviceRef, CNFSError, CNFSResult,
+           FileBlockDevice, FileSystem, FileSystemFactory, HostFs, MountOptions, MountSource, Path};
+use std::env::current_dir;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// A host directory described by an image file holding `HOSTFS <directory>`
+struct DescribedHostFs;
+
+impl FileSystemFactory for DescribedHostFs
+{
+    fn create(&self, source: &MountSource) -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::File(path) => {
+                let image = String::from_utf8(read_to_end(path)?).unwrap();
+                let dir = image.strip_prefix("HOSTFS ").ok_or(CNFSError::InvalidArgument)?;
+                Ok(Arc::new(HostFs::new(dir)?))
+            }
+            _ => Err(CNFSError::InvalidArgument),
+        }
+    }
+
+    fn probe(&self, superblock: &[u8]) -> bool {
+        superblock.starts_with(b"HOSTFS ")
+    }
+}
+
+#[test]
+fn registry_test() -> CNFSResult
+{
+    let base = current_dir().unwrap().join("registry_test");
+    std::fs::create_dir_all(base.join("mnt")).unwrap();
+    std::fs::create_dir_all(base.join("described")).unwrap();
+    std::fs::write(base.join("described/file"), "cnss").unwrap();
+
+    register_filesystem("hostfs", Arc::new(|source: &MountSource| -> CNFSResult<Arc<dyn FileSystem>> {
+        match source {
+            MountSource::Options(dir) => Ok(Arc::new(HostFs::new(dir)?)),
+            _ => Err(CNFSError::InvalidArgument),
+        }
+    }))?;
+    register_filesystem("described", Arc::new(DescribedHostFs))?;
+    assert_eq!(register_filesystem("hostfs", Arc::new(DescribedHostFs)), Err(CNFSError::AlreadyExisted));
+    assert_eq!(register_filesystem("ext2", Arc::new(DescribedHostFs)), Err(CNFSError::AlreadyExisted));
+    let builtin: &[&str] = if cfg!(feature = "fatfs") {
+        &["ext2", "vfat", "tar", "zip"]
+    } else {
+        &["ext2", "tar", "zip"]
+    };
+    assert_eq!(filesystems(), [builtin, &["hostfs", "described"]].concat());
+
+    mount_by_type("hostfs", MountSource::Options(base.to_str().unwrap().into()),
+                  Path::new("/"), MountOptions::new())?;
+    let image = Path::new("/image");
+    write_all(&image, format!("HOSTFS {}", base.join("described").to_str().unwrap()).as_bytes())?;
+
+    // Auto-detect
+    assert_eq!(probe_filesystem(&MountSource::File(image.clone()))?, "described");
+    mount_by_type("auto", MountSource::File(image.clone()), Path::new("/mnt"), MountOptions::new())?;
+    assert_eq!(read_to_end(&Path::new("/mnt/file"))?, b"cnss");
+    assert_eq!(mounts().count(), 2);
+    umount(Path::new("/mnt"))?;
+
+    // Built-in types
+    let bundle = std::fs::read("tests/resources/bundle.tar").unwrap();
+    let tar: BlockDeviceRef = Arc::new(FileBlockDevice::new(Cursor::new(bundle), 512)?);
+    assert_eq!(probe_filesystem(&MountSource::Device(tar.clone()))?, "tar");
+    mount_by_type("auto", MountSource::Device(tar), Path::new("/mnt"), MountOptions::new().read_only(true))?;
+    assert_eq!(read_to_end(&Path::new("/mnt/version"))?, b"cnss");
+    umount(Path::new("/mnt"))?;
+
+    write_all(&image, b"garbage")?;
+    assert_eq!(probe_filesystem(&MountSource::File(image.clone())), Err(CNFSError::UnknownFileSystem));
+    assert_eq!(mount_by_type("auto", MountSource::File(image.clone()), Path::new("/mnt"),
+                             MountOptions::new()), Err(CNFSError::UnknownFileSystem));
+    assert_eq!(mount_by_type("ext4", MountSource::Options("".into()), Path::new("/mnt"),
+                             MountOptions::new()), Err(CNFSError::UnknownFileSystem));
+    assert!(!exists(&Path::new("/mnt/file"))?);
+
+    unregister_filesystem("described")?;
+    assert_eq!(unregister_filesystem("described"), Err(CNFSError::UnknownFileSystem));
+    umount(Path::new("/"))?;
+    std::fs::remove_dir_all(&base).unwrap();
+    Ok(())
+}
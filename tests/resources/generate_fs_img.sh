@@ -2,3 +2,39 @@ dd if=/dev/zero of=fat_1.img count=1024
 dd if=/dev/zero of=fat_2.img count=1024
 mkfs.vfat fat_1.img
 mkfs.vfat fat_2.img
+mkdir -p ext2_root/dir
+printf cnss > ext2_root/hello.txt
+printf 'cnss{nested}' > ext2_root/dir/nested.txt
+yes 'cnss{th1s_i5_my_vfs_t3st}' | head -c 307200 > ext2_root/big.bin
+ln -s hello.txt ext2_root/link
+dd if=/dev/zero of=ext2_1.img bs=1024 count=2048
+mkfs.ext2 -b 1024 -g 512 -N 256 -d ext2_root ext2_1.img
+rm -r ext2_root
+dd if=/dev/zero of=disk_mbr.img count=10240
+printf 'start=2048,size=1024,type=c\nstart=4096,size=4096,type=83\nstart=8192,size=2048,type=5\nstart=8193,size=1024,type=c\n' | sfdisk disk_mbr.img
+dd if=fat_1.img of=disk_mbr.img seek=2048 conv=notrunc
+dd if=ext2_1.img of=disk_mbr.img seek=4096 conv=notrunc
+dd if=fat_2.img of=disk_mbr.img seek=8193 conv=notrunc
+dd if=/dev/zero of=disk_gpt.img count=10240
+printf 'label: gpt\nstart=2048,size=1024,type=EBD0A0A2-B9E5-4433-87C0-68B6B72699C7,name=fat\nstart=4096,size=4096,type=0FC63DAF-8483-4772-8E79-3D69D8477DE4,name=ext2\n' | sfdisk disk_gpt.img
+dd if=fat_1.img of=disk_gpt.img seek=2048 conv=notrunc
+dd if=ext2_1.img of=disk_gpt.img seek=4096 conv=notrunc
+mkdir -p bundle/firmware/bin bundle/empty bundle/firmware/a_very_long_directory_name_that_does_not_fit_in_the_ustar_name_field
+printf cnss > bundle/version
+yes 'cnss{th1s_i5_my_vfs_t3st}' | head -c 100000 > bundle/firmware/image.bin
+printf 'cnss{long}' > bundle/firmware/a_very_long_directory_name_that_does_not_fit_in_the_ustar_name_field/and_a_long_file_name_as_well.txt
+ln bundle/version bundle/firmware/bin/version.hard
+ln -s ../../version bundle/firmware/bin/version.link
+tar --format=pax --pax-option=delete=atime,delete=ctime --owner=0 --group=0 --mtime=@1700000000 --sort=name -cf bundle.tar -C bundle .
+rm -r bundle
+mkdir -p plugin/lib plugin/empty
+printf 'cnss{plugin}' > plugin/manifest.txt
+seq 1 60000 > plugin/lib/numbers.txt
+yes 'cnss{th1s_i5_my_vfs_t3st}' | head -c 3145728 > plugin/lib/big.bin
+printf 'cnss{stored}' > plugin/stored.txt
+printf "cnss{deflated} %.0s" $(seq 1 16) > plugin/readme.txt
+find plugin -exec touch -h -d @1700000000 {} +
+rm -f plugin.zip
+zip -q -r -9 plugin.zip plugin -x plugin/stored.txt
+zip -q -0 plugin.zip plugin/stored.txt
+rm -r plugin
@@ -0,0 +1,88 @@
+use cnfs::{create_directory, metadata, mount, open, read_dir, read_to_end, umount, write_all, BlockDeviceRef,
+           CNFSError, CNFSResult, DirEntry, FileMode, FileSystem, InodeType, LoopDevice, MountOptions, Path,
+           RamFs, TarFs};
+use std::sync::Arc;
+
+const BUNDLE: &[u8] = include_bytes!("resources/bundle.tar");
+const LONG_DIR: &str = "/bundle/firmware/a_very_long_directory_name_that_does_not_fit_in_the_ustar_name_field";
+
+/// Content of `firmware/image.bin` in the archive
+fn pattern(len: usize) -> Vec<u8>
+{
+    b"cnss{th1s_i5_my_vfs_t3st}\n".iter().cycle().take(len).copied().collect()
+}
+
+fn check_bundle() -> CNFSResult
+{
+    assert_eq!(read_to_end(&Path::new("/bundle/firmware/image.bin"))?, pattern(100000));
+    assert_eq!(read_to_end(&Path::new(&format!("{LONG_DIR}/and_a_long_file_name_as_well.txt")))?, b"cnss{long}");
+    assert_eq!(read_to_end(&Path::new("/bundle/version"))?, b"cnss");
+    assert_eq!(read_to_end(&Path::new("/bundle/firmware/bin/version.hard"))?, b"cnss");
+    assert_eq!(read_to_end(&Path::new("/bundle/firmware/bin/version.link"))?, b"../../version");
+    assert_eq!(read_dir(&Path::new("/bundle"))?, [
+        DirEntry { name: "empty".into(), inode_type: InodeType::Dir },
+        DirEntry { name: "firmware".into(), inode_type: InodeType::Dir },
+        DirEntry { name: "version".into(), inode_type: InodeType::File },
+    ]);
+    assert!(read_dir(&Path::new("/bundle/empty"))?.is_empty());
+    let image = metadata(&Path::new("/bundle/firmware/image.bin"))?;
+    assert_eq!((image.inode_type, image.size, image.modified), (InodeType::File, 100000, Some(1700000000)));
+    assert_eq!(metadata(&Path::new(LONG_DIR))?.inode_type, InodeType::Dir);
+    assert_eq!(metadata(&Path::new("/bundle/firmware/bin/version.link"))?.inode_type, InodeType::Symlink);
+    Ok(())
+}
+
+#[test]
+fn tar_test() -> CNFSResult
+{
+    assert!(TarFs::probe(BUNDLE));
+    assert!(!TarFs::probe(&std::fs::read("tests/resources/ext2_1.img").unwrap()[..4096]));
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/bundle"))?;
+
+    // Mounted from bytes built into the program
+    mount(Arc::new(TarFs::new(BUNDLE)?), Path::new("/bundle"), MountOptions::new().read_only(true))?;
+    check_bundle()?;
+    // Reads at any offset come from the archive
+    let mut file = open(&Path::new("/bundle/firmware/image.bin"), FileMode::read)?;
+    let mut buffer = [0_u8; 26];
+    assert_eq!(file.read_at(26 * 3000 + 5, &mut buffer)?, 26);
+    assert_eq!(&buffer, b"th1s_i5_my_vfs_t3st}\ncnss{");
+    assert_eq!(file.read_at(99990, &mut buffer)?, 10);
+    drop(file);
+    assert_eq!(write_all(&Path::new("/bundle/version"), b"cnss"), Err(CNFSError::ReadOnly));
+    assert_eq!(create_directory(&Path::new("/bundle/new")), Err(CNFSError::ReadOnly));
+    umount(Path::new("/bundle"))?;
+
+    // Even writable mounts can't change the archive
+    mount(Arc::new(TarFs::new(BUNDLE.to_vec())?), Path::new("/bundle"), MountOptions::new())?;
+    assert_eq!(create_directory(&Path::new("/bundle/new")), Err(CNFSError::ReadOnly));
+    assert_eq!(cnfs::remove(&Path::new("/bundle/version")), Err(CNFSError::ReadOnly));
+    umount(Path::new("/bundle"))?;
+
+    // Mounted from a tarball inside the VFS without extracting it
+    write_all(&Path::new("/bundle.tar"), BUNDLE)?;
+    let device = Arc::new(LoopDevice::new(open(&Path::new("/bundle.tar"), FileMode::read)?, 512)?);
+    mount(Arc::new(TarFs::new(device as BlockDeviceRef)?), Path::new("/bundle"), MountOptions::new().read_only(true))?;
+    check_bundle()?;
+    umount(Path::new("/bundle"))?;
+
+    // Broken archives
+    assert_eq!(TarFs::new(vec![0x55_u8; 4096]).err(), Some(CNFSError::UnknownFileSystem));
+    assert!(TarFs::new(Vec::new())?.root_inode().read_dir()?.is_empty());
+    let mut corrupted = BUNDLE.to_vec();
+    corrupted[512 * 3] ^= 1;
+    assert!(matches!(TarFs::new(corrupted).err(), Some(CNFSError::FSInternal(_))));
+    assert!(matches!(TarFs::new(BUNDLE[..60 * 1024].to_vec()).err(), Some(CNFSError::FSInternal(_))));
+    // A base-256 size reaching past the largest offset
+    let mut huge = vec![0_u8; 1024];
+    huge[..4].copy_from_slice(b"huge");
+    huge[124] = 0x80;
+    huge[128..136].copy_from_slice(&(u64::MAX - 100).to_be_bytes());
+    huge[148..156].fill(b' ');
+    let sum: u32 = huge[..512].iter().map(|&b| b as u32).sum();
+    huge[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    assert!(matches!(TarFs::new(huge).err(), Some(CNFSError::FSInternal(_))));
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use cnfs::{exists, mount, open, umount, umount_with_flags, BlockDeviceRef, CNFSError, CNFSResult, Ext2FileSystem,
+           FileMode, HostFs, MemBlockDevice, MountOptions, Path, UmountFlags};
+use std::env::current_dir;
+use std::sync::Arc;
+
+#[test]
+fn umount_test() -> CNFSResult
+{
+    let root = current_dir().unwrap();
+    let real_mnt = root.join("umount_test_mnt");
+    let real_src = root.join("umount_test_src");
+    let real_file = real_src.join("test_file");
+    std::fs::create_dir_all(&real_mnt).unwrap();
+    std::fs::create_dir_all(&real_src).unwrap();
+
+    mount(Arc::new(HostFs::new(root)?), Path::new("/"), MountOptions::new())?;
+    let mnt = Path::new("/umount_test_mnt");
+    let file_path = Path::new("/umount_test_mnt/test_file");
+    let data = "cnss{th1s_i5_my_vfs_t3st}";
+
+    // Busy while files are opened, flushed on umount
+    mount(Arc::new(HostFs::new(real_src.clone())?), mnt.clone(), MountOptions::new())?;
+    assert_eq!(umount(Path::new("/")), Err(CNFSError::Busy));
+    let mut file = open(&file_path, FileMode::write)?;
+    file.write_all(data.as_bytes())?;
+    assert_eq!(umount(mnt.clone()), Err(CNFSError::Busy));
+    file.sync()?;
+    drop(file);
+    umount(mnt.clone())?;
+    assert_eq!(std::fs::read(&real_file).unwrap(), data.as_bytes());
+    assert!(!exists(&file_path)?);
+    assert_eq!(umount(mnt.clone()), Err(CNFSError::NoMountedFilesystem));
+
+    // Lazy
+    mount(Arc::new(HostFs::new(real_src.clone())?), mnt.clone(), MountOptions::new())?;
+    let mut file = open(&file_path, FileMode::write)?;
+    umount_with_flags(mnt.clone(), UmountFlags::LAZY)?;
+    assert!(!exists(&file_path)?);
+    file.write_all(b"CNSS")?;
+    drop(file);
+    assert_eq!(&std::fs::read(&real_file).unwrap()[..4], b"CNSS");
+
+    // Lazy unmounted filesystems are synchronized when their last file is closed
+    let image = std::fs::read("tests/resources/ext2_1.img").unwrap();
+    let device = Arc::new(MemBlockDevice::from_vec(512, image)?);
+    let fs = Arc::new(Ext2FileSystem::open(device.clone() as BlockDeviceRef)?);
+    mount(fs.clone(), mnt.clone(), MountOptions::new())?;
+    let mut file = open(&file_path, FileMode::write)?;
+    umount_with_flags(mnt.clone(), UmountFlags::LAZY)?;
+    file.write_all(b"cnss{lazy}")?;
+    let on_device = || device.to_vec().windows(10).any(|w| w == b"cnss{lazy}");
+    assert!(!on_device());
+    drop(file);
+    assert!(on_device());
+
+    // Force
+    mount(Arc::new(HostFs::new(real_src.clone())?), mnt.clone(), MountOptions::new())?;
+    let mut file = open(&file_path, FileMode::write)?;
+    umount_with_flags(mnt.clone(), UmountFlags::FORCE)?;
+    assert!(!exists(&file_path)?);
+    assert_eq!(file.write(b"cnss"), Err(CNFSError::NoMountedFilesystem));
+    drop(file);
+    assert_eq!(&std::fs::read(&real_file).unwrap()[..4], b"CNSS");
+
+    umount(Path::new("/"))?;
+    std::fs::remove_dir_all(&real_mnt).unwrap();
+    std::fs::remove_dir_all(&real_src).unwrap();
+    Ok(())
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "fatfs")]
+
+use cnfs::{bind_mount, create_directory, get_xattr, list_xattr, mount, remove, remove_xattr, set_xattr, umount,
+           with_credentials, write_all, BlockStorage, CNFSError, CNFSResult, Credentials, FatFileSystem,
+           MemBlockDevice, MountOptions, Path, RamFs, XATTR_SIZE_MAX};
+use std::sync::Arc;
+
+fn check_xattrs(dir: &str) -> CNFSResult
+{
+    let file = Path::new(&format!("{dir}/data.bin"));
+    write_all(&file, b"cnss")?;
+    assert!(list_xattr(&file)?.is_empty());
+    assert_eq!(get_xattr(&file, "user.sha256"), Err(CNFSError::NoAttribute));
+    set_xattr(&file, "user.sha256", b"d34db33f")?;
+    set_xattr(&file, "user.origin", b"crawler")?;
+    set_xattr(&file, "trusted.quarantine", b"")?;
+    set_xattr(&file, "user.origin", b"upload")?;
+    assert_eq!(get_xattr(&file, "user.sha256")?, b"d34db33f");
+    assert_eq!(get_xattr(&file, "user.origin")?, b"upload");
+    assert_eq!(list_xattr(&file)?, ["trusted.quarantine", "user.origin", "user.sha256"]);
+    remove_xattr(&file, "user.origin")?;
+    assert_eq!(remove_xattr(&file, "user.origin"), Err(CNFSError::NoAttribute));
+    assert_eq!(list_xattr(&file)?, ["trusted.quarantine", "user.sha256"]);
+
+    // Directories have them too
+    let dir = Path::new(dir);
+    set_xattr(&dir, "user.tag", b"dir")?;
+    assert_eq!(get_xattr(&dir, "user.tag")?, b"dir");
+    remove_xattr(&dir, "user.tag")?;
+
+    // A file made again at the same path starts without them
+    remove(&file)?;
+    write_all(&file, b"cnss")?;
+    assert!(list_xattr(&file)?.is_empty());
+    remove(&file)?;
+    Ok(())
+}
+
+#[test]
+fn xattr_test() -> CNFSResult
+{
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/fat"))?;
+    let image = std::fs::read("tests/resources/fat_1.img").unwrap();
+    let device = Arc::new(MemBlockDevice::from_vec(512, image)?);
+    mount(Arc::new(FatFileSystem::open(BlockStorage::new(device))?), Path::new("/fat"), MountOptions::new())?;
+
+    // Kept by the ramfs itself, and by the VFS for FAT
+    create_directory(&Path::new("/ram"))?;
+    check_xattrs("/ram")?;
+    create_directory(&Path::new("/fat/xattr"))?;
+    check_xattrs("/fat/xattr")?;
+
+    // Names need a known namespace, and values have a size limit
+    let file = Path::new("/fat/xattr/file");
+    write_all(&file, b"cnss")?;
+    for name in ["sha256", "user.", "system.posix_acl_access", "security.selinux"]
+    {
+        assert_eq!(set_xattr(&file, name, b"x"), Err(CNFSError::InvalidArgument));
+    }
+    assert_eq!(set_xattr(&file, &format!("user.{}", "x".repeat(251)), b"x"), Err(CNFSError::InvalidArgument));
+    set_xattr(&file, &format!("user.{}", "x".repeat(250)), b"x")?;
+    assert_eq!(set_xattr(&file, "user.big", &vec![0; XATTR_SIZE_MAX + 1]), Err(CNFSError::InvalidArgument));
+    set_xattr(&file, "user.big", &vec![0; XATTR_SIZE_MAX])?;
+    remove_xattr(&file, "user.big")?;
+
+    // Bind mounts see the attributes of the same inode
+    set_xattr(&file, "user.sha256", b"d34db33f")?;
+    set_xattr(&file, "trusted.quarantine", b"1")?;
+    create_directory(&Path::new("/view"))?;
+    bind_mount(&Path::new("/fat/xattr"), &Path::new("/view"), MountOptions::new())?;
+    assert_eq!(get_xattr(&Path::new("/view/file"), "user.sha256")?, b"d34db33f");
+    set_xattr(&Path::new("/view/file"), "user.origin", b"view")?;
+    assert_eq!(get_xattr(&file, "user.origin")?, b"view");
+    umount(Path::new("/view"))?;
+
+    // User attributes follow the permission bits, trusted ones are for the superuser only
+    let user = Credentials::new(1000, 1000);
+    with_credentials(user.clone(), || -> CNFSResult {
+        assert_eq!(get_xattr(&file, "user.sha256")?, b"d34db33f");
+        assert_eq!(list_xattr(&file)?.len(), 3);
+        assert_eq!(get_xattr(&file, "trusted.quarantine"), Err(CNFSError::PermissionDenied));
+        assert_eq!(set_xattr(&file, "user.sha256", b"x"), Err(CNFSError::PermissionDenied));
+        assert_eq!(remove_xattr(&file, "user.sha256"), Err(CNFSError::PermissionDenied));
+        Ok(())
+    })?;
+    assert_eq!(list_xattr(&file)?.len(), 4);
+
+    remove(&file)?;
+    remove(&Path::new("/fat/xattr"))?;
+    umount(Path::new("/fat"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use cnfs::{create_directory, metadata, mount, open, read_dir, read_to_end, umount, CNFSError, CNFSResult, DirEntry,
+           FileMode, FileSystem, InodeType, MountOptions, Path, RamFs, VfsConfig, ZipFs};
+use std::cmp::min;
+use std::sync::Arc;
+
+const PLUGIN: &[u8] = include_bytes!("resources/plugin.zip");
+
+/// Content of `plugin/lib/big.bin` in the archive
+fn pattern(len: usize) -> Vec<u8>
+{
+    b"cnss{th1s_i5_my_vfs_t3st}\n".iter().cycle().take(len).copied().collect()
+}
+
+/// Content of `plugin/lib/numbers.txt` in the archive
+fn numbers() -> Vec<u8>
+{
+    (1..=60000).map(|i| format!("{i}\n")).collect::<String>().into_bytes()
+}
+
+#[test]
+fn zip_test() -> CNFSResult
+{
+    assert!(ZipFs::probe(PLUGIN));
+    assert!(!ZipFs::probe(include_bytes!("resources/bundle.tar")));
+    mount(Arc::new(RamFs::new()), Path::new("/"), MountOptions::new())?;
+    create_directory(&Path::new("/plugins"))?;
+
+    mount(Arc::new(ZipFs::new(PLUGIN)?), Path::new("/plugins"), MountOptions::new().read_only(true))?;
+    assert_eq!(read_dir(&Path::new("/plugins/plugin"))?, [
+        DirEntry { name: "empty".into(), inode_type: InodeType::Dir },
+        DirEntry { name: "lib".into(), inode_type: InodeType::Dir },
+        DirEntry { name: "manifest.txt".into(), inode_type: InodeType::File },
+        DirEntry { name: "readme.txt".into(), inode_type: InodeType::File },
+        DirEntry { name: "stored.txt".into(), inode_type: InodeType::File },
+    ]);
+    assert!(read_dir(&Path::new("/plugins/plugin/empty"))?.is_empty());
+    assert_eq!(read_to_end(&Path::new("/plugins/plugin/manifest.txt"))?, b"cnss{plugin}");
+    assert_eq!(read_to_end(&Path::new("/plugins/plugin/stored.txt"))?, b"cnss{stored}");
+    assert_eq!(read_to_end(&Path::new("/plugins/plugin/readme.txt"))?, b"cnss{deflated} ".repeat(16));
+    assert_eq!(read_to_end(&Path::new("/plugins/plugin/lib/numbers.txt"))?, numbers());
+    let big = metadata(&Path::new("/plugins/plugin/lib/big.bin"))?;
+    assert_eq!((big.inode_type, big.size, big.modified), (InodeType::File, 3 << 20, Some(1700000000)));
+    assert_eq!(read_to_end(&Path::new("/plugins/plugin/lib/big.bin"))?, pattern(3 << 20));
+    assert_eq!(create_directory(&Path::new("/plugins/new")), Err(CNFSError::ReadOnly));
+    umount(Path::new("/plugins"))?;
+
+    // Random reads of a deflated entry, with a page cache too small to help
+    let options = MountOptions::new().read_only(true).config(VfsConfig::default().page_entry_size(1));
+    mount(Arc::new(ZipFs::new(PLUGIN.to_vec())?), Path::new("/plugins"), options)?;
+    let expected = pattern(3 << 20);
+    let mut file = open(&Path::new("/plugins/plugin/lib/big.bin"), FileMode::read)?;
+    let mut buffer = [0_u8; 100];
+    for offset in [3_000_000, 10, 2_500_000, 1_048_570, 3_145_700, 5, 2_097_100]
+    {
+        let len = file.read_at(offset, &mut buffer)?;
+        assert_eq!(&buffer[..len], &expected[offset as usize..min(offset as usize + 100, expected.len())]);
+    }
+    drop(file);
+    umount(Path::new("/plugins"))?;
+
+    // Broken archives
+    assert_eq!(ZipFs::new(vec![0_u8; 4096]).err(), Some(CNFSError::UnknownFileSystem));
+    assert!(ZipFs::new(PLUGIN[..PLUGIN.len() - 100].to_vec()).is_err());
+    let fs = ZipFs::new(b"PK\x05\x06\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec())?;
+    assert!(fs.root_inode().read_dir()?.is_empty());
+    // A zip64 compressed size reaching past the largest offset
+    let mut huge = Vec::new();
+    huge.extend_from_slice(b"PK\x03\x04\x14\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\xff\xff\xff\x04\0\0\0\x01\0\0\0a");
+    huge.extend_from_slice(b"cnss");
+    let directory = huge.len() as u32;
+    huge.extend_from_slice(b"PK\x01\x02\x14\0\x14\0\0\0\0\0\0\0\0\0\0\0\0\0\xff\xff\xff\xff\x04\0\0\0");
+    huge.extend_from_slice(b"\x01\0\x0c\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0a\x01\0\x08\0");
+    huge.extend_from_slice(&(u64::MAX - 10).to_le_bytes());
+    let directory_size = huge.len() as u32 - directory;
+    huge.extend_from_slice(b"PK\x05\x06\0\0\0\0\x01\0\x01\0");
+    huge.extend_from_slice(&directory_size.to_le_bytes());
+    huge.extend_from_slice(&directory.to_le_bytes());
+    huge.extend_from_slice(b"\0\0");
+    let file = ZipFs::new(huge)?.root_inode().lookup("a")?;
+    assert!(matches!(file.read(0, &mut buffer), Err(CNFSError::FSInternal(_))));
+    // A flipped bit in the compressed data fails the CRC32 check or the decompression
+    let mut corrupted = PLUGIN.to_vec();
+    let position = corrupted.windows(11).position(|w| w == b"numbers.txt").unwrap() + 11 + 28 + 1000;
+    corrupted[position] ^= 0x10;
+    mount(Arc::new(ZipFs::new(corrupted)?), Path::new("/plugins"), MountOptions::new().read_only(true))?;
+    let mut file = open(&Path::new("/plugins/plugin/lib/numbers.txt"), FileMode::read)?;
+    assert!(matches!(file.read_at(numbers().len() as u64 - 10, &mut buffer), Err(CNFSError::FSInternal(_))));
+    drop(file);
+    umount(Path::new("/plugins"))?;
+    umount(Path::new("/"))?;
+    Ok(())
+}